@@ -5,37 +5,1687 @@
 
 extern crate brainf;
 
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
 use std::mem;
-use brainf::repl::Interpreter;
-use brainf::repl::Parser;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use brainf::repl::archive::Archive;
+use brainf::repl::bisect::{self, Never};
+use brainf::repl::channel::Pair;
+use brainf::repl::catalog;
+use brainf::repl::check::{self, Level};
+use brainf::repl::cli::{self, Args, ErrorFormat, RunOptions};
+use brainf::repl::condition::Condition;
+use brainf::repl::config::Config;
+#[cfg(feature = "corpus")]
+use brainf::repl::corpus;
+use brainf::repl::disasm;
+use brainf::repl::error;
+use brainf::repl::fetch;
+use brainf::repl::format::{self, BrainFormatter};
+use brainf::repl::fuzz::{self, FuzzOptions};
+use brainf::repl::generate::{self, GenerateOptions};
+use brainf::repl::golf;
+use brainf::repl::http;
+use brainf::repl::json::Json;
+use brainf::repl::io::{Buffer, Stdio, Terminal};
+use brainf::repl::random::Rng;
+use brainf::repl::reduce;
+use brainf::repl::replay::{self, Replay};
+use brainf::repl::script;
+use brainf::repl::serve::{self, ServeOptions};
+use brainf::repl::shell::{Console, Repl, Turn};
+use brainf::repl::snapshot::{self, Recovery, Snapshot};
+use brainf::repl::spec::{self, Outcome};
+use brainf::repl::xcheck;
+use brainf::repl::{self, Interpreter};
+
+// One `brainf <name>`, `brainf help` lists them. Flags are parsed before
+// run gets called, so it only sees ones in switches and values
+struct Subcommand {
+    name: &'static str,
+    usage: &'static str,
+    summary: &'static str,
+    switches: &'static [&'static str],
+    values: &'static [&'static str],
+    run: fn(&Args) -> i32,
+}
+
+const REPL_SWITCHES: &[&str] = &[
+    "--resume",
+    "--recover",
+    "--autosave",
+    "--quiet",
+    "-q",
+    "--no-emoji",
+    "--no-color",
+    "--strict-comments",
+    "--no-config",
+];
+const REPL_VALUES: &[&str] = &[
+    "--session",
+    "--log",
+    "--script",
+    "--preload",
+    "--dialect",
+    "--input",
+    "--input-file",
+    "--eof",
+    "--alias",
+    "--watchdog",
+    "--history",
+    "--capacity-cap",
+    "--output-file",
+    "--tee",
+    "--transcript",
+];
+
+const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "repl",
+        usage: "repl [--resume] [--recover] [--autosave] [--quiet] [--no-emoji] [--no-color] [--session <file>] [--log <file>] [--script <file>] [--preload <file>] [--dialect <name>] [--input <text> | --input-file <file>] [--eof <unchanged | 0 | 255>] [--alias <from>=<to> | common]... [--strict-comments] [--watchdog <steps>] [--history <count>] [--capacity-cap <cells>] [--output-file <file>] [--tee <file>]... [--transcript <file>] [--no-config]",
+        summary: "start the REPL, which is also what plain `brainf` does",
+        switches: REPL_SWITCHES,
+        values: REPL_VALUES,
+        run: repl,
+    },
+    Subcommand {
+        name: "run",
+        usage: "run <program.bf... | url | - | -e <code>> [--watch] [-i | --interactive] [--no-emoji] [--no-color] [--max-download <bytes>] [--max-steps <count>] [--capacity-cap <cells>] [--input <text> | --input-file <file>] [--eof <unchanged | 0 | 255>] [--dialect <name>] [--alias <from>=<to> | common]... [--strict] [--strict-comments] [--bang-input] [--fix-brackets] [--watchdog <steps>] [--history <count>] [--loop-quota <offset>=<count>]... [--output-file <file>] [--tee <file>]... [--transcript <file>] [--exit-cell <current | cell>] [--time] [--show-tape] [--tape-range <start..end>] [--tape-radix <10 | 16 | 8 | 2>] [--error-format <text | json>] [--no-config]",
+        summary: "run a program with stdin and stdout as its input and output",
+        switches: cli::RUN_SWITCHES,
+        values: cli::RUN_VALUES,
+        run,
+    },
+    Subcommand {
+        name: "pipe",
+        usage: "pipe <first.bf> <next.bf>... [--input <text> | --input-file <file>] [--eof <unchanged | 0 | 255>] [--max-steps <count>] [--watchdog <steps>] [--capacity-cap <cells>] [--dialect <name>] [--alias <from>=<to> | common]... [--strict-comments] [--no-config]",
+        summary: "run programs with each one's output as the next one's input, like a shell pipe",
+        switches: &["--strict-comments", "--no-config"],
+        values: &[
+            "--input",
+            "--input-file",
+            "--eof",
+            "--max-steps",
+            "--watchdog",
+            "--capacity-cap",
+            "--dialect",
+            "--alias",
+        ],
+        run: pipe,
+    },
+    Subcommand {
+        name: "bisect",
+        usage: "bisect <program.bf> --when <condition> [--input <text> | --input-file <file>] [--eof <unchanged | 0 | 255>] [--max-steps <count>] [--dialect <name>] [--alias <from>=<to> | common]... [--strict-comments] [--no-config]",
+        summary: "find the instruction where a condition like :break takes first comes true",
+        switches: &["--strict-comments", "--no-config"],
+        values: &[
+            "--when",
+            "--input",
+            "--input-file",
+            "--eof",
+            "--max-steps",
+            "--dialect",
+            "--alias",
+        ],
+        run: bisect,
+    },
+    Subcommand {
+        name: "pair",
+        usage: "pair <first.bf> <second.bf> [--input <text> | --input-file <file>] [--eof <unchanged | 0 | 255>] [--max-steps <count>] [--watchdog <steps>] [--capacity-cap <cells>] [--alias <from>=<to> | common]... [--strict-comments] [--no-config]",
+        summary: "run two programs taking turns, trading bytes with ^ to send the current cell and ~ to receive one",
+        switches: &["--strict-comments", "--no-config"],
+        values: &[
+            "--input",
+            "--input-file",
+            "--eof",
+            "--max-steps",
+            "--watchdog",
+            "--capacity-cap",
+            "--alias",
+        ],
+        run: pair,
+    },
+    Subcommand {
+        name: "debug",
+        usage: "debug <program.bf> [repl flags]",
+        summary: "start the REPL paused at a program's first instruction",
+        switches: REPL_SWITCHES,
+        values: REPL_VALUES,
+        run: debug,
+    },
+    Subcommand {
+        name: "serve",
+        usage: "serve [--listen <address>] [--max-steps <count>] [--watchdog <steps>] [--idle <seconds>] [--max-clients <count>]",
+        summary: "run a REPL for each connection to a TCP port",
+        switches: &[],
+        values: &["--listen", "--max-steps", "--watchdog", "--idle", "--max-clients"],
+        run: serve,
+    },
+    Subcommand {
+        name: "serve-http",
+        usage: "serve-http [--listen <address>] [--max-steps <count>] [--watchdog <steps>] [--idle <seconds>] [--max-clients <count>]",
+        summary: "run programs posted as JSON to /run over HTTP",
+        switches: &[],
+        values: &["--listen", "--max-steps", "--watchdog", "--idle", "--max-clients"],
+        run: serve_http,
+    },
+    Subcommand {
+        name: "check",
+        usage: "check [--strict] [--strict-comments] [--allow | --warn | --deny <lint | warnings>]... [--error-format <text | json>] [--no-config] <program.bf>",
+        summary: "find problems in a program without running it",
+        switches: &["--strict", "--strict-comments", "--no-config"],
+        values: &["--error-format", "--allow", "--warn", "--deny"],
+        run: check,
+    },
+    Subcommand {
+        name: "record",
+        usage: "record <program.bf> <run.replay> [--input <text> | --input-file <file>] [--max-steps <count>] [--dialect <name>] [--no-config]",
+        summary: "run a program and save everything needed to run it the same way again",
+        switches: &["--no-config"],
+        values: &["--input", "--input-file", "--max-steps", "--dialect"],
+        run: record,
+    },
+    Subcommand {
+        name: "replay",
+        usage: "replay <run.replay>",
+        summary: "run a recording again and compare the output",
+        switches: &[],
+        values: &[],
+        run: replay,
+    },
+    Subcommand {
+        name: "export",
+        usage: "export <program.bf> <stuck.archive> [--input <text> | --input-file <file>] [--eof <unchanged | 0 | 255>] [--max-steps <count>] [--watchdog <steps>] [--capacity-cap <cells>] [--dialect <name>] [--alias <from>=<to> | common]... [--strict-comments] [--no-config]",
+        summary: "run a program and save where it stopped, settings and all, to hand to someone else",
+        switches: &["--strict-comments", "--no-config"],
+        values: &[
+            "--input",
+            "--input-file",
+            "--eof",
+            "--max-steps",
+            "--watchdog",
+            "--capacity-cap",
+            "--dialect",
+            "--alias",
+        ],
+        run: export,
+    },
+    Subcommand {
+        name: "import",
+        usage: "import <stuck.archive> [--quiet] [--no-emoji] [--no-color]",
+        summary: "pick up an exported run in the REPL, paused where it stopped",
+        switches: &["--quiet", "-q", "--no-emoji", "--no-color"],
+        values: &[],
+        run: import,
+    },
+    Subcommand {
+        name: "xcheck",
+        usage: "xcheck <program.bf> --against \"<command %f>\" [--input <text>] [--reduce]",
+        summary: "compare the output with another interpreter's",
+        switches: &["--reduce"],
+        values: &["--against", "--input"],
+        run: xcheck,
+    },
+    Subcommand {
+        name: "test",
+        usage: "test <program.bf> <program.test>",
+        summary: "run every case in a spec file",
+        switches: &[],
+        values: &[],
+        run: test,
+    },
+    Subcommand {
+        name: "batch",
+        usage: "batch <corpus.zip | corpus.tar | corpus.tar.gz> [--max-steps <count>] [--eof <unchanged | 0 | 255>] [--dialect <name>] [--alias <from>=<to> | common]... [--strict-comments] [--no-config]",
+        summary: "judge every x.bf in an archive on x.in against x.expected",
+        switches: &["--strict-comments", "--no-config"],
+        values: &["--max-steps", "--eof", "--dialect", "--alias"],
+        run: batch,
+    },
+    Subcommand {
+        name: "fuzz-io",
+        usage: "fuzz-io <program.bf> [--runs <count>] [--seed <n>] [--max-len <bytes>] [--max-steps <count>] [--watchdog <steps>] [--dialect <name>] [--alias <from>=<to> | common]... [--strict-comments] [--reduce] [--no-config]",
+        summary: "run a program on lots of generated input to find what breaks it",
+        switches: &["--strict-comments", "--reduce", "--no-config"],
+        values: &["--runs", "--seed", "--max-len", "--max-steps", "--watchdog", "--dialect", "--alias"],
+        run: fuzz_io,
+    },
+    Subcommand {
+        name: "generate",
+        usage: "generate [--seed <n>] [--count <programs>] [--size <commands>] [--depth <levels>] [--io <percent>] [--loops <percent>]",
+        summary: "print random programs that always parse, for differential testing",
+        switches: &[],
+        values: &["--seed", "--count", "--size", "--depth", "--io", "--loops"],
+        run: generate,
+    },
+    Subcommand {
+        name: "golf",
+        usage: "golf <program.bf>",
+        summary: "rewrite a program shorter and check it still prints the same",
+        switches: &[],
+        values: &[],
+        run: golf,
+    },
+    Subcommand {
+        name: "disasm",
+        usage: "disasm <program.bf>",
+        summary: "show what each line of a program tokenizes to",
+        switches: &[],
+        values: &[],
+        run: disasm,
+    },
+];
+
+fn lookup(name: &str) -> Option<&'static Subcommand> {
+    SUBCOMMANDS.iter().find(|subcommand| subcommand.name == name)
+}
+
+fn usage(subcommand: &Subcommand) -> i32 {
+    eprintln!("{}", catalog::with_catalog(|catalog| catalog.usage(subcommand.usage)));
+    error::EXIT_USAGE
+}
+
+// What's wrong with the command line, then how it should go
+fn misuse(subcommand: &Subcommand, message: &str) -> i32 {
+    eprintln!("{}", error::coded(error::CODE_USAGE, message));
+    usage(subcommand)
+}
+
+// A file given on the command line that couldn't be read
+fn unreadable<T: fmt::Display, E: fmt::Display>(what: T, e: E) -> i32 {
+    eprintln!("{}", error::coded(error::CODE_READ, error::read_error(what, e)));
+    error::EXIT_IO
+}
 
-#[allow(unused_assignments)]
 fn main() {
-    let mut input_buffer = String::new();
-    let mut parser = Parser::new();
-    let mut interpreter = Interpreter::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let piped = ["-".to_string()];
+    let (subcommand, rest) = match args.first().map(String::as_str) {
+        Some("help") | Some("--help") | Some("-h") => process::exit(help(&args[1..])),
+        Some(name) => match lookup(name) {
+            Some(subcommand) => (subcommand, &args[1..]),
+            // `brainf program.bf` is short for `brainf run program.bf`, and
+            // `brainf -e <code>` for `brainf run -e <code>`
+            None if !name.starts_with('-') || name == "-e" || name == "--eval" => {
+                (&SUBCOMMANDS[1], &args[..])
+            }
+            None => (&SUBCOMMANDS[0], &args[..]),
+        },
+        // `cat hello.bf | brainf` runs the program rather than feeding it to
+        // a REPL nobody is looking at. `brainf repl` still takes piped lines
+        None if !io::stdin().is_terminal() => (&SUBCOMMANDS[1], &piped[..]),
+        None => (&SUBCOMMANDS[0], &args[..]),
+    };
+    match Args::parse(rest, subcommand.switches, subcommand.values) {
+        Ok(args) => process::exit((subcommand.run)(&args)),
+        Err(e) => {
+            process::exit(misuse(subcommand, &e))
+        }
+    }
+}
 
-    println!("Starting BrainF REPL (type \"?\" to quit)");
+// `brainf help` lists the subcommands, `brainf help run` shows one
+fn help(args: &[String]) -> i32 {
+    match args.first() {
+        Some(name) => match lookup(name) {
+            Some(subcommand) => {
+                println!("Usage: brainf {}", subcommand.usage);
+                println!("{}", subcommand.summary);
+                0
+            }
+            None => {
+                let message = catalog::usage_message("unknown-subcommand", &[name]);
+                eprintln!("{}", error::coded(error::CODE_USAGE, message));
+                error::EXIT_USAGE
+            }
+        },
+        None => {
+            println!("Usage: brainf [<subcommand>] [flags], or `brainf <program.bf>` to run one");
+            println!();
+            for subcommand in SUBCOMMANDS {
+                println!("  {:<8} {}", subcommand.name, subcommand.summary);
+            }
+            println!();
+            println!("`brainf help <subcommand>` shows its flags");
+            0
+        }
+    }
+}
+
+// Flags for the REPL, the ones shared with `run` are in run
+#[derive(Default, Debug)]
+struct Options {
+    resume: bool,
+    recover: bool,
+    autosave: bool,
+    quiet: bool,
+    no_emoji: bool,
+    no_color: bool,
+    session: Option<PathBuf>,
+    // `brainf debug program.bf` starts paused at the program's first instruction
+    debug: Option<PathBuf>,
+    log: Option<PathBuf>,
+    script: Option<PathBuf>,
+    preload: Option<PathBuf>,
+    run: RunOptions,
+}
+
+impl Options {
+    fn from_args(args: &Args) -> Result<Options, String> {
+        let config = config(args)?;
+        Ok(Options {
+            resume: args.has("--resume"),
+            recover: args.has("--recover"),
+            autosave: args.has("--autosave"),
+            quiet: args.has("--quiet") || args.has("-q") || config.quiet,
+            no_emoji: args.has("--no-emoji") || config.no_emoji,
+            no_color: args.has("--no-color") || config.no_color,
+            session: args.value("--session").map(PathBuf::from),
+            debug: None,
+            log: args.value("--log").map(PathBuf::from),
+            script: args.value("--script").map(PathBuf::from),
+            preload: args.value("--preload").map(PathBuf::from),
+            run: config.run.with_args(args)?,
+        })
+    }
+}
+
+// Settings from the config files, unless --no-config says to leave them.
+// BRAINF_ variables count either way
+fn config(args: &Args) -> Result<Config, String> {
+    let config = if args.has("--no-config") {
+        let mut config = Config::default();
+        config.read_env()?;
+        config
+    } else {
+        Config::load()?
+    };
+    for warning in &config.warnings {
+        eprintln!("{}", error::warned(error::CODE_UNKNOWN_SETTING, warning));
+    }
+    Ok(config)
+}
+
+// `brainf repl [flags]`
+fn repl(args: &Args) -> i32 {
+    match Options::from_args(args) {
+        Ok(ref options) if args.positional.is_empty() => run_repl(options),
+        Ok(_) => usage(lookup("repl").unwrap()),
+        Err(e) => {
+            misuse(lookup("repl").unwrap(), &e)
+        }
+    }
+}
 
-    // Loop
+// `brainf debug program.bf [flags]`
+fn debug(args: &Args) -> i32 {
+    match (Options::from_args(args), &args.positional[..]) {
+        (Ok(mut options), [program]) => {
+            options.debug = Some(PathBuf::from(program));
+            run_repl(&options)
+        }
+        (Err(e), _) => {
+            misuse(lookup("debug").unwrap(), &e)
+        }
+        _ => usage(lookup("debug").unwrap()),
+    }
+}
+
+// `brainf run program.bf [flags]` runs a program with stdin and stdout as
+// its raw input and output
+// A program of `-` is read from stdin, which leaves --input for its input,
+// and `-e <code>` runs code straight off the command line
+// Exit statuses are the EXIT_ constants in repl::error
+//
+// With more than one program each runs in a fresh interpreter, one after
+// the other, with a line on stderr for how each went. The exit status is
+// the highest any of them had
+fn run(args: &Args) -> i32 {
+    let code = args.value("-e").or_else(|| args.value("--eval"));
+    let config = config(args);
+    let no_emoji = args.has("--no-emoji") || config.as_ref().is_ok_and(|config| config.no_emoji);
+    let no_color = args.has("--no-color") || config.as_ref().is_ok_and(|config| config.no_color);
+    let options = config.and_then(|config| config.run.with_args(args));
+    let (options, programs) = match (options, &args.positional[..], code) {
+        (Ok(options), programs, None) if !programs.is_empty() => (options, programs),
+        (Ok(options), [], Some(_)) => (options, &["-e".to_string()][..]),
+        (Err(e), _, _) => return misuse(lookup("run").unwrap(), &e),
+        _ => return usage(lookup("run").unwrap()),
+    };
+    // Each program would start the files over
+    let files = !options.tees.is_empty() || options.transcript.is_some() || options.output_file.is_some();
+    if programs.len() > 1 && files {
+        return misuse(lookup("run").unwrap(), &catalog::usage_message("files-and-programs", &[]));
+    }
+    let input = match options.read_input() {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_READ, e));
+            return error::EXIT_IO;
+        }
+    };
+    if args.has("--watch") {
+        let unwatchable = |program: &String| program == "-" || fetch::is_url(program);
+        if code.is_some() || programs.iter().any(unwatchable) {
+            return misuse(lookup("run").unwrap(), &catalog::usage_message("watch-without-files", &[]));
+        }
+        return watch(programs, &options, input.as_deref());
+    }
+    if args.has("-i") || args.has("--interactive") {
+        // stdin is the REPL's once the program's done
+        if programs.len() > 1 || programs[0] == "-" {
+            return misuse(lookup("run").unwrap(), &catalog::usage_message("interactive-without-file", &[]));
+        }
+        repl::set_color(!no_color);
+        return interactive(&programs[0], code, options, input.as_deref(), no_emoji);
+    }
+    run_all(programs, code, &options, input.as_deref())
+}
+
+// `brainf run -i program.bf` runs it like any other, then starts the REPL
+// on the same interpreter, tape, pointer and program all as the run left
+// them. Whatever --tee and --transcript were writing stops with the run
+fn interactive(program: &str, code: Option<&str>, options: RunOptions, input: Option<&[u8]>, no_emoji: bool) -> i32 {
+    let (status, interpreter) = execute(program, code, &options, input, true);
+    let mut interpreter = match interpreter {
+        Some(interpreter) => interpreter,
+        None => return status,
+    };
+    let _ = io::stdout().flush();
+    repl::set_emoji(!no_emoji);
+    let console = Rc::new(RefCell::new(Console::stdio()));
+    interpreter.set_io(Box::new(Terminal::with_console(Rc::clone(&console))));
+    interpreter.print_brain();
+    let options = Options {
+        no_emoji,
+        run: options,
+        ..Options::default()
+    };
+    start_repl(&options, interpreter, console)
+}
+
+// Every program once, with a line each on how it went if there's more than one
+fn run_all(programs: &[String], code: Option<&str>, options: &RunOptions, input: Option<&[u8]>) -> i32 {
+    if let [program] = programs {
+        return run_program(program, code, options, input);
+    }
+
+    let mut failed = 0;
+    let mut worst = 0;
+    for program in programs {
+        let started = Instant::now();
+        let status = run_program(program, None, options, input);
+        let _ = io::stdout().flush();
+        let how = if status == 0 { "ok".to_string() } else { format!("exit {}", status) };
+        eprintln!("{}: {} in {:.2?}", program, how, started.elapsed());
+        if status != 0 {
+            failed += 1;
+        }
+        worst = worst.max(status);
+    }
+    eprintln!("{} of {} programs failed", failed, programs.len());
+    worst
+}
+
+// Run the programs, then again every time one of them is saved, until
+// killed. Files are polled since a few times a second is plenty for
+// someone switching over from their editor
+fn watch(programs: &[String], options: &RunOptions, input: Option<&[u8]>) -> i32 {
+    let modified = |program: &String| fs::metadata(program).and_then(|meta| meta.modified()).ok();
+    let mut seen: Vec<_> = programs.iter().map(modified).collect();
     loop {
-        // Read
-        input_buffer = Parser::read_std();
-        parser.tokenize(&input_buffer);
+        run_all(programs, None, options, input);
+        let _ = io::stdout().flush();
+        eprintln!("\n--- watching {} for changes ---", programs.join(", "));
+        let changed = loop {
+            thread::sleep(Duration::from_millis(250));
+            let now: Vec<_> = programs.iter().map(modified).collect();
+            // Editors that save by replacing the file leave a moment
+            // where it isn't there, so wait for it to come back
+            let changed = (0..programs.len()).find(|&i| now[i].is_some() && now[i] != seen[i]);
+            if let Some(i) = changed {
+                seen = now;
+                break &programs[i];
+            }
+        };
+        eprintln!("--- {} changed, running again ---", changed);
+    }
+}
+
+// `brainf pipe a.bf b.bf c.bf` is `a | b | c` with every program running at
+// once, the first reading stdin or --input and the last writing stdout.
+// Every program has to load before any of them start. The exit status is
+// the worst of them, a program stopped by the next one finishing first
+// having done nothing wrong
+fn pipe(args: &Args) -> i32 {
+    let options = config(args).and_then(|config| config.run.with_args(args));
+    let (options, programs) = match (options, &args.positional[..]) {
+        (Ok(options), programs) if programs.len() >= 2 => (options, programs),
+        (Err(e), _) => return misuse(lookup("pipe").unwrap(), &e),
+        _ => return usage(lookup("pipe").unwrap()),
+    };
+    let input = match options.read_input() {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_READ, e));
+            return error::EXIT_IO;
+        }
+    };
+    let mut sources = Vec::new();
+    for program in programs {
+        let source = match read_program(program) {
+            Ok(source) => source,
+            Err(e) => return unreadable(program, e),
+        };
+        let mut interpreter = Interpreter::new();
+        options.configure(&mut interpreter);
+        if let Err(e) = interpreter.load_source(&source) {
+            eprintln!("{}: {}", program, error::coded(e.code(), e));
+            return error::EXIT_PARSE;
+        }
+        warn_loaded(ErrorFormat::Text, program, &source, &mut interpreter);
+        sources.push(source);
+    }
+
+    let stages = repl::pipe::run(&sources, &options, input.as_deref());
+    let _ = io::stdout().flush();
+    let mut worst = 0;
+    for (program, stage) in programs.iter().zip(stages) {
+        if let Err(e) = stage.result {
+            eprintln!("{}: {}", program, error::coded(e.code(), &e));
+            worst = worst.max(e.exit_code());
+        }
+    }
+    worst
+}
 
-        // If `[` is unclosed continue accepting input
-        while !parser.match_stack.is_empty() {
-            input_buffer = Parser::read_cont();
-            parser.tokenize(&input_buffer);
+// `brainf bisect program.bf --when "cell[4] != 0"` prints the step and the
+// instruction after which the condition first holds, exiting 1 when it
+// never does. Input that isn't given is read from stdin up front, every
+// run of the search needs the same bytes
+fn bisect(args: &Args) -> i32 {
+    let options = config(args).and_then(|config| config.run.with_args(args));
+    let (options, program, when) = match (options, &args.positional[..], args.value("--when")) {
+        (Ok(options), [program], Some(when)) => (options, program, when),
+        (Err(e), _, _) => return misuse(lookup("bisect").unwrap(), &e),
+        _ => return usage(lookup("bisect").unwrap()),
+    };
+    let condition = match Condition::parse(when) {
+        Ok(condition) => condition,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_USAGE, catalog::usage_message("when", &[&when, &e])));
+            return error::EXIT_USAGE;
+        }
+    };
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => return unreadable(program, e),
+    };
+    let mut interpreter = Interpreter::new();
+    options.configure(&mut interpreter);
+    if let Err(e) = interpreter.load_source(&source) {
+        eprintln!("{}: {}", program, error::coded(e.code(), e));
+        return error::EXIT_PARSE;
+    }
+    warn_loaded(ErrorFormat::Text, program, &source, &mut interpreter);
+    let input = match options.read_input() {
+        Ok(Some(input)) => input,
+        Ok(None) => {
+            let mut input = Vec::new();
+            if let Err(e) = io::stdin().read_to_end(&mut input) {
+                return unreadable("stdin", e);
+            }
+            input
         }
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_READ, e));
+            return error::EXIT_IO;
+        }
+    };
 
-        // Evaluate
-        interpreter.take_tokens(mem::replace(&mut parser.tokens, Vec::new()));
-        interpreter.interpret();
+    match bisect::bisect(&source, &options, &input, &condition) {
+        Ok(found) => {
+            println!(
+                "{} first holds after step {}, the '{}' at #{} offset {} (line {}, column {})",
+                condition,
+                format::thousands(found.step as usize),
+                found.instruction,
+                found.cursor,
+                found.offset,
+                found.line,
+                found.column
+            );
+            println!("Found in {} runs", found.runs);
+            0
+        }
+        Err(Never::AtStart) => {
+            println!("{} already holds before the first step", condition);
+            0
+        }
+        Err(Never::Finished(steps)) => {
+            println!("{} never holds in {} steps", condition, format::thousands(steps as usize));
+            1
+        }
+        Err(Never::Stopped(e)) => {
+            eprintln!("{}: {}", program, error::coded(e.code(), &e));
+            e.exit_code()
+        }
+    }
+}
 
-        parser.reset();
+// `brainf pair producer.bf consumer.bf` runs both on one thread, see
+// repl::channel. Both write to stdout, only the first reads stdin or
+// --input, and max-steps is per program
+fn pair(args: &Args) -> i32 {
+    let options = config(args).and_then(|config| config.run.with_args(args));
+    let (options, programs) = match (options, &args.positional[..]) {
+        (Ok(options), programs @ [_, _]) => (options, programs),
+        (Err(e), _) => return misuse(lookup("pair").unwrap(), &e),
+        _ => return usage(lookup("pair").unwrap()),
+    };
+    let input = match options.read_input() {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_READ, e));
+            return error::EXIT_IO;
+        }
+    };
+    let mut pair = Pair::new();
+    pair.configure(&options);
+    for (i, program) in programs.iter().enumerate() {
+        let source = match read_program(program) {
+            Ok(source) => source,
+            Err(e) => return unreadable(program, e),
+        };
+        let machine = pair.machine(i);
+        if let Err(e) = machine.load_source(&source) {
+            eprintln!("{}: {}", program, error::coded(e.code(), e));
+            return error::EXIT_PARSE;
+        }
+        warn_loaded(ErrorFormat::Text, program, &source, machine);
+        machine.set_io(Box::new(match (i, input.as_deref()) {
+            (0, Some(input)) => Stdio::with_input(input),
+            (0, None) => Stdio::new(),
+            _ => Stdio::with_input(&[]),
+        }));
+    }
 
-        // Print
+    let results = pair.run();
+    let _ = io::stdout().flush();
+    let mut worst = 0;
+    for (program, result) in programs.iter().zip(results) {
+        if let Err(e) = result {
+            eprintln!("{}: {}", program, error::coded(e.code(), &e));
+            worst = worst.max(e.exit_code());
+        }
+    }
+    worst
+}
+
+// One program in an interpreter of its own, code being `-e`'s
+fn run_program(program: &str, code: Option<&str>, options: &RunOptions, input: Option<&[u8]>) -> i32 {
+    execute(program, code, options, input, false).0
+}
+
+// run_program, keeping the interpreter if the program got as far as
+// running. keep_tape skips the cache of constant output, which has
+// nothing to leave on the tape
+fn execute(
+    program: &str,
+    code: Option<&str>,
+    options: &RunOptions,
+    input: Option<&[u8]>,
+    keep_tape: bool,
+) -> (i32, Option<Interpreter>) {
+    let read = if let Some(code) = code {
+        Ok(code.to_string())
+    } else if program == "-" {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map(|_| repl::strip_shebang(repl::decode_source(&bytes)))
+    } else if fetch::is_url(program) {
+        fetch::fetch(program, options.max_download.unwrap_or(fetch::MAX_DOWNLOAD)).map(repl::strip_shebang)
+    } else {
+        read_program(program)
+    };
+    let program = if program == "-" { "<stdin>" } else { program };
+    let format = options.error_format;
+    let source = match read {
+        Ok(source) => source,
+        Err(e) => {
+            let message = error::read_error(program, e);
+            let json = error::to_json("io", error::CODE_READ, &message, None, error::EXIT_IO);
+            report(format, program, &format!("error[{}]: {}", error::CODE_READ, message), json);
+            return (error::EXIT_IO, None);
+        }
+    };
+    let mut interpreter = Interpreter::new();
+    options.configure(&mut interpreter);
+    let (source, data) = if options.bang_input {
+        interpreter.split_input(&source)
+    } else {
+        (&source[..], None)
+    };
+    if data.is_some() && input.is_some() {
+        let message = catalog::usage_message("input-and-bang-input", &[]);
+        let json = error::to_json("usage", error::CODE_USAGE, &message, None, error::EXIT_USAGE);
+        report(format, program, &format!("{}: error[{}]: {}", program, error::CODE_USAGE, message), json);
+        return (error::EXIT_USAGE, None);
+    }
+    let input = data.map(str::as_bytes).or(input);
+    let fixed;
+    let repair = if options.fix_brackets { interpreter.fix_brackets(source) } else { None };
+    let source = match repair {
+        Some((text, missing)) => {
+            fixed = text;
+            // Next to the program, prog.bf's repairs in prog.fixed.bf
+            let sidecar = Some(Path::new(program))
+                .filter(|_| code.is_none() && program != "<stdin>" && !fetch::is_url(program))
+                .map(|path| path.with_extension(match path.extension() {
+                    Some(ext) => format!("fixed.{}", ext.to_string_lossy()),
+                    None => "fixed".to_string(),
+                }));
+            let warning = format!("{} is missing {} closing ']', running it with them added at the end", program, missing);
+            eprintln!("{}", error::warned(error::CODE_REPAIRED, warning));
+            match sidecar.map(|path| (fs::write(&path, &fixed), path)) {
+                Some((Ok(()), path)) => {
+                    let warning = format!("the repaired program is in {}", path.display());
+                    eprintln!("{}", error::warned(error::CODE_REPAIRED, warning));
+                }
+                Some((Err(e), path)) => {
+                    eprintln!("{}", error::coded(error::CODE_WRITE, error::write_error(path.display(), e)));
+                }
+                None => (),
+            }
+            &fixed[..]
+        }
+        None => source,
+    };
+    // Text stops at the first parse error like it always has, JSON is for
+    // tools that can show them all
+    let errors = interpreter.parse_errors(source);
+    if format == ErrorFormat::Json {
+        for e in &errors {
+            report(format, program, "", e.to_json(source));
+        }
+    } else if let Some(e) = errors.first() {
+        eprintln!("{}: error[{}]: {}", program, e.code(), e);
+    }
+    if !errors.is_empty() {
+        return (error::EXIT_PARSE, None);
+    }
+    if let Err(e) = interpreter.load_source(source) {
+        eprintln!("{}: {}", program, error::coded(e.code(), &e));
+        return (error::EXIT_PARSE, None);
+    }
+    warn_loaded(format, program, source, &mut interpreter);
+    if let Err(e) = options.set_loop_quotas(&mut interpreter) {
+        let json = error::to_json("usage", error::CODE_USAGE, &e, None, error::EXIT_USAGE);
+        report(format, program, &format!("{}: error[{}]: {}", program, error::CODE_USAGE, e), json);
+        return (error::EXIT_USAGE, None);
+    }
+    let stdio = Box::new(match input {
+        Some(input) => Stdio::with_input(input),
+        None => Stdio::new(),
+    });
+    match options.io(stdio) {
+        Ok(io) => interpreter.set_io(io),
+        Err(e) => {
+            let json = error::to_json("io", error::CODE_WRITE, &e, None, error::EXIT_IO);
+            report(format, program, &format!("error[{}]: {}", error::CODE_WRITE, e), json);
+            return (error::EXIT_IO, None);
+        }
+    }
+    // A cached run doesn't leave a tape behind to look at
+    let started = Instant::now();
+    let result = if keep_tape || options.exit_cell.is_some() || options.show_tape.is_some() || options.time {
+        interpreter.interpret()
+    } else {
+        interpreter.interpret_constant()
+    };
+    let elapsed = started.elapsed();
+    // On stderr to keep it out of the output, whether the run finished or
+    // not, starting on a line of its own when the output didn't end one
+    let _ = io::stdout().flush();
+    let mut mid_line = options.output_file.is_none()
+        && interpreter.outputs().get(1).and_then(|run| run.last()).is_some_and(|&b| b != b'\n');
+    let mut newline = || if mem::take(&mut mid_line) { "\n" } else { "" };
+    if let Some(formatter) = options.show_tape {
+        let mut tape = String::new();
+        let _ = formatter.render(&interpreter.brain, &mut tape);
+        eprintln!("{}Tape:{}", newline(), tape);
+    }
+    if options.time {
+        let stats = interpreter.stats();
+        eprintln!(
+            "{}Time: {:.2?}, {} steps, {} cells of tape at most, {} bytes in, {} bytes out",
+            newline(),
+            elapsed,
+            format::thousands(stats.steps as usize),
+            format::thousands(stats.peak_tape),
+            format::thousands(stats.input_bytes as usize),
+            format::thousands(stats.output_bytes as usize)
+        );
+    }
+    let status = match result {
+        Ok(()) => options.exit_cell.map_or(0, |cell| cell.value(&interpreter) as i32),
+        Err(e) => {
+            let mut json = e.to_json(interpreter.source(), interpreter.source_offset());
+            let mut text = format!("{}: error[{}]: {}", program, e.code(), e);
+            // With --history, how it got there
+            if let (Json::Object(ref mut pairs), Some(recent)) = (&mut json, interpreter.recent_json()) {
+                pairs.push(("history".to_string(), recent));
+            }
+            if let Some(recent) = interpreter.render_recent() {
+                text = format!("{}\n{}", text, recent);
+            }
+            report(format, program, &text, json);
+            e.exit_code()
+        }
+    };
+    (status, Some(interpreter))
+}
+
+// An error on stderr, text as it is or json with the program it's about
+// as "file" in front of the rest
+// What loading a program had to say that isn't an error, like an alias
+// read as a command, as program:line:column lines the way check warns
+fn warn_loaded(format: ErrorFormat, program: &str, source: &str, interpreter: &mut Interpreter) {
+    for (offset, message) in interpreter.take_warnings() {
+        let json = error::to_json("warning", error::CODE_ALIASED, &message, Some((source, offset)), 0);
+        let (line, column) = error::position(source, offset);
+        let text = format!("{}:{}:{}: {}", program, line, column, error::warned(error::CODE_ALIASED, &message));
+        report(format, program, &text, json);
+    }
+}
+
+fn report(format: ErrorFormat, program: &str, text: &str, json: Json) {
+    match (format, json) {
+        (ErrorFormat::Json, Json::Object(pairs)) => {
+            let mut with_file = vec![("file".to_string(), Json::from(program))];
+            with_file.extend(pairs);
+            eprintln!("{}", Json::Object(with_file));
+        }
+        (ErrorFormat::Json, json) => eprintln!("{}", json),
+        (ErrorFormat::Text, _) => eprintln!("{}", text),
+    }
+}
+
+// `brainf serve --listen 127.0.0.1:7070` gives everyone who connects their
+// own REPL, with ServeOptions' limits unless the flags say otherwise
+fn serve(args: &Args) -> i32 {
+    let options = match serve_options(args) {
+        Ok(options) if args.positional.is_empty() => options,
+        Ok(_) => return usage(lookup("serve").unwrap()),
+        Err(e) => return misuse(lookup("serve").unwrap(), &e),
+    };
+    let address = args.value("--listen").unwrap_or("127.0.0.1:7070");
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_LISTEN, format!("Couldn't listen on {}: {}", address, e)));
+            return error::EXIT_IO;
+        }
+    };
+    eprintln!("Listening on {}", address);
+    match serve::serve(listener, options) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_LISTEN, format!("Stopped listening: {}", e)));
+            error::EXIT_IO
+        }
+    }
+}
+
+// `brainf serve-http [--listen address] [limits]` answers POST /run with
+// JSON, see repl::http. Listens on 127.0.0.1:8080 unless told otherwise
+fn serve_http(args: &Args) -> i32 {
+    let options = match serve_options(args) {
+        Ok(options) if args.positional.is_empty() => options,
+        Ok(_) => return usage(lookup("serve-http").unwrap()),
+        Err(e) => return misuse(lookup("serve-http").unwrap(), &e),
+    };
+    let address = args.value("--listen").unwrap_or("127.0.0.1:8080");
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_LISTEN, format!("Couldn't listen on {}: {}", address, e)));
+            return error::EXIT_IO;
+        }
+    };
+    eprintln!("Listening on http://{}", address);
+    match http::serve_http(listener, options) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_LISTEN, format!("Stopped listening: {}", e)));
+            error::EXIT_IO
+        }
+    }
+}
+
+fn serve_options(args: &Args) -> Result<ServeOptions, String> {
+    let defaults = ServeOptions::default();
+    Ok(ServeOptions {
+        max_steps: args.number("--max-steps")?.or(defaults.max_steps),
+        watchdog: args.number("--watchdog")?.or(defaults.watchdog),
+        idle: args.number("--idle")?.map(Duration::from_secs).or(defaults.idle),
+        max_clients: args.number("--max-clients")?.unwrap_or(defaults.max_clients),
+        ..defaults
+    })
+}
+
+// `brainf check [--strict] [--strict-comments] program.bf` finds problems
+// without running it. Warnings alone still exit 0, denied lints exit 3 like
+// errors. `--deny warnings` denies every lint that would warn, and lint
+// levels from the config's [lints] go under the flags
+fn check(args: &Args) -> i32 {
+    let parsed = config(args).and_then(|config| {
+        let format = match args.value("--error-format") {
+            Some(name) => ErrorFormat::parse(name)?,
+            None => config.run.error_format,
+        };
+        let mut lints = config.lints;
+        for (flag, lint) in args.values_of(&["--allow", "--warn", "--deny"]) {
+            lints.set(lint, Level::parse(&flag[2..])?)?;
+        }
+        Ok((format, lints, config.run.strict_comments || args.has("--strict-comments")))
+    });
+    let (format, lints, strict_comments, program) = match (parsed, &args.positional[..]) {
+        (Ok((format, lints, strict_comments)), [program]) => (format, lints, strict_comments, program),
+        (Err(e), _) => return misuse(lookup("check").unwrap(), &e),
+        _ => return usage(lookup("check").unwrap()),
+    };
+    let json = format == ErrorFormat::Json;
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => {
+            let message = error::read_error(program, e);
+            let json = error::to_json("io", error::CODE_READ, &message, None, error::EXIT_IO);
+            report(format, program, &format!("error[{}]: {}", error::CODE_READ, message), json);
+            return error::EXIT_IO;
+        }
+    };
+    // JSON goes to stderr with everything else that does, warnings with a
+    // kind of "warning" and an exit_code of 0
+    match check::check(&source, args.has("--strict"), strict_comments, &lints) {
+        Ok(warnings) => {
+            let mut status = 0;
+            for warning in &warnings {
+                let (severity, exit_code) = match warning.level {
+                    Level::Deny => ("error", error::EXIT_PARSE),
+                    _ => ("warning", 0),
+                };
+                status = status.max(exit_code);
+                if json {
+                    let at = Some((source.as_str(), warning.offset));
+                    let json = error::to_json("warning", warning.code, &warning.message, at, exit_code);
+                    report(format, program, "", json);
+                } else {
+                    let (line, column) = error::position(&source, warning.offset);
+                    println!("{}:{}:{}: {}[{}]: {}", program, line, column, severity, warning.code, warning.message);
+                }
+            }
+            if warnings.is_empty() && !json {
+                println!("{}: ok", program);
+            }
+            status
+        }
+        Err(errors) => {
+            for e in &errors {
+                if json {
+                    report(format, program, "", e.to_json(&source));
+                } else {
+                    let (line, column) = error::position(&source, e.offset());
+                    println!("{}:{}:{}: error[{}]: {}", program, line, column, e.code(), e.message());
+                }
+            }
+            error::EXIT_PARSE
+        }
+    }
+}
+
+// `brainf record program.bf run.replay [--input text | --input-file file] [--max-steps N]
+// [--dialect name]`
+// runs a program and keeps everything needed to run it the same way again
+// Exits 2 if the program couldn't be run or the replay saved
+fn record(args: &Args) -> i32 {
+    let options = config(args).and_then(|config| config.run.with_args(args));
+    let (options, program, path) = match (options, &args.positional[..]) {
+        (Ok(options), [program, path]) => (options, program, path),
+        (Err(e), _) => return misuse(lookup("record").unwrap(), &e),
+        _ => return usage(lookup("record").unwrap()),
+    };
+    let input = match options.read_input() {
+        Ok(input) => input.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_READ, e));
+            return error::EXIT_IO;
+        }
+    };
+    let dialect = options.dialect.as_deref().unwrap_or("brainfuck");
+
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => return unreadable(program, e),
+    };
+    let replay = match Replay::record(&source, dialect, options.max_steps, &input) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{}: {}", program, error::coded(e.code(), &e));
+            return e.exit_code();
+        }
+    };
+    if let Err(e) = replay.save(path) {
+        eprintln!("{}", error::coded(error::CODE_WRITE, error::write_error(path, e)));
+        return error::EXIT_IO;
+    }
+    println!("Recorded {} bytes of output to {}", replay.output.len(), path);
+    0
+}
+
+// `brainf replay run.replay` runs a recording again and compares outputs
+// Exits 0 when they match, 1 when they don't, 2 if it couldn't be run
+fn replay(args: &Args) -> i32 {
+    let args = &args.positional[..];
+    if args.len() != 1 {
+        return usage(lookup("replay").unwrap());
+    }
+    let replay = match Replay::load(&args[0]) {
+        Ok(replay) => replay,
+        Err(e) => return unreadable(&args[0], e),
+    };
+    let output = match replay.run() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{}: {}", args[0], error::coded(e.code(), &e));
+            return e.exit_code();
+        }
+    };
+
+    match xcheck::first_difference(&replay.output, &output) {
+        None => {
+            println!(
+                "Replay matches ({} bytes, program {:016x})",
+                output.len(),
+                replay::hash(replay.source.as_bytes())
+            );
+            0
+        }
+        Some(i) => {
+            let from = i.saturating_sub(16);
+            println!("Replay differs at byte {}", i);
+            println!("  recorded: \"{}\"", xcheck::escape(&replay.output[from.min(replay.output.len())..]));
+            println!("  now:      \"{}\"", xcheck::escape(&output[from.min(output.len())..]));
+            1
+        }
+    }
+}
+
+// `brainf export program.bf stuck.archive [flags]` runs it and saves where
+// it got to, see archive::Archive. Stopping at a limit is the usual reason
+// to, so that still exits 0
+fn export(args: &Args) -> i32 {
+    let options = config(args).and_then(|config| config.run.with_args(args));
+    let (options, program, path) = match (options, &args.positional[..]) {
+        (Ok(options), [program, path]) => (options, program, path),
+        (Err(e), _) => return misuse(lookup("export").unwrap(), &e),
+        _ => return usage(lookup("export").unwrap()),
+    };
+    let input = match options.read_input() {
+        Ok(input) => input.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_READ, e));
+            return error::EXIT_IO;
+        }
+    };
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => return unreadable(program, e),
+    };
+    let archive = match Archive::record(&source, &options, &input) {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("{}: {}", program, e);
+            return error::EXIT_PARSE;
+        }
+    };
+    if let Err(e) = archive.save(path) {
+        eprintln!("{}", error::coded(error::CODE_WRITE, error::write_error(path, e)));
+        return error::EXIT_IO;
+    }
+    match archive.error {
+        Some(ref e) => println!("Saved {} to {}, it stopped: {}", program, path, e),
+        None => println!("Saved {} to {}, it finished", program, path),
+    }
+    0
+}
+
+// `brainf import stuck.archive` starts the REPL with everything as the
+// exported run left it, step mode on so nothing moves until asked
+fn import(args: &Args) -> i32 {
+    let path = match &args.positional[..] {
+        [path] => path,
+        _ => return usage(lookup("import").unwrap()),
+    };
+    repl::set_emoji(!args.has("--no-emoji"));
+    repl::set_color(!args.has("--no-color") && !config(args).is_ok_and(|config| config.no_color));
+    let archive = match Archive::load(path) {
+        Ok(archive) => archive,
+        Err(e) => return unreadable(path, e),
+    };
+    let console = Rc::new(RefCell::new(Console::stdio()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_io(Box::new(Terminal::with_console(Rc::clone(&console))));
+    if let Err(e) = archive.load_into(&mut interpreter) {
+        eprintln!("{}: {}", path, e);
+        return error::EXIT_PARSE;
+    }
+    interpreter.set_step_mode(true);
+    let replay = &archive.replay;
+    println!(
+        "{} read {} of {} input bytes and wrote {}: {}",
+        path,
+        archive.read,
+        replay.input.len(),
+        format::thousands(replay.output.len()),
+        archive.error.as_deref().unwrap_or("it finished")
+    );
+    println!("Output so far: \"{}\"", xcheck::escape(&replay.output));
+    interpreter.print_brain();
+    if !interpreter.is_finished() {
+        interpreter.print_position();
+    }
+    let options = Options {
+        quiet: args.has("--quiet") || args.has("-q"),
+        ..Options::default()
+    };
+    start_repl(&options, interpreter, console)
+}
+
+// `brainf xcheck program.bf --against "bf %f" [--input text]`
+// Exits 0 when both interpreters agree, 1 when they don't, 2 if either failed
+fn xcheck(args: &Args) -> i32 {
+    let input = args.value("--input").unwrap_or_default();
+    let (program, against) = match (&args.positional[..], args.value("--against")) {
+        ([program], Some(against)) => (Path::new(program), against),
+        _ => return usage(lookup("xcheck").unwrap()),
+    };
+
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => return unreadable(program.display(), e),
+    };
+    let ours = match repl::capture(&source, input.as_bytes()) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{}: {}", program.display(), error::coded(e.code(), &e));
+            return e.exit_code();
+        }
+    };
+    let theirs = match xcheck::run_reference(against, program, input.as_bytes()) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_REFERENCE, format!("Reference interpreter failed: {}", e)));
+            return error::EXIT_IO;
+        }
+    };
+
+    match xcheck::first_difference(&ours, &theirs) {
+        None => {
+            println!("Outputs match ({} bytes)", ours.len());
+            0
+        }
+        Some(i) => {
+            // A little context before the first difference helps
+            let from = i.saturating_sub(16);
+            println!("Outputs differ at byte {}", i);
+            println!("  brainf:    \"{}\"", xcheck::escape(&ours[from.min(ours.len())..]));
+            println!("  reference: \"{}\"", xcheck::escape(&theirs[from.min(theirs.len())..]));
+            if args.has("--reduce") {
+                reduce_difference(&source, against, input.as_bytes());
+            }
+            1
+        }
+    }
+}
+
+// Candidates have to finish in this many steps here before the reference
+// gets a go at them, so cutting a `-` out of a loop can't hang either one
+const REDUCE_STEPS: u64 = 10_000_000;
+
+// Shrinks a program whose output differs from the reference's to the
+// smallest one that still differs, reusing one file for every candidate
+fn reduce_difference(source: &str, against: &str, input: &[u8]) {
+    let path = env::temp_dir().join(format!("brainf-reduce-{}.bf", process::id()));
+    let reduction = reduce::reduce(source, |candidate| {
+        let ours = match repl::capture_within(candidate, input, Some(REDUCE_STEPS)) {
+            Ok(ours) => ours,
+            Err(_) => return false,
+        };
+        fs::write(&path, candidate).is_ok()
+            && xcheck::run_reference(against, &path, input).is_ok_and(|theirs| theirs != ours)
+    });
+    let _ = fs::remove_file(&path);
+    print_reduction(&reduction, source);
+}
+
+fn print_reduction(reduction: &reduce::Reduction, source: &str) {
+    if reduction.program == source {
+        println!("Couldn't make it any smaller");
+    } else {
+        println!(
+            "Smallest that still does it, {} commands after {} tries:",
+            format::thousands(reduction.program.chars().count()),
+            format::thousands(reduction.tests)
+        );
+        println!("  {}", reduction.program);
+    }
+}
+
+// `brainf fuzz-io program.bf` runs it on generated inputs under a step
+// limit, a million unless --max-steps says otherwise. Without --seed a
+// new one is picked and printed so the run can be repeated
+// Exits 1 if any input stopped the program, 2 if it couldn't be run
+fn fuzz_io(args: &Args) -> i32 {
+    let parsed = config(args).and_then(|config| {
+        let run = config.run.with_args(args)?;
+        let defaults = FuzzOptions::default();
+        let fuzz = FuzzOptions {
+            runs: args.number("--runs")?.unwrap_or(defaults.runs),
+            seed: args.number("--seed")?.unwrap_or_else(Rng::fresh_seed),
+            max_len: args.number("--max-len")?.unwrap_or(defaults.max_len),
+        };
+        Ok((run, fuzz))
+    });
+    let (run, options, program) = match (parsed, &args.positional[..]) {
+        (Ok((run, options)), [program]) => (run, options, program),
+        (Err(e), _) => return misuse(lookup("fuzz-io").unwrap(), &e),
+        _ => return usage(lookup("fuzz-io").unwrap()),
+    };
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => return unreadable(program, e),
+    };
+    let mut interpreter = Interpreter::new();
+    run.configure(&mut interpreter);
+    if run.max_steps.is_none() {
+        interpreter.set_max_steps(Some(1_000_000));
+    }
+    if let Err(e) = interpreter.load_source(&source) {
+        eprintln!("{}: {}", program, error::coded(e.code(), e));
+        return error::EXIT_PARSE;
+    }
+    warn_loaded(ErrorFormat::Text, program, &source, &mut interpreter);
+
+    let report = fuzz::fuzz(&mut interpreter, &options);
+    println!("Ran {} inputs from seed {}", format::thousands(report.runs), options.seed);
+    for failure in &report.failures {
+        println!(
+            "  {} stopped it: {}, shortest \"{}\"",
+            format::thousands(failure.count),
+            failure.error,
+            xcheck::escape(&failure.input)
+        );
+        if args.has("--reduce") {
+            let reduction = reduce::reduce(&source, |candidate| {
+                let mut interpreter = Interpreter::new();
+                run.configure(&mut interpreter);
+                interpreter.set_max_steps(run.max_steps.or(Some(1_000_000)));
+                if interpreter.load_source(candidate).is_err() {
+                    return false;
+                }
+                interpreter.set_io(Box::new(Buffer::new(&failure.input)));
+                interpreter
+                    .interpret()
+                    .is_err_and(|e| mem::discriminant(&e) == mem::discriminant(&failure.error))
+            });
+            print_reduction(&reduction, &source);
+        }
+    }
+    if let Some((ref input, most)) = report.most_output {
+        println!("Most output: {} bytes from \"{}\"", format::thousands(most), xcheck::escape(input));
+    }
+    if report.failures.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+// `brainf generate` prints random programs, one a line, so they can be
+// fed to anything that runs two backends and compares them. Program n is
+// from seed + n, and the seed goes to stderr when it wasn't given
+fn generate(args: &Args) -> i32 {
+    let (fresh, count, options) = match (generate_options(args), &args.positional[..]) {
+        (Ok(parsed), []) => parsed,
+        (Err(e), _) => return misuse(lookup("generate").unwrap(), &e),
+        _ => return usage(lookup("generate").unwrap()),
+    };
+    if fresh {
+        eprintln!("Seed {}", options.seed);
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for n in 0..count {
+        let options = GenerateOptions {
+            seed: options.seed.wrapping_add(n),
+            ..options
+        };
+        if writeln!(out, "{}", generate::generate(&options)).is_err() {
+            break;
+        }
+    }
+    0
+}
+
+// Whether the seed is fresh, how many programs and the options for the first
+fn generate_options(args: &Args) -> Result<(bool, u64, GenerateOptions), String> {
+    let defaults = GenerateOptions::default();
+    let seed = args.number("--seed")?;
+    let options = GenerateOptions {
+        seed: seed.unwrap_or_else(Rng::fresh_seed),
+        size: args.number("--size")?.unwrap_or(defaults.size),
+        max_depth: args.number("--depth")?.unwrap_or(defaults.max_depth),
+        io_percent: args.number("--io")?.unwrap_or(defaults.io_percent),
+        loop_percent: args.number("--loops")?.unwrap_or(defaults.loop_percent),
+    };
+    if options.io_percent > 100 || options.loop_percent > 100 {
+        return Err(catalog::usage_message("percentages", &[]));
+    }
+    Ok((seed.is_none(), args.number("--count")?.unwrap_or(1), options))
+}
+
+// `brainf golf program.bf` prints a shorter program that prints the same,
+// and how much each pass took off on stderr
+// Exits 2 if the program couldn't be read, 3 if it doesn't load
+fn golf(args: &Args) -> i32 {
+    let program = match &args.positional[..] {
+        [program] => program,
+        _ => return usage(lookup("golf").unwrap()),
+    };
+    let source = match read_program(program) {
+        Ok(source) => source,
+        Err(e) => return unreadable(program, e),
+    };
+    if let Some(e) = Interpreter::new().parse_errors(&source).first() {
+        eprintln!("{}: {}", program, error::coded(e.code(), e));
+        return error::EXIT_PARSE;
+    }
+
+    let golfed = golf::golf(&source);
+    for pass in &golfed.passes {
+        let outcome = match pass.outcome {
+            golf::Outcome::Verified(1) => "same output".to_string(),
+            golf::Outcome::Verified(n) => format!("same output on {} inputs", n),
+            golf::Outcome::Unverified => "unverified, it didn't finish".to_string(),
+            golf::Outcome::Differs(ref input) => {
+                format!("left out, the output changed on \"{}\"", xcheck::escape(input))
+            }
+        };
+        eprintln!("  {:<14}{:>7} bytes  {}", pass.name, format!("-{}", pass.saved), outcome);
+    }
+    let (before, after) = (source.len(), golfed.program.len());
+    eprintln!(
+        "{} bytes down to {}, {} saved",
+        format::thousands(before),
+        format::thousands(after),
+        format::thousands(before - after.min(before))
+    );
+    println!("{}", golfed.program);
+    0
+}
+
+// `brainf test program.bf program.test` runs every case in the spec file
+// Exits 1 if any case fails, 3 if the spec doesn't parse and 6 if either
+// file can't be read
+fn test(args: &Args) -> i32 {
+    let args = &args.positional[..];
+    if args.len() != 2 {
+        return usage(lookup("test").unwrap());
+    }
+    let (source, cases) = match (read_program(&args[0]), fs::read_to_string(&args[1])) {
+        (Ok(source), Ok(spec)) => match spec::parse(&spec) {
+            Ok(cases) => (source, cases),
+            Err(e) => {
+                eprintln!("{}: {}", args[1], error::coded(error::CODE_SPEC, e));
+                return error::EXIT_PARSE;
+            }
+        },
+        (Err(e), _) => return unreadable(&args[0], e),
+        (_, Err(e)) => return unreadable(&args[1], e),
+    };
+
+    let mut failed = 0;
+    for case in &cases {
+        match spec::run(&source, case) {
+            Outcome::Pass => println!("ok    line {}", case.line),
+            Outcome::Fail { expected, actual } => {
+                failed += 1;
+                println!("FAIL  line {}", case.line);
+                println!("  expected: \"{}\"", xcheck::escape(&expected));
+                println!("  actual:   \"{}\"", xcheck::escape(&actual));
+            }
+            Outcome::Error(e) => {
+                failed += 1;
+                println!("FAIL  line {}: {}", case.line, e);
+            }
+        }
+    }
+    println!("{} passed, {} failed", cases.len() - failed, failed);
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// `brainf batch corpus.zip` runs each program in the archive on its input
+// and checks what it prints, a million steps apiece unless told otherwise
+#[cfg(feature = "corpus")]
+fn batch(args: &Args) -> i32 {
+    let (mut run, archive) = match (config(args).and_then(|config| config.run.with_args(args)), &args.positional[..]) {
+        (Ok(run), [archive]) => (run, archive),
+        (Err(e), _) => return misuse(lookup("batch").unwrap(), &e),
+        _ => return usage(lookup("batch").unwrap()),
+    };
+    if run.max_steps.is_none() {
+        run.max_steps = Some(1_000_000);
+    }
+    let programs = match corpus::read(archive) {
+        Ok(programs) => programs,
+        Err(e) => return unreadable(archive, e),
+    };
+    if programs.is_empty() {
+        eprintln!("{}", error::coded(error::CODE_USAGE, catalog::usage_message("no-programs", &[&archive])));
+        return error::EXIT_USAGE;
+    }
+
+    let mut failed = 0;
+    for program in &programs {
+        match corpus::judge(program, &run) {
+            Outcome::Pass if program.expected.is_none() => println!("ran   {}", program.name),
+            Outcome::Pass => println!("ok    {}", program.name),
+            Outcome::Fail { expected, actual } => {
+                failed += 1;
+                println!("FAIL  {}", program.name);
+                println!("  expected: \"{}\"", xcheck::escape(&expected));
+                println!("  actual:   \"{}\"", xcheck::escape(&actual));
+            }
+            Outcome::Error(e) => {
+                failed += 1;
+                println!("FAIL  {}: {}", program.name, e);
+            }
+        }
+    }
+    println!("{} passed, {} failed", programs.len() - failed, failed);
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(not(feature = "corpus"))]
+fn batch(_args: &Args) -> i32 {
+    eprintln!("{}", error::coded(error::CODE_USAGE, catalog::usage_message("no-corpus", &[])));
+    error::EXIT_USAGE
+}
+
+// `brainf disasm program.bf` shows what each line tokenizes to
+fn disasm(args: &Args) -> i32 {
+    let args = &args.positional[..];
+    if args.len() != 1 {
+        return usage(lookup("disasm").unwrap());
+    }
+    let source = match read_program(&args[0]) {
+        Ok(source) => source,
+        Err(e) => return unreadable(&args[0], e),
+    };
+    match disasm::listing(&source) {
+        Ok(listing) => {
+            print!("{}", listing);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {}", args[0], e);
+            error::EXIT_PARSE
+        }
+    }
+}
+
+fn run_repl(options: &Options) -> i32 {
+    repl::set_emoji(!options.no_emoji);
+    repl::set_color(!options.no_color);
+    let session = options.session.clone().unwrap_or_else(snapshot::session_path);
+
+    let console = Rc::new(RefCell::new(Console::stdio()));
+    let mut interpreter = Interpreter::new();
+
+    // A recovery snapshot is newer than the session if both are asked for
+    if options.resume {
+        restore(&mut interpreter, &session);
+    }
+    if options.recover {
+        restore(&mut interpreter, &snapshot::recovery_path());
+    }
+    if let Some(ref path) = options.script {
+        if let Err(e) = script::replay_from(path) {
+            eprintln!("{}", error::coded(error::CODE_READ, error::read_error(path.display(), e)));
+            process::exit(1);
+        }
+    }
+    if let Some(ref path) = options.log {
+        if let Err(e) = script::record_to(path) {
+            eprintln!("{}", error::coded(error::CODE_WRITE, error::write_error(path.display(), e)));
+        }
+    }
+    options.run.configure(&mut interpreter);
+    match options.run.io(Box::new(Terminal::with_console(Rc::clone(&console)))) {
+        Ok(io) => interpreter.set_io(io),
+        Err(e) => eprintln!("{}", error::coded(error::CODE_WRITE, e)),
+    }
+    // Typed input only once these run out
+    match options.run.read_input() {
+        Ok(Some(input)) => interpreter.feed(&input),
+        Ok(None) => (),
+        Err(e) => eprintln!("{}", error::coded(error::CODE_READ, e)),
+    }
+    start_repl(options, interpreter, console)
+}
+
+// The prompt for an interpreter that's ready for it, until :quit
+fn start_repl(options: &Options, mut interpreter: Interpreter, console: Rc<RefCell<Console>>) -> i32 {
+    let session = options.session.clone().unwrap_or_else(snapshot::session_path);
+    interpreter.set_show_progress(!options.quiet && io::stderr().is_terminal());
+    interpreter.set_recovery(Some(Recovery::new(
+        snapshot::recovery_path(),
+        Duration::from_secs(5),
+    )));
+
+    // Preloaded code runs before the prompt, a debugged program waits for it
+    if let Some(ref path) = options.preload {
+        load_file(&mut interpreter, path);
+        if let Err(e) = interpreter.interpret() {
+            repl::print_error(error::coded(e.code(), &e));
+        }
         interpreter.print_brain();
     }
+    if let Some(ref path) = options.debug {
+        load_file(&mut interpreter, path);
+        interpreter.set_step_mode(true);
+        interpreter.begin_run();
+    }
+    let mut repl = Repl::new(interpreter, console);
+    let result = repl.greet().and_then(|()| loop {
+        match repl.turn()? {
+            Turn::Quit => return Ok(()),
+            Turn::Evaluated if options.autosave => save_session(&repl.interpreter, &session),
+            _ => (),
+        }
+    });
+    let status = match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", error::coded(error::CODE_TERMINAL, format!("The REPL stopped: {}", e)));
+            error::EXIT_IO
+        }
+    };
+    let interpreter = repl.interpreter;
+
+    if options.resume || options.autosave {
+        save_session(&interpreter, &session);
+    }
+    if let Some(recovery) = interpreter.recovery() {
+        recovery.discard();
+    }
+    status
+}
+
+// A program file, minus any shebang line, see repl::decode_source and
+// repl::strip_shebang
+fn read_program<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    fs::read(path).map(|bytes| repl::strip_shebang(repl::decode_source(&bytes)))
+}
+
+// Files given on the command line have to load, or there's no point starting
+fn load_file(interpreter: &mut Interpreter, path: &Path) {
+    let source = match read_program(path) {
+        Ok(source) => source,
+        Err(e) => process::exit(unreadable(path.display(), e)),
+    };
+    if let Err(e) = interpreter.load_source(&source) {
+        eprintln!("{}: {}", path.display(), error::coded(e.code(), &e));
+        process::exit(error::EXIT_PARSE);
+    }
+    warn_loaded(ErrorFormat::Text, &path.display().to_string(), &source, interpreter);
+}
+
+fn restore(interpreter: &mut Interpreter, path: &PathBuf) {
+    match Snapshot::load(path) {
+        Ok(saved) => {
+            saved.restore(&mut interpreter.brain);
+            interpreter.print_brain();
+        }
+        Err(e) => {
+            let message = error::read_error(path.display(), e);
+            eprintln!("{}", error::coded(error::CODE_READ, message));
+        }
+    }
+}
+
+fn save_session(interpreter: &Interpreter, session: &PathBuf) {
+    if let Err(e) = Snapshot::of(&interpreter.brain).save(session) {
+        let message = error::write_error(session.display(), e);
+        eprintln!("{}", error::coded(error::CODE_WRITE, message));
+    }
 }
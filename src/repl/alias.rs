@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use super::catalog::usage_message;
+use super::Token;
+
+// Lookalikes that turn up when programs are copied out of blogs and PDFs:
+// full-width forms, angle quotes, arrows and dashes. A UTF-8 `›` or
+// `‹` read as Windows-1252 comes out as `â€º` or `â€¹`, and since `â` and
+// `€` are comments anyway, only the last character needs an alias
+const COMMON: &[(char, char)] = &[
+    ('＞', '>'),
+    ('＜', '<'),
+    ('＋', '+'),
+    ('－', '-'),
+    ('．', '.'),
+    ('，', ','),
+    ('［', '['),
+    ('］', ']'),
+    ('›', '>'),
+    ('‹', '<'),
+    ('→', '>'),
+    ('←', '<'),
+    ('−', '-'),
+    ('–', '-'),
+    ('º', '>'),
+    ('¹', '<'),
+];
+
+// Extra characters the lexer reads as one of the eight commands, with a
+// warning each time so the source can be cleaned up
+// Interpreter::set_aliases plugs them in, see Parser::aliased
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Aliases {
+    map: BTreeMap<char, char>,
+}
+
+impl Aliases {
+    pub fn new() -> Aliases {
+        Aliases::default()
+    }
+
+    // Everything in COMMON
+    pub fn common() -> Aliases {
+        Aliases {
+            map: COMMON.iter().cloned().collect(),
+        }
+    }
+
+    // to has to be one of the eight commands, and from can't be one
+    pub fn add(&mut self, from: char, to: char) -> Result<(), String> {
+        if Token::from_char(to).is_none() {
+            return Err(usage_message("alias-target", &[&to]));
+        }
+        if Token::from_char(from).is_some() {
+            return Err(usage_message("alias-taken", &[&from]));
+        }
+        self.map.insert(from, to);
+        Ok(())
+    }
+
+    // `›=>` from the command line, or `common` for all of COMMON
+    pub fn parse(&mut self, spec: &str) -> Result<(), String> {
+        if spec == "common" {
+            self.map.extend(Aliases::common().map);
+            return Ok(());
+        }
+        let mut chars = spec.chars();
+        match (chars.next(), chars.next(), chars.next(), chars.next()) {
+            (Some(from), Some('='), Some(to), None) => self.add(from, to),
+            _ => Err(usage_message("alias", &[&spec])),
+        }
+    }
+
+    // The command c stands for, if it's an alias
+    pub fn get(&self, c: char) -> Option<char> {
+        self.map.get(&c).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (char, char)> + '_ {
+        self.map.iter().map(|(&from, &to)| (from, to))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
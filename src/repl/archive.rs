@@ -0,0 +1,230 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use super::cli::RunOptions;
+use super::config::{self, Config};
+use super::error::coded;
+use super::io::IoHandler;
+use super::replay::Replay;
+use super::snapshot::Snapshot;
+use super::Interpreter;
+
+// Bump whenever the format changes, like replay::VERSION
+pub const VERSION: u32 = 1;
+
+// Everything about where one run got to, in a single file to send someone
+// who's going to help work out why: the settings it ran with, the tape as
+// it stopped, and a replay with the program, its input and what it printed
+//
+//     brainf-archive 1
+//     stopped 12
+//     read 3
+//     error Stopped after the 100 step limit
+//     config 28
+//     [run]
+//     max_steps = 100
+//     ...
+//     state 40
+//     brainf-snapshot 1
+//     ...
+//     replay 120
+//     brainf-replay 1
+//     ...
+//
+// stopped is the token index the run got to and read how many bytes of
+// the input it had used by then. error is only there when the run didn't
+// finish. config, state and replay are each followed by that many bytes,
+// the config in the config file format, and replay has to come last
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Archive {
+    pub config: String,
+    pub state: Snapshot,
+    pub replay: Replay,
+    pub stopped: usize,
+    pub read: usize,
+    pub error: Option<String>,
+}
+
+impl Archive {
+    // Run the program with options and input, and keep wherever it stops
+    pub fn record(source: &str, options: &RunOptions, input: &[u8]) -> Result<Archive, String> {
+        let mut interpreter = Interpreter::new();
+        options.configure(&mut interpreter);
+        interpreter.load_source(source).map_err(|e| coded(e.code(), e))?;
+        let counter = Counter::new(input);
+        let (output, read) = (Rc::clone(&counter.output), Rc::clone(&counter.read));
+        interpreter.set_io(Box::new(counter));
+        let result = interpreter.interpret();
+
+        let output = output.borrow().clone();
+        Ok(Archive {
+            config: config::write_run(options),
+            state: Snapshot::of(&interpreter.brain),
+            replay: Replay {
+                source: source.to_string(),
+                dialect: options.dialect.clone().unwrap_or_else(|| "brainfuck".to_string()),
+                max_steps: options.max_steps,
+                seed: 0,
+                input: input.to_vec(),
+                output,
+            },
+            stopped: interpreter.cursor(),
+            read: read.get(),
+            error: result.err().map(|e| e.to_string()),
+        })
+    }
+
+    // The settings the run had, from config
+    pub fn options(&self) -> Result<RunOptions, String> {
+        let mut config = Config::default();
+        config.read(&self.config, "the archive's config")?;
+        Ok(config.run)
+    }
+
+    // Put interpreter where the run stopped: same settings, program, tape
+    // and cursor, and the input it hadn't read yet fed to whatever io it has
+    pub fn load_into(&self, interpreter: &mut Interpreter) -> Result<(), String> {
+        self.options()?.configure(interpreter);
+        interpreter.load_source(&self.replay.source).map_err(|e| coded(e.code(), e))?;
+        self.state.restore(&mut interpreter.brain);
+        interpreter.begin_run();
+        interpreter.cursor = self.stopped.min(interpreter.tokens.len());
+        interpreter.feed(self.replay.input.get(self.read..).unwrap_or(&[]));
+        Ok(())
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut state = Vec::new();
+        self.state.write_to(&mut state)?;
+        let mut replay = Vec::new();
+        self.replay.write_to(&mut replay)?;
+
+        writeln!(w, "brainf-archive {}", VERSION)?;
+        writeln!(w, "stopped {}", self.stopped)?;
+        writeln!(w, "read {}", self.read)?;
+        if let Some(ref error) = self.error {
+            writeln!(w, "error {}", error.replace('\n', " "))?;
+        }
+        for (name, part) in [("config", self.config.as_bytes()), ("state", &state), ("replay", &replay)] {
+            writeln!(w, "{} {}", name, part.len())?;
+            w.write_all(part)?;
+            writeln!(w)?;
+        }
+        w.flush()
+    }
+
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Archive> {
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+
+        let (header, mut rest) = split_line(&bytes);
+        let version = match header.strip_prefix("brainf-archive ") {
+            Some(version) => version
+                .parse::<u32>()
+                .map_err(|_| invalid(format!("bad archive version '{}'", version)))?,
+            None => return Err(invalid(format!("not an archive, it starts '{}'", header))),
+        };
+        if version > VERSION {
+            return Err(invalid(format!(
+                "archive format version {} is newer than this brainf reads ({})",
+                version, VERSION
+            )));
+        }
+
+        let mut archive = Archive::default();
+        loop {
+            if rest.is_empty() {
+                return Err(invalid("archive has no replay".to_string()));
+            }
+            let (line, after) = split_line(rest);
+            rest = after;
+            let (key, value) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+            match key {
+                "" => (),
+                "stopped" => archive.stopped = parse(value)?,
+                "read" => archive.read = parse(value)?,
+                "error" => archive.error = Some(value.to_string()),
+                "config" | "state" | "replay" => {
+                    let len: usize = parse(value)?;
+                    let part = rest
+                        .get(..len)
+                        .ok_or_else(|| invalid(format!("archive {} is cut short", key)))?;
+                    rest = &rest[len..];
+                    match key {
+                        "config" => {
+                            archive.config = String::from_utf8(part.to_vec())
+                                .map_err(|_| invalid("archive config isn't UTF-8".to_string()))?
+                        }
+                        "state" => archive.state = Snapshot::read_from(part)?,
+                        _ => {
+                            archive.replay = Replay::read_from(part)?;
+                            break;
+                        }
+                    }
+                }
+                _ => return Err(invalid(format!("unknown archive field '{}'", key))),
+            }
+        }
+        Ok(archive)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Archive> {
+        Archive::read_from(File::open(path)?)
+    }
+}
+
+// io::Buffer, counting what's been read
+struct Counter {
+    input: VecDeque<u8>,
+    output: Rc<RefCell<Vec<u8>>>,
+    read: Rc<Cell<usize>>,
+}
+
+impl Counter {
+    fn new(input: &[u8]) -> Counter {
+        Counter {
+            input: input.iter().cloned().collect(),
+            output: Rc::new(RefCell::new(Vec::new())),
+            read: Rc::new(Cell::new(0)),
+        }
+    }
+}
+
+impl IoHandler for Counter {
+    fn read(&mut self) -> Option<u8> {
+        let byte = self.input.pop_front()?;
+        self.read.set(self.read.get() + 1);
+        Some(byte)
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.borrow_mut().push(byte);
+    }
+}
+
+// The line as text, lossily since only the parts in between can be bytes
+// that aren't UTF-8, and everything after it
+fn split_line(bytes: &[u8]) -> (String, &[u8]) {
+    let (line, rest) = match bytes.iter().position(|&b| b == b'\n') {
+        Some(i) => (&bytes[..i], &bytes[i + 1..]),
+        None => (bytes, &[][..]),
+    };
+    (String::from_utf8_lossy(line).into_owned(), rest)
+}
+
+fn parse<T: ::std::str::FromStr>(word: &str) -> io::Result<T> {
+    word.parse()
+        .map_err(|_| invalid(format!("'{}' isn't a number", word)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
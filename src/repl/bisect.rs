@@ -0,0 +1,132 @@
+// Find the step where something about a run first comes true, `cell[4]
+// != 0` or `out >= 3`, by running the program again from the start for
+// as many steps as each guess says. The step count doubles until the
+// condition holds, then halves back down to the instruction that did it
+//
+// Every guess is a fresh run with the same input, so the program has to
+// go the same way each time, which plain brainfuck always does. When the
+// condition turns true, false and true again this finds one of those
+// times rather than the first
+use super::cli::RunOptions;
+use super::condition::Condition;
+use super::error::{position, RuntimeError};
+use super::io::Buffer;
+use super::Interpreter;
+
+// Where the condition came true: after step steps, the last of them
+// being token cursor at byte offset in source
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bisection {
+    pub step: u64,
+    pub cursor: usize,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub instruction: char,
+    // How many runs it took to get there
+    pub runs: u32,
+}
+
+// Why nothing was found
+#[derive(Debug)]
+pub enum Never {
+    // It holds before anything has run
+    AtStart,
+    // The program ended after this many steps without it holding
+    Finished(u64),
+    // Or the run stopped on its own
+    Stopped(RuntimeError),
+}
+
+// Bisect source on input for the first step after which condition holds,
+// the source already known to load. options.max_steps caps the search
+pub fn bisect(
+    source: &str,
+    options: &RunOptions,
+    input: &[u8],
+    condition: &Condition,
+) -> Result<Bisection, Never> {
+    let mut runs = 0;
+    let mut probe = |steps: u64| {
+        runs += 1;
+        Probe::run(source, options, input, condition, steps)
+    };
+    if probe(0).holds {
+        return Err(Never::AtStart);
+    }
+
+    // Double until it holds, the program ends or the limit's reached
+    let (mut lo, mut hi) = (0, 1);
+    loop {
+        if let Some(max) = options.max_steps {
+            hi = hi.min(max);
+        }
+        let at = probe(hi);
+        if at.holds {
+            break;
+        }
+        if let Some(e) = at.error {
+            return Err(Never::Stopped(e));
+        }
+        if at.ran < hi || options.max_steps == Some(hi) {
+            return Err(Never::Finished(at.ran));
+        }
+        lo = hi;
+        hi *= 2;
+    }
+
+    // It doesn't hold after lo steps and does after hi
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if probe(mid).holds {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    // Paused just before the step that made it true
+    let before = Probe::run(source, options, input, condition, lo);
+    runs += 1;
+    let offset = before.offset;
+    let (line, column) = position(source, offset);
+    Ok(Bisection {
+        step: hi,
+        cursor: before.cursor,
+        offset,
+        line,
+        column,
+        instruction: source[offset..].chars().next().unwrap_or(' '),
+        runs,
+    })
+}
+
+// One guess
+struct Probe {
+    holds: bool,
+    ran: u64,
+    cursor: usize,
+    offset: usize,
+    error: Option<RuntimeError>,
+}
+
+impl Probe {
+    fn run(source: &str, options: &RunOptions, input: &[u8], condition: &Condition, steps: u64) -> Probe {
+        let mut interpreter = Interpreter::new();
+        options.configure(&mut interpreter);
+        // The search takes care of max_steps itself
+        interpreter.set_max_steps(None);
+        interpreter
+            .load_source(source)
+            .expect("the program is checked before bisecting");
+        interpreter.set_io(Box::new(Buffer::new(input)));
+        interpreter.begin_run();
+        let error = interpreter.step(steps).err();
+        Probe {
+            holds: condition.eval(&interpreter.brain, interpreter.output_count),
+            ran: interpreter.steps(),
+            cursor: interpreter.cursor(),
+            offset: interpreter.source_offset(),
+            error,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+// The wording of every diagnostic that has a code, so a front end like a
+// web playground or an editor plugin can put them in its own words or
+// language and still get the same codes, offsets and exit statuses. Set one
+// with set_catalog and Display, message() and the JSON all go through it.
+// Anything a catalog doesn't override stays English
+//
+//     struct Terse;
+//     impl Catalog for Terse {
+//         fn runtime_error(&self, error: &RuntimeError) -> String {
+//             error.code().to_string()
+//         }
+//     }
+//     catalog::set_catalog(Some(Box::new(Terse)));
+use std::fmt;
+use std::sync::{PoisonError, RwLock};
+
+use super::error::{ParseError, RuntimeError, CODE_UNDERFLOW};
+use super::format::thousands;
+
+pub trait Catalog: Send + Sync {
+    // Without saying where, for when the position is shown some other way,
+    // e.g. "Unclosed '['"
+    fn parse_message(&self, error: &ParseError) -> String {
+        English::parse_message(error)
+    }
+
+    // Saying where, e.g. "Unclosed '[' at offset 4"
+    fn parse_error(&self, error: &ParseError) -> String {
+        English::parse_error(error)
+    }
+
+    fn runtime_error(&self, error: &RuntimeError) -> String {
+        English::runtime_error(error)
+    }
+
+    // A check warning by its code, see error::CODE_UNDERFLOW
+    fn warning(&self, code: &str) -> String {
+        English::warning(code)
+    }
+
+    // error::CODE_READ, what being a path or "stdin" and error why, e.g.
+    // "Couldn't read prog.bf: No such file or directory (os error 2)"
+    fn read_error(&self, what: &str, error: &str) -> String {
+        English::read_error(what, error)
+    }
+
+    // error::CODE_WRITE, the same for a file or output
+    fn write_error(&self, what: &str, error: &str) -> String {
+        English::write_error(what, error)
+    }
+
+    // What follows an error::CODE_USAGE, how a subcommand goes, e.g.
+    // "Usage: brainf pair <first.bf> <second.bf>"
+    fn usage(&self, usage: &str) -> String {
+        English::usage(usage)
+    }
+
+    // Everything else that's an error::CODE_USAGE, a bad flag or a REPL
+    // command that doesn't make sense, by its key in USAGE_MESSAGES with
+    // what goes in each {}, e.g. ("flag-needs-value", ["--max-steps"])
+    fn usage_message(&self, key: &str, args: &[String]) -> String {
+        English::usage_message(key, args)
+    }
+}
+
+// The English for each usage_message key, {} filled in order
+pub const USAGE_MESSAGES: &[(&str, &str)] = &[
+    // Flags
+    ("flag-takes-no-value", "{} doesn't take a value"),
+    ("flag-needs-value", "{} needs a value"),
+    ("flag-needs-number", "{} needs a number, not '{}'"),
+    ("unknown-flag", "Unknown flag '{}'"),
+    ("unknown-subcommand", "No subcommand called '{}', `brainf help` lists them"),
+    ("error-format", "--error-format takes text or json, not '{}'"),
+    ("exit-cell", "--exit-cell takes 'current' or a cell number, not '{}'"),
+    ("eof", "--eof takes unchanged, 0 or 255, not '{}'"),
+    ("radix", "'{}' isn't a radix, try 10, 16, 8 or 2"),
+    ("range", "'{}' isn't a range like 0..16"),
+    ("alias", "'{}' isn't an alias, try something like '›=>'"),
+    ("alias-target", "'{}' isn't a brainfuck command"),
+    ("alias-taken", "'{}' is already a brainfuck command"),
+    ("loop-quota", "'{}' isn't a loop quota, try something like '12=1000'"),
+    ("input-and-input-file", "--input and --input-file don't go together"),
+    ("input-and-bang-input", "--input and input after a `!` in the program don't go together"),
+    ("files-and-programs", "--output-file, --tee and --transcript only go with one program"),
+    ("watch-without-files", "--watch needs program files to watch"),
+    ("interactive-without-file", "--interactive needs a single program that isn't read from stdin"),
+    ("percentages", "--io and --loops are percentages, 0 to 100"),
+    ("when", "--when {}: {}"),
+    ("no-programs", "{} has no .bf files in it"),
+    ("no-corpus", "batch needs brainf built with --features corpus"),
+    ("unknown-dialect", "No dialect called '{}', there's {}"),
+    ("no-loop-at", "No loop starts at offset {}"),
+    // REPL commands
+    ("unknown-command", "Unknown command ':{}', try :help"),
+    ("command-usage", "Usage: {}"),
+    ("not-a-cell-count", "'{}' isn't a cell count"),
+    ("not-a-step", "'{}' isn't a step"),
+    ("not-a-step-count", "'{}' isn't a step count"),
+    ("not-a-token-index", "'{}' isn't a token index"),
+    ("not-a-source-offset", "'{}' isn't a source offset"),
+    ("not-a-byte-count", "'{}' isn't a byte count"),
+    ("not-a-byte-value", "'{}' isn't a byte value"),
+    ("not-a-watch-number", "'{}' isn't a watch number"),
+    ("not-an-iteration-count", "'{}' isn't an iteration count"),
+    ("too-big-for-a-cell", "'{}' doesn't fit in a cell"),
+    ("empty-range", "Empty range '{}'"),
+    ("nothing-loaded", "Nothing loaded from {}"),
+    ("nothing-to-save", "Nothing to save, evaluate some code first"),
+    ("nothing-to-run", "Nothing to run, evaluate some code first"),
+    ("trace-too-short", "The last run only took {} steps"),
+    ("no-savepoint", "No savepoint called '{}'"),
+    ("no-watch", "There's no watch {}"),
+    ("no-bracket-at", "No bracket at offset {}"),
+    ("not-a-bracket", "Token #{} isn't a bracket"),
+    ("not-a-loop-start", "Token #{} doesn't start a loop"),
+    ("no-code-at", "No code at or after offset {}"),
+    ("no-token", "There is no token #{}"),
+    ("loop-without-source", "That loop has no source to show"),
+    ("not-in-a-loop", "The cursor isn't in a loop"),
+];
+
+// What everything says unless told otherwise
+#[derive(Copy, Clone, Debug, Default)]
+pub struct English;
+
+impl Catalog for English {}
+
+impl English {
+    fn parse_message(error: &ParseError) -> String {
+        match *error {
+            ParseError::UnbalancedClose(_) => "Unbalanced ']'".to_string(),
+            ParseError::Unclosed(_) => "Unclosed '['".to_string(),
+            ParseError::NotCode(_, c) => format!("'{}' isn't code", c.escape_debug()),
+        }
+    }
+
+    fn parse_error(error: &ParseError) -> String {
+        match *error {
+            ParseError::UnbalancedClose(offset) => format!("Unbalanced ']' at offset {}", offset),
+            ParseError::Unclosed(offset) => format!("Unclosed '[' at offset {}", offset),
+            ParseError::NotCode(offset, c) => format!("'{}' at offset {} isn't code", c.escape_debug(), offset),
+        }
+    }
+
+    fn runtime_error(error: &RuntimeError) -> String {
+        match *error {
+            RuntimeError::StepLimit(max) => format!("Stopped after the {} step limit", thousands(max as usize)),
+            RuntimeError::Stalled(quiet) => format!(
+                "Nothing was written and no cell changed in {} steps, it looks stuck",
+                thousands(quiet as usize)
+            ),
+            RuntimeError::LoopQuota { offset, limit } => format!(
+                "The loop at offset {} used up its {} iterations",
+                offset,
+                thousands(limit as usize)
+            ),
+            RuntimeError::Io(ref e) => format!("Couldn't write output: {}", e),
+            RuntimeError::Deadlock => "Waiting on a channel that nothing is going to send to or take from".to_string(),
+            RuntimeError::PointerUnderflow => "Moved the pointer left of cell 0".to_string(),
+        }
+    }
+
+    fn warning(code: &str) -> String {
+        match code {
+            CODE_UNDERFLOW => "This always moves the pointer left of cell 0".to_string(),
+            _ => format!("Warning {}", code),
+        }
+    }
+
+    fn read_error(what: &str, error: &str) -> String {
+        format!("Couldn't read {}: {}", what, error)
+    }
+
+    fn write_error(what: &str, error: &str) -> String {
+        format!("Couldn't write {}: {}", what, error)
+    }
+
+    fn usage(usage: &str) -> String {
+        format!("Usage: brainf {}", usage)
+    }
+
+    fn usage_message(key: &str, args: &[String]) -> String {
+        let template = match USAGE_MESSAGES.iter().find(|&&(k, _)| k == key) {
+            Some(&(_, template)) => template,
+            None => return format!("{} {}", key, args.join(" ")),
+        };
+        let mut args = args.iter();
+        let mut parts = template.split("{}");
+        let mut message = parts.next().unwrap_or("").to_string();
+        for part in parts {
+            message.push_str(args.next().map_or("", String::as_str));
+            message.push_str(part);
+        }
+        message
+    }
+}
+
+static CATALOG: RwLock<Option<Box<dyn Catalog>>> = RwLock::new(None);
+
+// For the whole process, None goes back to English
+pub fn set_catalog(catalog: Option<Box<dyn Catalog>>) {
+    *CATALOG.write().unwrap_or_else(PoisonError::into_inner) = catalog;
+}
+
+// A usage_message from whichever catalog is set
+pub fn usage_message(key: &str, args: &[&dyn fmt::Display]) -> String {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    with_catalog(|catalog| catalog.usage_message(key, &args))
+}
+
+// f with whichever catalog is set
+pub fn with_catalog<T, F: FnOnce(&dyn Catalog) -> T>(f: F) -> T {
+    let catalog = CATALOG.read().unwrap_or_else(PoisonError::into_inner);
+    match *catalog {
+        Some(ref catalog) => f(&**catalog),
+        None => f(&English),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{English, USAGE_MESSAGES};
+
+    fn fill(key: &str, args: &[&str]) -> String {
+        English::usage_message(key, &args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn usage_messages_fill_in_order() {
+        assert_eq!(fill("flag-needs-number", &["--max-steps", "lots"]), "--max-steps needs a number, not 'lots'");
+        assert_eq!(fill("nothing-to-run", &[]), "Nothing to run, evaluate some code first");
+        // A catalog that lost a key still says something
+        assert_eq!(fill("no-such-key", &["x"]), "no-such-key x");
+    }
+
+    #[test]
+    fn usage_message_keys_are_unique() {
+        for (i, &(key, _)) in USAGE_MESSAGES.iter().enumerate() {
+            assert!(USAGE_MESSAGES[i + 1..].iter().all(|&(other, _)| other != key), "{} twice", key);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// Two machines in one process trading bytes like coroutines: brainfuck
+// plus `^`, which sends the current cell to the other machine, and `~`,
+// which takes the next byte it sent into the current cell. A machine runs
+// until it has to wait, on `~` with nothing sent yet or `^` with the
+// other's queue full, then the other one gets a turn
+//
+//     ++++++++[>++++++++<-]>+ ^          sends 'A'
+//     ~ .                                prints it
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use super::cli::RunOptions;
+use super::dialect::Dialect;
+use super::error::RuntimeError;
+use super::io::IoHandler;
+use super::{Brain, Interpreter, Token};
+
+pub const SEND: char = '^';
+pub const RECEIVE: char = '~';
+
+// Bytes sent and not yet taken before `^` has to wait
+pub const CAPACITY: usize = 64;
+
+type Queue = Rc<RefCell<VecDeque<u8>>>;
+
+// One machine's end of the pair
+#[derive(Debug, Default)]
+pub struct Channels {
+    inbox: Queue,
+    outbox: Queue,
+}
+
+impl Channels {
+    // Whether the instruction the machine is at would have to wait
+    fn blocked(&self, interpreter: &Interpreter) -> bool {
+        match interpreter.tokens.get(interpreter.cursor) {
+            Some(&Token::Extension(SEND)) => self.outbox.borrow().len() >= CAPACITY,
+            Some(&Token::Extension(RECEIVE)) => self.inbox.borrow().is_empty(),
+            _ => false,
+        }
+    }
+}
+
+impl Dialect for Channels {
+    fn name(&self) -> &str {
+        "channels"
+    }
+
+    fn token(&self, c: char) -> Option<Token> {
+        match c {
+            SEND | RECEIVE => Some(Token::Extension(c)),
+            _ => Token::from_char(c),
+        }
+    }
+
+    // Only ever called when blocked() said it could go ahead
+    fn execute(&self, c: char, brain: &mut Brain, _io: &mut dyn IoHandler) {
+        if c == SEND {
+            self.outbox.borrow_mut().push_back(brain.current());
+        } else if let Some(byte) = self.inbox.borrow_mut().pop_front() {
+            brain.set_current(byte);
+        }
+    }
+}
+
+// The two machines and the queues between them. Set each one up through
+// machine() and load its program, then run() takes turns until both stop
+pub struct Pair {
+    machines: [Interpreter; 2],
+    ends: [Rc<Channels>; 2],
+}
+
+impl Default for Pair {
+    fn default() -> Pair {
+        Pair::new()
+    }
+}
+
+impl Pair {
+    pub fn new() -> Pair {
+        let (a, b) = (Queue::default(), Queue::default());
+        let ends = [
+            Rc::new(Channels {
+                inbox: Rc::clone(&a),
+                outbox: Rc::clone(&b),
+            }),
+            Rc::new(Channels { inbox: b, outbox: a }),
+        ];
+        let mut machines = [Interpreter::new(), Interpreter::new()];
+        for (machine, end) in machines.iter_mut().zip(&ends) {
+            machine.set_dialect(Some(Rc::clone(end) as Rc<dyn Dialect>));
+        }
+        Pair { machines, ends }
+    }
+
+    // Anything but set_dialect, which would cut it off from the other one
+    pub fn machine(&mut self, i: usize) -> &mut Interpreter {
+        &mut self.machines[i]
+    }
+
+    // Both machines set up the way options says, except for the dialect
+    pub fn configure(&mut self, options: &RunOptions) {
+        for (machine, end) in self.machines.iter_mut().zip(&self.ends) {
+            options.configure(machine);
+            machine.set_dialect(Some(Rc::clone(end) as Rc<dyn Dialect>));
+        }
+    }
+
+    // Run both from the start until each one finishes or fails, each
+    // machine's max_steps counting all of its turns. When both are waiting
+    // on each other, or one waits on one that's stopped, the waiting ones
+    // fail with RuntimeError::Deadlock
+    pub fn run(&mut self) -> [Result<(), RuntimeError>; 2] {
+        let mut results = [None, None];
+        let mut used = [0u64; 2];
+        let limits = [self.machines[0].max_steps, self.machines[1].max_steps];
+        for machine in &mut self.machines {
+            machine.begin_run();
+        }
+        while results.iter().any(Option::is_none) {
+            let mut moved = false;
+            for i in 0..2 {
+                if results[i].is_some() {
+                    continue;
+                }
+                let (machine, end) = (&mut self.machines[i], &self.ends[i]);
+                machine.set_max_steps(limits[i].map(|max| max - used[i]));
+                let result = machine.run_while(|machine| !end.blocked(machine));
+                used[i] += machine.steps();
+                moved |= machine.steps() > 0;
+                match result {
+                    Err(RuntimeError::StepLimit(_)) => results[i] = Some(Err(RuntimeError::StepLimit(used[i]))),
+                    Err(e) => results[i] = Some(Err(e)),
+                    Ok(()) if machine.is_finished() => results[i] = Some(Ok(())),
+                    Ok(()) => (),
+                }
+            }
+            if !moved {
+                for result in results.iter_mut().filter(|result| result.is_none()) {
+                    *result = Some(Err(RuntimeError::Deadlock));
+                }
+            }
+        }
+        for (machine, max) in self.machines.iter_mut().zip(limits) {
+            machine.set_max_steps(max);
+        }
+        results.map(Option::unwrap)
+    }
+}
@@ -0,0 +1,162 @@
+use super::catalog::with_catalog;
+use super::error::{ParseError, CODE_UNDERFLOW};
+use super::{Interpreter, Token};
+
+// Every lint by name, with its code
+pub const LINTS: &[(&str, &str)] = &[("underflow", CODE_UNDERFLOW)];
+
+// "warnings" stands for whichever lints would warn, like rustc's -D warnings
+pub const ALL_WARNINGS: &str = "warnings";
+
+// What a lint does when it finds something, the way compilers do it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    // Still reported, but check fails
+    Deny,
+}
+
+impl Level {
+    pub fn parse(text: &str) -> Result<Level, String> {
+        match text {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(format!("'{}' isn't allow, warn or deny", text)),
+        }
+    }
+}
+
+// Levels set from the command line or the [lints] section of the config,
+// later settings winning. A lint nobody set keeps its own default
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lints {
+    // By code
+    levels: Vec<(&'static str, Level)>,
+    warnings: Option<Level>,
+}
+
+impl Lints {
+    // lint is a name like underflow, a code like W0101 or "warnings"
+    pub fn set(&mut self, lint: &str, level: Level) -> Result<(), String> {
+        if lint == ALL_WARNINGS {
+            self.warnings = Some(level);
+            return Ok(());
+        }
+        let code = LINTS
+            .iter()
+            .find(|&&(name, code)| name == lint || code == lint)
+            .map(|&(_, code)| code)
+            .ok_or_else(|| format!("There's no lint called '{}'", lint))?;
+        self.levels.retain(|&(c, _)| c != code);
+        self.levels.push((code, level));
+        Ok(())
+    }
+
+    // What the lint with code comes to, default when it wasn't set
+    pub fn level(&self, code: &str, default: Level) -> Level {
+        let level = self
+            .levels
+            .iter()
+            .find(|&&(c, _)| c == code)
+            .map_or(default, |&(_, level)| level);
+        match (level, self.warnings) {
+            (Level::Warn, Some(warnings)) => warnings,
+            _ => level,
+        }
+    }
+}
+
+// Something a program is sure to do wrong, found without running it
+// offset is a byte offset into the source
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub offset: usize,
+    pub code: &'static str,
+    pub message: String,
+    // Warn or Deny, allowed lints aren't reported at all
+    pub level: Level,
+}
+
+// Brackets have to balance or it's an error. strict turns on the lints for
+// a fixed tape that starts at cell 0, where moving left of it is a fault,
+// unless lints says otherwise. strict_comments makes anything but code and
+// whitespace an error too. Errors come back all at once rather than
+// stopping at the first
+pub fn check(
+    source: &str,
+    strict: bool,
+    strict_comments: bool,
+    lints: &Lints,
+) -> Result<Vec<Warning>, Vec<ParseError>> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_strict_comments(strict_comments);
+    let errors = interpreter.parse_errors(source);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    // Can't fail now
+    interpreter.load_source(source).map_err(|_| errors)?;
+    let mut warnings = Vec::new();
+    let underflow_level = lints.level(CODE_UNDERFLOW, if strict { Level::Warn } else { Level::Allow });
+    if underflow_level != Level::Allow {
+        warnings.extend(underflow(interpreter.tokens(), interpreter.spans(), underflow_level));
+    }
+    Ok(warnings)
+}
+
+// Follow the pointer through the code that's certain to run: top level
+// straight-line code, and loops that always put the pointer back where
+// they found it. Anything else loses track of where the pointer is
+fn underflow(tokens: &[Token], spans: &[usize], level: Level) -> Option<Warning> {
+    let mut ptr: isize = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::PointerIncrement => ptr += 1,
+            Token::PointerDecrement => {
+                ptr -= 1;
+                if ptr < 0 {
+                    return Some(Warning {
+                        offset: spans[i],
+                        code: CODE_UNDERFLOW,
+                        level,
+                        message: with_catalog(|catalog| catalog.warning(CODE_UNDERFLOW)),
+                    });
+                }
+            }
+            Token::JumpForward(close) => {
+                if shift(tokens, i, close) != Some(0) {
+                    return None;
+                }
+                i = close;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+// How far one pass through the loop body moves the pointer, if that's the
+// same every time
+fn shift(tokens: &[Token], open: usize, close: usize) -> Option<isize> {
+    let mut net = 0;
+    let mut i = open + 1;
+    while i < close {
+        match tokens[i] {
+            Token::PointerIncrement => net += 1,
+            Token::PointerDecrement => net -= 1,
+            Token::JumpForward(inner) => {
+                if shift(tokens, i, inner) != Some(0) {
+                    return None;
+                }
+                i = inner;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    Some(net)
+}
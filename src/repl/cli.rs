@@ -0,0 +1,370 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use super::alias::Aliases;
+use super::catalog::usage_message;
+use super::dialect::Registry;
+use super::error;
+use super::format::{Radix, RangeFormatter};
+use super::io::{Eof, IoHandler, Sink, Split, Tee, Transcript};
+use super::recent::Recent;
+use super::Interpreter;
+
+// A command line split into flags and everything else. Each subcommand
+// says which flags it knows, switches on their own and the rest with a
+// value as `--flag value` or `--flag=value`. `--` ends the flags and a
+// lone `-` is an ordinary argument
+#[derive(Debug, Default)]
+pub struct Args {
+    pub positional: Vec<String>,
+    flags: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse(args: &[String], switches: &[&str], values: &[&str]) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positional.extend(args.cloned());
+                break;
+            }
+            if !arg.starts_with('-') || arg == "-" {
+                parsed.positional.push(arg.clone());
+                continue;
+            }
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if switches.contains(&name) {
+                if value.is_some() {
+                    return Err(usage_message("flag-takes-no-value", &[&name]));
+                }
+                parsed.flags.push((name.to_string(), None));
+            } else if values.contains(&name) {
+                match value.or_else(|| args.next().cloned()) {
+                    Some(value) => parsed.flags.push((name.to_string(), Some(value))),
+                    None => return Err(usage_message("flag-needs-value", &[&name])),
+                }
+            } else {
+                return Err(usage_message("unknown-flag", &[&name]));
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|(flag, _)| flag == name)
+    }
+
+    // The last one wins if a flag is given more than once
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(flag, _)| flag == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    // Each of several flags with its value, in the order they were given,
+    // for ones that override each other like --allow and --deny
+    pub fn values_of<'a>(&'a self, names: &'a [&str]) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.flags
+            .iter()
+            .filter(move |(flag, _)| names.contains(&flag.as_str()))
+            .filter_map(|(flag, value)| Some((flag.as_str(), value.as_deref()?)))
+    }
+
+    // Every value of a flag that can be repeated, in order
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.flags
+            .iter()
+            .filter(move |(flag, _)| flag == name)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    pub fn number<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.value(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| usage_message("flag-needs-number", &[&name, &value])),
+            None => Ok(None),
+        }
+    }
+}
+
+// The flags `run` takes, the REPL and `record` take some of them too
+// `-e <code>` is the program itself rather than how to run it, so it
+// isn't in RunOptions, and neither are `--watch` and `-i`. --no-config skips the
+// config files, see config::Config
+pub const RUN_SWITCHES: &[&str] = &["--strict-comments", "--no-config", "--watch", "-i", "--interactive", "--show-tape", "--bang-input", "--time", "--no-emoji", "--no-color", "--fix-brackets", "--strict"];
+pub const RUN_VALUES: &[&str] = &[
+    "-e",
+    "--eval",
+    "--max-steps",
+    "--capacity-cap",
+    "--input",
+    "--input-file",
+    "--eof",
+    "--dialect",
+    "--alias",
+    "--watchdog",
+    "--history",
+    "--loop-quota",
+    "--output-file",
+    "--tee",
+    "--transcript",
+    "--exit-cell",
+    "--error-format",
+    "--max-download",
+    "--tape-range",
+    "--tape-radix",
+];
+
+// How to run a program, shared by every subcommand that runs one so they
+// all read the flags the same way. The config files fill one in too
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub max_steps: Option<u64>,
+    // Cells worth of tape memory kept between runs, see Brain::set_capacity_cap
+    pub capacity_cap: Option<usize>,
+    pub input: Option<Vec<u8>>,
+    pub input_file: Option<PathBuf>,
+    pub eof: Eof,
+    pub dialect: Option<String>,
+    pub aliases: Aliases,
+    pub strict_comments: bool,
+    // `<` on cell 0 is a fault, see Interpreter::set_strict
+    pub strict: bool,
+    // Input after the program's first `!`, see Interpreter::split_input
+    pub bang_input: bool,
+    pub watchdog: Option<u64>,
+    // Instructions to remember for error reports, see recent::Recent
+    pub history: Option<usize>,
+    // Byte offset of a `[` and how many iterations it gets
+    pub loop_quotas: Vec<(usize, u64)>,
+    // Where output goes instead, as raw bytes
+    pub output_file: Option<PathBuf>,
+    pub tees: Vec<PathBuf>,
+    pub transcript: Option<PathBuf>,
+    pub exit_cell: Option<ExitCell>,
+    pub error_format: ErrorFormat,
+    // Bytes a program given as a URL can be, see fetch::MAX_DOWNLOAD
+    pub max_download: Option<usize>,
+    // How to print the tape once the program stops, for --show-tape
+    pub show_tape: Option<RangeFormatter>,
+    // Time and RunStats on stderr once the program stops
+    pub time: bool,
+    // Close brackets a program left open at the end, see
+    // Interpreter::fix_brackets
+    pub fix_brackets: bool,
+}
+
+// How a subcommand reports what went wrong on stderr
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ErrorFormat {
+    // `program: message` lines for people
+    #[default]
+    Text,
+    // A JSON object a line for editors and CI, see error::to_json
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn parse(name: &str) -> Result<ErrorFormat, String> {
+        match name {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(usage_message("error-format", &[&name])),
+        }
+    }
+}
+
+// A cell whose value at the end becomes the exit status, the way a lot of
+// brainfuck test harnesses expect
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExitCell {
+    // Wherever the pointer finished
+    Current,
+    Cell(usize),
+}
+
+impl ExitCell {
+    // `--exit-cell current` or `--exit-cell 0`
+    pub fn parse(text: &str) -> Result<ExitCell, String> {
+        match text {
+            "current" => Ok(ExitCell::Current),
+            n => n
+                .parse()
+                .map(ExitCell::Cell)
+                .map_err(|_| usage_message("exit-cell", &[&n])),
+        }
+    }
+
+    pub fn value(&self, interpreter: &Interpreter) -> u8 {
+        match *self {
+            ExitCell::Current => interpreter.brain.current(),
+            ExitCell::Cell(i) => interpreter.brain.get(i),
+        }
+    }
+}
+
+impl RunOptions {
+    // Flags a subcommand doesn't take just never show up in args
+    pub fn from_args(args: &Args) -> Result<RunOptions, String> {
+        RunOptions::default().with_args(args)
+    }
+
+    // Flags on top of these options, from the config files say. A flag
+    // replaces the setting, except aliases which add to the ones there
+    pub fn with_args(self, args: &Args) -> Result<RunOptions, String> {
+        let mut options = RunOptions {
+            max_steps: args.number("--max-steps")?.or(self.max_steps),
+            capacity_cap: args.number("--capacity-cap")?.or(self.capacity_cap),
+            input: args.value("--input").map(|text| text.as_bytes().to_vec()),
+            input_file: args.value("--input-file").map(PathBuf::from),
+            eof: match args.value("--eof") {
+                Some(text) => Eof::parse(text)?,
+                None => self.eof,
+            },
+            dialect: args.value("--dialect").map(String::from).or(self.dialect),
+            strict_comments: args.has("--strict-comments") || self.strict_comments,
+            strict: args.has("--strict"),
+            bang_input: args.has("--bang-input") || self.bang_input,
+            watchdog: args.number("--watchdog")?.or(self.watchdog),
+            history: args.number("--history")?.or(self.history),
+            output_file: args.value("--output-file").map(PathBuf::from),
+            tees: args.values("--tee").map(PathBuf::from).collect(),
+            transcript: args.value("--transcript").map(PathBuf::from),
+            aliases: self.aliases,
+            loop_quotas: Vec::new(),
+            exit_cell: args.value("--exit-cell").map(ExitCell::parse).transpose()?,
+            error_format: match args.value("--error-format") {
+                Some(name) => ErrorFormat::parse(name)?,
+                None => self.error_format,
+            },
+            max_download: args.number("--max-download")?.or(self.max_download),
+            show_tape: show_tape(args)?,
+            time: args.has("--time"),
+            fix_brackets: args.has("--fix-brackets"),
+        };
+        if options.input.is_some() && options.input_file.is_some() {
+            return Err(usage_message("input-and-input-file", &[]));
+        }
+        if let Some(ref name) = options.dialect {
+            check_dialect(name)?;
+        }
+        for spec in args.values("--alias") {
+            options.aliases.parse(spec)?;
+        }
+        for quota in args.values("--loop-quota") {
+            options.loop_quotas.push(parse_quota(quota)?);
+        }
+        Ok(options)
+    }
+
+    // --input, or whatever is in --input-file
+    pub fn read_input(&self) -> Result<Option<Vec<u8>>, String> {
+        match self.input_file {
+            Some(ref path) => fs::read(path)
+                .map(Some)
+                .map_err(|e| error::read_error(path.display(), e)),
+            None => Ok(self.input.clone()),
+        }
+    }
+
+    // Everything that has to be set before a program is loaded
+    pub fn configure(&self, interpreter: &mut Interpreter) {
+        interpreter.set_dialect(self.dialect.as_ref().and_then(|name| Registry::new().get(name)));
+        if !self.aliases.is_empty() {
+            interpreter.set_aliases(Some(Rc::new(self.aliases.clone())));
+        }
+        interpreter.set_strict_comments(self.strict_comments);
+        interpreter.set_max_steps(self.max_steps);
+        interpreter.set_watchdog(self.watchdog);
+        interpreter.set_strict(self.strict);
+        interpreter.set_eof(self.eof);
+        // 0 is for turning off what the config files turned on
+        interpreter.set_recent(self.history.filter(|&n| n > 0).map(Recent::new));
+        if self.capacity_cap.is_some() {
+            interpreter.brain.set_capacity_cap(self.capacity_cap);
+        }
+    }
+
+    // Loop quotas go by the loaded program, each offset has to be a `[`
+    pub fn set_loop_quotas(&self, interpreter: &mut Interpreter) -> Result<(), String> {
+        for &(offset, limit) in &self.loop_quotas {
+            match interpreter.token_at(offset) {
+                Some(i) if interpreter.spans()[i] == offset && interpreter.partner(i) > Some(i) => {
+                    interpreter.set_loop_quota(offset, Some(limit))
+                }
+                _ => return Err(usage_message("no-loop-at", &[&offset])),
+            }
+        }
+        Ok(())
+    }
+
+    // Wraps io for --output-file, --tee and --transcript. Output goes to
+    // the output file if there is one and io otherwise, and a copy to each
+    // tee file, and every byte of input gets copied to the transcript
+    pub fn io(&self, io: Box<dyn IoHandler>) -> Result<Box<dyn IoHandler>, String> {
+        let mut io = io;
+        if let Some(ref path) = self.output_file {
+            let sink = Sink::create(path).map_err(|e| error::write_error(path.display(), e))?;
+            io = Box::new(Split::new(io, Box::new(sink)));
+        }
+        if !self.tees.is_empty() {
+            let mut tee = Tee::new(io);
+            for path in &self.tees {
+                let sink = Sink::create(path)
+                    .map_err(|e| error::write_error(path.display(), e))?;
+                tee.add(Box::new(sink));
+            }
+            io = Box::new(tee);
+        }
+        if let Some(ref path) = self.transcript {
+            let log = File::create(path)
+                .map_err(|e| error::write_error(path.display(), e))?;
+            io = Box::new(Transcript::new(io, BufWriter::new(log)));
+        }
+        Ok(io)
+    }
+}
+
+// --tape-range and --tape-radix say how, and either one is enough to
+// mean --show-tape
+fn show_tape(args: &Args) -> Result<Option<RangeFormatter>, String> {
+    let (range, radix) = (args.value("--tape-range"), args.value("--tape-radix"));
+    if !args.has("--show-tape") && range.is_none() && radix.is_none() {
+        return Ok(None);
+    }
+    let mut formatter = RangeFormatter::default();
+    if let Some(range) = range {
+        formatter.parse_range(range)?;
+    }
+    if let Some(radix) = radix {
+        formatter.radix = Radix::parse(radix)?;
+    }
+    Ok(Some(formatter))
+}
+
+pub fn check_dialect(name: &str) -> Result<(), String> {
+    let registry = Registry::new();
+    match registry.get(name) {
+        Some(_) => Ok(()),
+        None => Err(usage_message("unknown-dialect", &[&name, &registry.names().join(", ")])),
+    }
+}
+
+// `--loop-quota 12=1000`
+fn parse_quota(quota: &str) -> Result<(usize, u64), String> {
+    quota
+        .split_once('=')
+        .and_then(|(offset, limit)| Some((offset.parse().ok()?, limit.parse().ok()?)))
+        .ok_or_else(|| usage_message("loop-quota", &[&quota]))
+}
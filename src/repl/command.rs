@@ -0,0 +1,1277 @@
+// REPL commands are lines starting with `:` and never reach the tokenizer
+use std::fs;
+use std::rc::Rc;
+
+use super::alias::Aliases;
+use super::catalog::usage_message;
+use super::condition::Condition;
+use super::dialect::Registry;
+use super::error::{coded, position, read_error, warned, write_error, RuntimeError, CODE_ALIASED, CODE_READ, CODE_WRITE};
+use super::export::{self, Delimited};
+use super::format::{self, CompactFormatter, FullFormatter, RangeFormatter, WindowFormatter};
+use super::journal::Journal;
+use super::spec;
+use super::trace::Trace;
+use super::xcheck;
+use super::{decode_source, paint, strip_shebang, symbol_from_prompt, to_source, Interpreter, Prompt, Token};
+
+// println! for commands, whose output collects in a String for run to hand
+// back, so it can go wherever the REPL's output goes
+macro_rules! say {
+    ($out:expr, $($arg:tt)*) => {{
+        $out.push_str(&format!($($arg)*));
+        $out.push('\n');
+    }};
+}
+
+// What :help knows about a command
+// args describes each argument in order, for completion
+#[derive(Copy, Clone, Debug)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub summary: &'static str,
+    pub examples: &'static [&'static str],
+    pub args: &'static [Arg],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Arg {
+    // One of a fixed set of words
+    Words(&'static [&'static str]),
+    // A command name, like :help takes
+    Command,
+    Path,
+    // Anything goes, nothing to suggest
+    Free,
+}
+
+const ON_OFF: &[&str] = &["on", "off"];
+
+// :tokens without a range stops here
+const MAX_TOKENS: usize = 200;
+
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "help",
+        usage: ":help [command]",
+        summary: "Lists the commands, or explains one",
+        examples: &[":help", ":help find"],
+        args: &[Arg::Command],
+    },
+    CommandInfo {
+        name: "quit",
+        usage: ":quit",
+        summary: "Ends the session, so does ? on a line of its own",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "state",
+        usage: ":state [from..to | @ptr±n]",
+        summary: "Prints the tape again, or just some of it",
+        examples: &[":state", ":state 10..40", ":state @ptr±8"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "reset",
+        usage: ":reset",
+        summary: "Wipes the tape back to one zero cell and forgets the code run so far",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "load",
+        usage: ":load <file> [stage]",
+        summary: "Runs a program from a file, or stages it to run with the next code typed",
+        examples: &[":load examples/rot13.bf", ":load lib.bf stage"],
+        args: &[Arg::Path, Arg::Words(&["stage"])],
+    },
+    CommandInfo {
+        name: "save",
+        usage: ":save <file>",
+        summary: "Writes everything evaluated this session out as one program",
+        examples: &[":save session.bf"],
+        args: &[Arg::Path],
+    },
+    CommandInfo {
+        name: "find",
+        usage: ":find <value | lo..hi | lo..=hi | 'c'>",
+        summary: "Lists the cells holding a value",
+        examples: &[":find 72", ":find 60..70", ":find 'H'", ":find ' '"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "shrink",
+        usage: ":shrink",
+        summary: "Drops trailing zero cells past the pointer",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "format",
+        usage: ":format <window [limit] | full | compact>",
+        summary: "Changes how the tape is printed",
+        examples: &[":format window 16", ":format compact"],
+        args: &[Arg::Words(&["window", "full", "compact"]), Arg::Free],
+    },
+    CommandInfo {
+        name: "export",
+        usage: ":export <csv | tsv> <file>",
+        summary: "Writes the nonzero cells out as index,value,ascii rows",
+        examples: &[":export csv tape.csv"],
+        args: &[Arg::Words(&["csv", "tsv"]), Arg::Path],
+    },
+    CommandInfo {
+        name: "profile",
+        usage: ":profile [on | off]",
+        summary: "Times every loop and prints the slowest after each evaluation",
+        examples: &[":profile on", ":profile"],
+        args: &[Arg::Words(ON_OFF)],
+    },
+    CommandInfo {
+        name: "feed",
+        usage: ":feed <text | \"quoted text\">",
+        summary: "Queues input for `,` so it doesn't have to prompt",
+        examples: &[":feed hello", ":feed \"two\\nlines\\n\""],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "loops",
+        usage: ":loops",
+        summary: "Shows how many times each loop has gone round this run",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "out",
+        usage: ":out [-n]",
+        summary: "Shows what the last evaluation wrote, or the nth from last",
+        examples: &[":out", ":out -2"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "trace",
+        usage: ":trace [on | off | <step>]",
+        summary: "Records every step of each run, then shows what a given step did",
+        examples: &[":trace on", ":trace 1000"],
+        args: &[Arg::Words(ON_OFF)],
+    },
+    CommandInfo {
+        name: "journal",
+        usage: ":journal [on | off | all]",
+        summary: "Records every cell change of each run, then lists the latest ones",
+        examples: &[":journal on", ":journal", ":journal all"],
+        args: &[Arg::Words(&["on", "off", "all"])],
+    },
+    CommandInfo {
+        name: "save-state",
+        usage: ":save-state <name>",
+        summary: "Keeps the tape and pointer as they are under a name, to come back to",
+        examples: &[":save-state before_sort"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "restore-state",
+        usage: ":restore-state <name>",
+        summary: "Puts back a tape saved with :save-state and shows what that changed",
+        examples: &[":restore-state before_sort"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "states",
+        usage: ":states [drop <name>]",
+        summary: "Lists the savepoints, or forgets one",
+        examples: &[":states", ":states drop before_sort"],
+        args: &[Arg::Words(&["drop"]), Arg::Free],
+    },
+    CommandInfo {
+        name: "tokens",
+        usage: ":tokens [from..to]",
+        summary: "Lists what the code was tokenized to, with each jump's target",
+        examples: &[":tokens", ":tokens 20..40"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "match",
+        usage: ":match <offset | #token>",
+        summary: "Shows the source around a bracket with its partner highlighted",
+        examples: &[":match 3", ":match #12"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "dialect",
+        usage: ":dialect [name]",
+        summary: "Switches what language code typed from now on is read as",
+        examples: &[":dialect", ":dialect boolfuck"],
+        args: &[Arg::Words(&["brainfuck", "boolfuck", "spoon"])],
+    },
+    CommandInfo {
+        name: "alias",
+        usage: ":alias [<from> <to> | common | off]",
+        summary: "Reads another character as one of the eight commands, with a warning",
+        examples: &[":alias › >", ":alias common", ":alias"],
+        args: &[Arg::Words(&["common", "off"]), Arg::Free],
+    },
+    CommandInfo {
+        name: "debug",
+        usage: ":debug [on | off]",
+        summary: "Step mode, where evaluated code waits to be stepped through",
+        examples: &[":debug on"],
+        args: &[Arg::Words(ON_OFF)],
+    },
+    CommandInfo {
+        name: "break",
+        usage: ":break [output <count> | <condition> | off]",
+        summary: "Pauses a run once it has written count bytes, or when a condition on ptr, cell[i] and out turns true",
+        examples: &[":break output 100", ":break cell[@] > 200 && ptr == 7", ":break off"],
+        args: &[Arg::Words(&["output", "off"]), Arg::Free],
+    },
+    CommandInfo {
+        name: "watch",
+        usage: ":watch [<expression> | cell <i> | drop <n> | off]",
+        summary: "Shows an expression like :break takes whenever stepping changes it",
+        examples: &[":watch ptr", ":watch cell 3", ":watch cell[@] - 'A'", ":watch drop 1"],
+        args: &[Arg::Words(&["ptr", "cell", "drop", "off"]), Arg::Free],
+    },
+    CommandInfo {
+        name: "watchdog",
+        usage: ":watchdog [<steps> | off]",
+        summary: "Pauses a run that goes steps without output or a cell changing",
+        examples: &[":watchdog 10000000", ":watchdog off"],
+        args: &[Arg::Words(&["off"])],
+    },
+    CommandInfo {
+        name: "quota",
+        usage: ":quota [<offset | #token> <count | off> | off]",
+        summary: "Stops a run once one loop has gone round count times",
+        examples: &[":quota 12 1000", ":quota #4 off", ":quota"],
+        args: &[Arg::Words(&["off"]), Arg::Free],
+    },
+    CommandInfo {
+        name: "step",
+        usage: ":step [count]",
+        summary: "Runs one paused instruction, or count of them",
+        examples: &[":step", ":step 100", ":step 1e3"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "run-for",
+        usage: ":run-for <count>",
+        summary: "Runs exactly count paused instructions, fewer if something stops it, and says how many ran",
+        examples: &[":run-for 1e6", ":run-for 250_000"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "next",
+        usage: ":next",
+        summary: "Like :step, but a whole [...] loop counts as one step",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "finish",
+        usage: ":finish",
+        summary: "Runs until the loop around the cursor exits",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "run-to",
+        usage: ":run-to <#token | offset>",
+        summary: "Runs until a token index or source offset is reached",
+        examples: &[":run-to #12", ":run-to 40"],
+        args: &[Arg::Free],
+    },
+    CommandInfo {
+        name: "skip-loop",
+        usage: ":skip-loop",
+        summary: "Jumps past the end of the current loop without running it",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "restart-loop",
+        usage: ":restart-loop",
+        summary: "Jumps back to the start of the current loop",
+        examples: &[],
+        args: &[],
+    },
+    CommandInfo {
+        name: "continue",
+        usage: ":continue",
+        summary: "Runs the rest of the paused code",
+        examples: &[],
+        args: &[],
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+pub fn is_command(line: &str) -> bool {
+    line.starts_with(':')
+}
+
+// `:quit`, or `?` the way it's always been typed. The shell looks for it
+// before anything else since only it can end the session
+pub fn is_quit(line: &str) -> bool {
+    line == "?" || (is_command(line) && line[1..].split_whitespace().next() == Some("quit"))
+}
+
+// Commands that read or write files where the REPL runs rather than where
+// the person typing is, which a shared server shouldn't allow
+const FILE_COMMANDS: &[&str] = &["export", "load", "save"];
+
+pub fn touches_files(line: &str) -> bool {
+    is_command(line) && line[1..].split_whitespace().next().is_some_and(|name| FILE_COMMANDS.contains(&name))
+}
+
+// Run a `:command` line against the interpreter and get back what it
+// has to say, a line at a time
+pub fn run(line: &str, interpreter: &mut Interpreter) -> String {
+    let mut words = line[1..].split_whitespace();
+    let name = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+    // Everything after the name, spaces and all
+    let rest = line[1..].trim_start()[name.len()..].trim();
+
+    let mut output = String::new();
+    let result = match name {
+        "help" => help(&args, &mut output),
+        // The shell never gets this far with it
+        "quit" => Ok(()),
+        "state" => state(&args, interpreter, &mut output),
+        "reset" => {
+            interpreter.reset();
+            say!(output, "{}", interpreter.render_state());
+            Ok(())
+        }
+        "load" => load(&args, interpreter, &mut output),
+        "save" => save(&args, interpreter, &mut output),
+        "find" => find(rest, interpreter, &mut output),
+        "shrink" => shrink(interpreter, &mut output),
+        "format" => format(&args, interpreter, &mut output),
+        "export" => export(&args, interpreter, &mut output),
+        "profile" => profile(&args, interpreter, &mut output),
+        "feed" => feed(rest, interpreter, &mut output),
+        "loops" => {
+            say!(output, "{}", interpreter.render_loops());
+            Ok(())
+        }
+        "out" => out(&args, interpreter, &mut output),
+        "trace" => trace(&args, interpreter, &mut output),
+        "journal" => journal(&args, interpreter, &mut output),
+        "save-state" => save_state(&args, interpreter, &mut output),
+        "restore-state" => restore_state(&args, interpreter, &mut output),
+        "states" => states(&args, interpreter, &mut output),
+        "tokens" => tokens(&args, interpreter, &mut output),
+        "match" => match_bracket(&args, interpreter, &mut output),
+        "dialect" => dialect(&args, interpreter, &mut output),
+        "alias" => alias(&args, interpreter, &mut output),
+        "debug" => debug(&args, interpreter, &mut output),
+        "break" => breakpoint(&args, interpreter, &mut output),
+        "watch" => watch(&args, interpreter, &mut output),
+        "watchdog" => watchdog(&args, interpreter, &mut output),
+        "quota" => quota(&args, interpreter, &mut output),
+        "step" => step(&args, interpreter, &mut output),
+        "run-for" => run_for(&args, interpreter, &mut output),
+        "next" => paused(interpreter, Interpreter::step_over, &mut output),
+        "finish" => paused(interpreter, Interpreter::finish_loop, &mut output),
+        "run-to" => run_to(&args, interpreter, &mut output),
+        "skip-loop" => jump(interpreter, Interpreter::skip_loop, &mut output),
+        "restart-loop" => jump(interpreter, Interpreter::restart_loop, &mut output),
+        "continue" => paused(interpreter, |i| i.run_while(|_| true), &mut output),
+        _ => Err(usage_message("unknown-command", &[&name])),
+    };
+
+    if let Err(message) = result {
+        say!(output, "{}  {}", symbol_from_prompt(Prompt::Error), message);
+    }
+    output
+}
+
+// `:help` lists everything, `:help find` shows one command in full
+fn help(args: &[&str], out: &mut String) -> Result<(), String> {
+    match args {
+        [] => {
+            say!(out, "{}  Commands, :help <command> for more", symbol_from_prompt(Prompt::State));
+            let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+            for command in COMMANDS {
+                say!(out, "    :{:<width$}  {}", command.name, command.summary, width = width);
+            }
+        }
+        [name] => {
+            let command = lookup(name.trim_start_matches(':'))
+                .ok_or_else(|| usage_message("unknown-command", &[&name]))?;
+            say!(out, "{}  {}", symbol_from_prompt(Prompt::State), command.usage);
+            say!(out, "    {}", command.summary);
+            for example in command.examples {
+                say!(out, "    e.g. {}", example);
+            }
+        }
+        _ => return Err(usage("help")),
+    }
+    Ok(())
+}
+
+fn usage(name: &str) -> String {
+    match lookup(name) {
+        Some(command) => usage_message("command-usage", &[&command.usage]),
+        None => usage_message("unknown-command", &[&name]),
+    }
+}
+
+// `:state 10..40` prints just those cells, `:state @ptr±8` the ones around
+// the pointer, `±` or `+-`, 8 when there's no number
+fn state(args: &[&str], interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    let arg = match args {
+        [] => {
+            say!(out, "{}", interpreter.render_state());
+            return Ok(());
+        }
+        [arg] => arg,
+        _ => return Err(usage("state")),
+    };
+    let ptr = interpreter.brain.ptr();
+    let around = ["@ptr", "ptr", "@"].iter().find_map(|prefix| arg.strip_prefix(prefix));
+    let (start, end) = match around {
+        Some(rest) => {
+            let n = match rest.strip_prefix('±').or_else(|| rest.strip_prefix("+-")) {
+                Some(n) => n.parse::<usize>().map_err(|_| usage_message("not-a-cell-count", &[&n]))?,
+                None if rest.is_empty() => 8,
+                None => return Err(usage("state")),
+            };
+            (ptr.saturating_sub(n), ptr.saturating_add(n).saturating_add(1))
+        }
+        None => {
+            let mut range = RangeFormatter::default();
+            range.parse_range(arg)?;
+            let end = range.end.unwrap_or_else(|| interpreter.brain.len().max(range.start));
+            (range.start, end)
+        }
+    };
+    say!(out, "{} {}", symbol_from_prompt(Prompt::State), interpreter.brain.render_range(start, end));
+    Ok(())
+}
+
+// `:load prog.bf` evaluates a file the way a typed line is, `:load prog.bf
+// stage` only loads it so it runs before whatever gets typed next. A file
+// that doesn't parse loads nothing and gets every error's line and column
+fn load(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let (path, stage) = match args {
+        [path] => (path, false),
+        [path, "stage"] => (path, true),
+        _ => return Err(usage("load")),
+    };
+    let source = fs::read(path)
+        .map(|bytes| strip_shebang(decode_source(&bytes)))
+        .map_err(|e| coded(CODE_READ, read_error(path, e)))?;
+    let errors = interpreter.parse_errors(&source);
+    if !errors.is_empty() {
+        for e in &errors {
+            let (line, column) = position(&source, e.offset());
+            let message = coded(e.code(), e.message());
+            say!(out, "{}  {}:{}:{}: {}", symbol_from_prompt(Prompt::Error), path, line, column, message);
+        }
+        return Err(usage_message("nothing-loaded", &[&path]));
+    }
+    let tokens = interpreter.tokens().len();
+    interpreter.load_source(&source).map_err(|e| coded(e.code(), e))?;
+    for (offset, message) in interpreter.take_warnings() {
+        let (line, column) = position(&source, offset);
+        let message = warned(CODE_ALIASED, message);
+        say!(out, "{}  {}:{}:{}: {}", symbol_from_prompt(Prompt::Warning), path, line, column, message);
+    }
+    let loaded = interpreter.tokens().len() - tokens;
+    if stage {
+        say!(out, "{}  Staged {} instructions from {}", symbol_from_prompt(Prompt::State), loaded, path);
+        return Ok(());
+    }
+
+    if interpreter.step_mode() {
+        interpreter.begin_run();
+        say!(out, "{}", interpreter.render_position());
+        return Ok(());
+    }
+    if let Err(e) = interpreter.interpret() {
+        say!(out, "{}  {}", symbol_from_prompt(Prompt::Error), coded(e.code(), &e));
+        if let Some(recent) = interpreter.render_recent() {
+            say!(out, "{}", recent);
+        }
+    }
+    if !interpreter.is_finished() {
+        say!(out, "{}", interpreter.render_position());
+    }
+    say!(out, "{}", interpreter.render_state());
+    if let Some(profile) = interpreter.render_profile() {
+        say!(out, "{}", profile);
+    }
+    Ok(())
+}
+
+// `:save session.bf` writes every instruction evaluated or loaded so far as
+// plain commands, comments and all the lines typed in between left out
+fn save(args: &[&str], interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(usage("save")),
+    };
+    let tokens = interpreter.tokens();
+    if tokens.is_empty() {
+        return Err(usage_message("nothing-to-save", &[]));
+    }
+    fs::write(path, to_source(tokens, 80)).map_err(|e| coded(CODE_WRITE, write_error(path, e)))?;
+    say!(out, "{}  Wrote {} instructions to {}", symbol_from_prompt(Prompt::State), tokens.len(), path);
+    Ok(())
+}
+
+// `:find 72`, `:find 60..70`, `:find 60..=70` or `:find 'H'`
+// The whole rest of the line is the pattern, so `:find ' '` and
+// `:find 'a' .. 'z'` work
+fn find(pattern: &str, interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    if pattern.is_empty() {
+        return Err(usage("find"));
+    }
+    let (lo, hi) = parse_byte_range(pattern)?;
+    let found = interpreter.brain.find(|cell| cell >= lo && cell <= hi);
+
+    if found.is_empty() {
+        say!(out, "{}  No cells match {}", symbol_from_prompt(Prompt::State), pattern);
+    } else {
+        let indices: Vec<String> = found.iter().map(|i| i.to_string()).collect();
+        say!(out, "{}  {}", symbol_from_prompt(Prompt::State), indices.join(", "));
+    }
+    Ok(())
+}
+
+// Trim the trailing zeros off a tape that got big once
+fn shrink(interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let before = interpreter.brain.len();
+    interpreter.brain.shrink();
+    say!(
+        out,
+        "{}  Freed {} cells",
+        symbol_from_prompt(Prompt::State),
+        before - interpreter.brain.len()
+    );
+    Ok(())
+}
+
+// Pick how print_brain draws the tape
+fn format(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        ["window"] => interpreter.set_formatter(Box::new(WindowFormatter::default())),
+        ["window", limit] => {
+            let limit = limit
+                .parse()
+                .map_err(|_| usage_message("not-a-cell-count", &[&limit]))?;
+            interpreter.set_formatter(Box::new(WindowFormatter { limit }))
+        }
+        ["full"] => interpreter.set_formatter(Box::new(FullFormatter)),
+        ["compact"] => interpreter.set_formatter(Box::new(CompactFormatter)),
+        _ => return Err(usage("format")),
+    }
+    say!(out, "{}", interpreter.render_state());
+    Ok(())
+}
+
+// `:export csv tape.csv` writes the nonzero cells out for other tools
+fn export(args: &[&str], interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    let (kind, path) = match args {
+        ["csv", path] => (Delimited::Csv, path),
+        ["tsv", path] => (Delimited::Tsv, path),
+        _ => return Err(usage("export")),
+    };
+    export::export(&interpreter.brain, kind, path)
+        .map_err(|e| coded(CODE_WRITE, write_error(path, e)))?;
+    say!(out, "{}  Wrote {}", symbol_from_prompt(Prompt::State), path);
+    Ok(())
+}
+
+// `:profile on` times every loop and prints a table after each evaluation
+fn profile(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => {
+            if let Some(profile) = interpreter.render_profile() {
+                say!(out, "{}", profile);
+            }
+        }
+        ["on"] => interpreter.set_profiling(true),
+        ["off"] => interpreter.set_profiling(false),
+        _ => return Err(usage("profile")),
+    }
+    Ok(())
+}
+
+// `:feed abc` or `:feed "abc\n"`, quoted text takes the same escapes
+// as test specs
+fn feed(rest: &str, interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    if rest.is_empty() {
+        return Err(usage("feed"));
+    }
+    let bytes = if rest.starts_with('"') {
+        spec::parse_string(rest)?
+    } else {
+        rest.as_bytes().to_vec()
+    };
+    interpreter.feed(&bytes);
+    say!(out, "{}  Queued {} bytes", symbol_from_prompt(Prompt::State), bytes.len());
+    Ok(())
+}
+
+// `:out` prints the last evaluation's output as one escaped, quoted
+// string, `:out -2` the one before that
+fn out(args: &[&str], interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    let back = match args {
+        [] => 1,
+        [n] => n
+            .strip_prefix('-')
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| usage("out"))?,
+        _ => return Err(usage("out")),
+    };
+    let outputs = interpreter.outputs();
+    let output = outputs.get(back).ok_or_else(|| match outputs.len() {
+        0 => "Nothing has run yet".to_string(),
+        n => format!("Only the last {} evaluations are kept", n),
+    })?;
+    say!(out, "{}  \"{}\"", symbol_from_prompt(Prompt::State), xcheck::escape(output));
+    Ok(())
+}
+
+// `:trace 1000` shows the token, pointer and cell of step 1000 of the last run
+fn trace(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        ["on"] => interpreter.set_trace(Some(new_trace())),
+        ["off"] => interpreter.set_trace(None),
+        [] => (),
+        [step] => {
+            let step: u64 = step.parse().map_err(|_| usage_message("not-a-step", &[&step]))?;
+            let trace = interpreter.trace().ok_or("Tracing is off, try :trace on")?;
+            let entry = trace
+                .get(step)
+                .ok_or_else(|| usage_message("trace-too-short", &[&trace.len()]))?;
+            let token = interpreter.tokens()[entry.cursor];
+            say!(
+                out,
+                "{}  Step {}: #{} '{}', pointer at {} holding {}",
+                symbol_from_prompt(Prompt::State),
+                step,
+                entry.cursor,
+                token,
+                entry.ptr,
+                entry.cell
+            );
+            return Ok(());
+        }
+        _ => return Err(usage("trace")),
+    }
+    match interpreter.trace() {
+        Some(trace) => say!(
+            out,
+            "{}  Tracing, {} steps in {} bytes",
+            symbol_from_prompt(Prompt::State),
+            format::thousands(trace.len() as usize),
+            format::thousands(trace.size())
+        ),
+        None => say!(out, "{}  Tracing is off", symbol_from_prompt(Prompt::State)),
+    }
+    Ok(())
+}
+
+// How many of the latest writes :journal shows without `all`
+const JOURNAL_TAIL: usize = 20;
+
+// `:journal` lists the last cell writes of the last run as step, cell,
+// old -> new
+fn journal(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let all = match args {
+        ["on"] => {
+            interpreter.set_journal(Some(Journal::new()));
+            say!(out, "{}  Journaling cell changes", symbol_from_prompt(Prompt::State));
+            return Ok(());
+        }
+        ["off"] => {
+            interpreter.set_journal(None);
+            say!(out, "{}  Journal is off", symbol_from_prompt(Prompt::State));
+            return Ok(());
+        }
+        [] => false,
+        ["all"] => true,
+        _ => return Err(usage("journal")),
+    };
+    let journal = interpreter.journal().ok_or("The journal is off, try :journal on")?;
+    let writes = journal.writes();
+    if writes.is_empty() {
+        say!(out, "{}  The last run changed no cells", symbol_from_prompt(Prompt::State));
+        return Ok(());
+    }
+
+    let skip = if all { 0 } else { writes.len().saturating_sub(JOURNAL_TAIL) };
+    say!(
+        out,
+        "{}  {} writes in {} steps{}",
+        symbol_from_prompt(Prompt::State),
+        format::thousands(writes.len()),
+        format::thousands(journal.steps() as usize),
+        if skip > 0 { format!(", the last {}", writes.len() - skip) } else { String::new() }
+    );
+    for write in &writes[skip..] {
+        say!(
+            out,
+            "    step {:>8}  cell {:>6}  {:>3} -> {}",
+            write.step, write.index, write.old, write.new
+        );
+    }
+    Ok(())
+}
+
+// `:save-state before_sort`, then `:restore-state before_sort` as often
+// as there are other ideas to try from there
+fn save_state(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let name = match args {
+        [name] => name,
+        _ => return Err(usage("save-state")),
+    };
+    interpreter.save_state(name)?;
+    say!(out, "{}  Saved the tape as '{}'", symbol_from_prompt(Prompt::State), name);
+    Ok(())
+}
+
+fn restore_state(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let name = match args {
+        [name] => name,
+        _ => return Err(usage("restore-state")),
+    };
+    let changes = interpreter.restore_state(name)?;
+    say!(out, "{}  Back to '{}'", symbol_from_prompt(Prompt::State), name);
+    for line in changes.lines() {
+        say!(out, "    {}", line);
+    }
+    say!(out, "{}", interpreter.render_state());
+    Ok(())
+}
+
+fn states(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => (),
+        ["drop", name] => {
+            if !interpreter.drop_state(name) {
+                return Err(usage_message("no-savepoint", &[&name]));
+            }
+            say!(out, "{}  Dropped '{}'", symbol_from_prompt(Prompt::State), name);
+            return Ok(());
+        }
+        _ => return Err(usage("states")),
+    }
+    let mut any = false;
+    for (name, snapshot) in interpreter.savepoints() {
+        if !any {
+            say!(out, "{}  Savepoints", symbol_from_prompt(Prompt::State));
+            any = true;
+        }
+        say!(
+            out,
+            "    {}  {} nonzero cells, pointer at {}",
+            name,
+            snapshot.cells.len(),
+            snapshot.ptr
+        );
+    }
+    if !any {
+        say!(out, "{}  No savepoints, try :save-state <name>", symbol_from_prompt(Prompt::State));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+fn new_trace() -> Trace {
+    Trace::compressed()
+}
+
+#[cfg(not(feature = "compress"))]
+fn new_trace() -> Trace {
+    Trace::new()
+}
+
+// `:tokens` lists every token with its index and source offset, jumps with
+// where they go, `:tokens 20..40` just those. A jump whose target doesn't
+// jump straight back is flagged, since that's the jump table gone wrong
+fn tokens(args: &[&str], interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    let mut range = RangeFormatter::default();
+    match args {
+        [] => range.end = Some(MAX_TOKENS),
+        [arg] => range.parse_range(arg)?,
+        _ => return Err(usage("tokens")),
+    }
+    let all = interpreter.tokens();
+    let end = range.end.unwrap_or(all.len()).min(all.len());
+    if range.start >= end {
+        say!(out, "{}  No tokens there, there are {}", symbol_from_prompt(Prompt::State), all.len());
+        return Ok(());
+    }
+
+    let width = (end - 1).to_string().len();
+    for i in range.start..end {
+        let token = all[i];
+        let mut line = format!("    #{:<width$}  {}  at {}", i, token, interpreter.spans()[i], width = width);
+        if let Token::JumpForward(target) | Token::JumpBackward(target) = token {
+            line.push_str(&format!("  -> #{}", target));
+            match all.get(target) {
+                Some(&Token::JumpForward(back)) | Some(&Token::JumpBackward(back)) if back == i => (),
+                _ => line.push_str(&format!("  {} #{} doesn't jump back", symbol_from_prompt(Prompt::Error), target)),
+            }
+        }
+        if i == interpreter.cursor() && !interpreter.is_finished() {
+            line.push_str("  <- next");
+        }
+        say!(out, "{}", line);
+    }
+    if end < all.len() && args.is_empty() {
+        say!(out, "    ...and {} more, :tokens {}.. for the rest", all.len() - end, end);
+    }
+    Ok(())
+}
+
+// `:match 3` echoes the lines from a bracket to its partner with both of
+// them highlighted
+fn match_bracket(args: &[&str], interpreter: &Interpreter, out: &mut String) -> Result<(), String> {
+    let i = match args {
+        [arg] => match arg.strip_prefix('#') {
+            Some(index) => index
+                .parse()
+                .map_err(|_| usage_message("not-a-token-index", &[&index]))?,
+            None => {
+                let offset: usize = arg
+                    .parse()
+                    .map_err(|_| usage_message("not-a-source-offset", &[&arg]))?;
+                interpreter
+                    .token_at(offset)
+                    .filter(|&i| interpreter.spans()[i] == offset)
+                    .ok_or_else(|| usage_message("no-bracket-at", &[&offset]))?
+            }
+        },
+        _ => return Err(usage("match")),
+    };
+    let j = interpreter
+        .partner(i)
+        .ok_or_else(|| usage_message("not-a-bracket", &[&i]))?;
+
+    let source = interpreter.source();
+    let (open, close) = (i.min(j), i.max(j));
+    let (open, open_end) = (interpreter.spans()[open], interpreter.ends()[open]);
+    let (close, close_end) = (interpreter.spans()[close], interpreter.ends()[close]);
+    if close >= source.len() {
+        return Err(usage_message("loop-without-source", &[]));
+    }
+    let start = source[..open].rfind('\n').map_or(0, |n| n + 1);
+    let end = source[close_end..].find('\n').map_or(source.len(), |n| close_end + n);
+    let first_line = source[..start].matches('\n').count() + 1;
+
+    let marked = format!(
+        "{}{}{}{}{}",
+        &source[start..open],
+        inverse(&source[open..open_end]),
+        &source[open_end..close],
+        inverse(&source[close..close_end]),
+        &source[close_end..end]
+    );
+    say!(out, "{}  #{} matches #{}", symbol_from_prompt(Prompt::State), i, j);
+    for (n, line) in marked.split('\n').enumerate() {
+        say!(out, "{:>6} | {}", first_line + n, line);
+    }
+    Ok(())
+}
+
+fn inverse(text: &str) -> String {
+    paint("7", text)
+}
+
+// `:dialect boolfuck`, anything in dialect::Registry::new() goes
+fn dialect(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let registry = Registry::new();
+    match args {
+        [] => (),
+        [name] => {
+            let dialect = registry
+                .get(name)
+                .ok_or_else(|| usage_message("unknown-dialect", &[&name, &registry.names().join(", ")]))?;
+            interpreter.set_dialect(Some(dialect));
+        }
+        _ => return Err(usage("dialect")),
+    }
+    let name = interpreter.dialect().map_or("brainfuck", |d| d.name());
+    say!(out, "{}  Speaking {}", symbol_from_prompt(Prompt::State), name);
+    Ok(())
+}
+
+// `:alias › >` adds one, `:alias common` the usual copy and paste lookalikes
+fn alias(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let mut aliases = interpreter.aliases().map_or_else(Aliases::new, |a| (**a).clone());
+    match args {
+        [] => (),
+        ["off"] => aliases = Aliases::new(),
+        ["common"] => aliases.parse("common")?,
+        [from, to] => match (single(from), single(to)) {
+            (Some(from), Some(to)) => aliases.add(from, to)?,
+            _ => return Err(usage("alias")),
+        },
+        _ => return Err(usage("alias")),
+    }
+
+    if aliases.is_empty() {
+        interpreter.set_aliases(None);
+        say!(out, "{}  No aliases", symbol_from_prompt(Prompt::State));
+        return Ok(());
+    }
+    let list: Vec<String> = aliases.iter().map(|(from, to)| format!("{} {}", from, to)).collect();
+    say!(out, "{}  {}", symbol_from_prompt(Prompt::State), list.join(", "));
+    interpreter.set_aliases(Some(Rc::new(aliases)));
+    Ok(())
+}
+
+fn single(word: &str) -> Option<char> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+// `:debug on` pauses evaluated code instead of running it
+fn debug(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => (),
+        ["on"] => interpreter.set_step_mode(true),
+        ["off"] => interpreter.set_step_mode(false),
+        _ => return Err(usage("debug")),
+    }
+    say!(
+        out,
+        "{}  Step mode is {}",
+        symbol_from_prompt(Prompt::Debug),
+        if interpreter.step_mode() { "on" } else { "off" }
+    );
+    Ok(())
+}
+
+// `:break output 100` stops just after the 100th byte of output,
+// `:break cell[@] > 200 && ptr == 7` whenever that becomes true
+fn breakpoint(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => (),
+        ["output", n] => {
+            let n = n.parse().map_err(|_| usage_message("not-a-byte-count", &[&n]))?;
+            interpreter.set_output_break(Some(n));
+        }
+        ["off"] => {
+            interpreter.set_output_break(None);
+            interpreter.clear_break_conditions();
+        }
+        _ => interpreter.add_break_condition(Condition::parse(&args.join(" "))?),
+    }
+    let prompt = symbol_from_prompt(Prompt::Debug);
+    if let Some(n) = interpreter.output_break() {
+        say!(out, "{}  Breaking after output byte {}", prompt, n);
+    }
+    for condition in interpreter.break_conditions() {
+        say!(out, "{}  Breaking when {}", prompt, condition);
+    }
+    if interpreter.output_break().is_none() && interpreter.break_conditions().next().is_none() {
+        say!(out, "{}  No breakpoints", prompt);
+    }
+    Ok(())
+}
+
+// `:watch ptr` or `:watch cell 3`, then stepping lists each change to them.
+// With nothing after it lists the watches, numbered for `:watch drop`
+fn watch(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => (),
+        ["off"] => interpreter.clear_watches(),
+        ["drop", n] => {
+            let i: usize = n.parse().map_err(|_| usage_message("not-a-watch-number", &[&n]))?;
+            if i == 0 || !interpreter.remove_watch(i - 1) {
+                return Err(usage_message("no-watch", &[&n]));
+            }
+        }
+        ["cell", i] => interpreter.add_watch(Condition::parse(&format!("cell[{}]", i))?),
+        _ => interpreter.add_watch(Condition::parse(&args.join(" "))?),
+    }
+    let prompt = symbol_from_prompt(Prompt::Debug);
+    if interpreter.watches().is_empty() {
+        say!(out, "{}  No watches", prompt);
+    }
+    for (i, watch) in interpreter.watches().iter().enumerate() {
+        say!(out, "{}  {}. {} = {}", prompt, i + 1, watch.expr(), watch.value());
+    }
+    Ok(())
+}
+
+// `:watchdog 10000000` stops a run stuck for ten million steps and shows
+// where, :continue carries on regardless
+fn watchdog(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => (),
+        ["off"] => interpreter.set_watchdog(None),
+        [n] => {
+            let n = n
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| usage_message("not-a-step-count", &[&n]))?;
+            interpreter.set_watchdog(Some(n));
+        }
+        _ => return Err(usage("watchdog")),
+    }
+    match interpreter.watchdog() {
+        Some(n) => say!(
+            out,
+            "{}  Pausing runs quiet for {} steps",
+            symbol_from_prompt(Prompt::Debug),
+            format::thousands(n as usize)
+        ),
+        None => say!(out, "{}  No watchdog", symbol_from_prompt(Prompt::Debug)),
+    }
+    Ok(())
+}
+
+// `:quota 12 1000` bounds the loop starting at offset 12, `:quota #4 1000`
+// the one starting at token 4
+fn quota(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    match args {
+        [] => (),
+        ["off"] => interpreter.clear_loop_quotas(),
+        [at, limit] => {
+            let i = match at.strip_prefix('#') {
+                Some(index) => index
+                    .parse()
+                    .map_err(|_| usage_message("not-a-token-index", &[&index]))?,
+                None => {
+                    let offset: usize = at
+                        .parse()
+                        .map_err(|_| usage_message("not-a-source-offset", &[&at]))?;
+                    interpreter
+                        .token_at(offset)
+                        .filter(|&i| interpreter.spans()[i] == offset)
+                        .ok_or_else(|| usage_message("no-loop-at", &[&offset]))?
+                }
+            };
+            if interpreter.partner(i).is_none_or(|close| close < i) {
+                return Err(usage_message("not-a-loop-start", &[&i]));
+            }
+            let limit = match *limit {
+                "off" => None,
+                n => Some(n.parse().map_err(|_| usage_message("not-an-iteration-count", &[&n]))?),
+            };
+            let offset = interpreter.spans()[i];
+            interpreter.set_loop_quota(offset, limit);
+        }
+        _ => return Err(usage("quota")),
+    }
+
+    let quotas = interpreter.loop_quotas();
+    if quotas.is_empty() {
+        say!(out, "{}  No loop quotas", symbol_from_prompt(Prompt::Debug));
+    }
+    for (offset, limit) in quotas {
+        say!(
+            out,
+            "{}  Loop at offset {} stops after {} iterations",
+            symbol_from_prompt(Prompt::Debug),
+            offset,
+            format::thousands(limit as usize)
+        );
+    }
+    Ok(())
+}
+
+// `:step` runs one instruction, `:step 10` runs ten
+fn step(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let n = match args {
+        [] => 1,
+        [n] => parse_count(n)?,
+        _ => return Err(usage("step")),
+    };
+    paused(interpreter, |i| i.step(n), out)
+}
+
+// `:run-for 1e6` is `:step 1e6` that owns up when a breakpoint, the end of
+// the program or an error stopped it short, for narrowing down where a
+// long run goes wrong by halves
+fn run_for(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let n = match args {
+        [n] => parse_count(n)?,
+        _ => return Err(usage("run-for")),
+    };
+    // None when there was nothing paused to run
+    let mut ran = None;
+    let result = paused(
+        interpreter,
+        |i| {
+            let result = i.step(n);
+            ran = Some(i.steps());
+            result
+        },
+        out,
+    );
+    let prompt = symbol_from_prompt(Prompt::Debug);
+    match ran {
+        Some(ran) if ran < n => say!(
+            out,
+            "{}  Ran {} of {} instructions",
+            prompt,
+            format::thousands(ran as usize),
+            format::thousands(n as usize)
+        ),
+        Some(_) => say!(out, "{}  Ran {} instructions", prompt, format::thousands(n as usize)),
+        None => (),
+    }
+    result
+}
+
+// A whole number of steps, with _ between digits or as 1e6 or 2.5e3
+fn parse_count(text: &str) -> Result<u64, String> {
+    let bad = || usage_message("not-a-step-count", &[&text]);
+    let digits = text.replace('_', "");
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<u32>().map_err(|_| bad())?),
+        None => return digits.parse().map_err(|_| bad()),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if whole.is_empty() || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(bad());
+    }
+    // Every digit of the fraction shifted in by the exponent, or it isn't whole
+    let shift = exponent.checked_sub(fraction.len() as u32).ok_or_else(bad)?;
+    let n: u64 = format!("{}{}", whole, fraction).parse().map_err(|_| bad())?;
+    10u64
+        .checked_pow(shift)
+        .and_then(|scale| n.checked_mul(scale))
+        .ok_or_else(bad)
+}
+
+// `:run-to #12` stops before token 12, `:run-to 40` before the first
+// token at or after source offset 40
+fn run_to(args: &[&str], interpreter: &mut Interpreter, out: &mut String) -> Result<(), String> {
+    let target = match args {
+        [arg] => match arg.strip_prefix('#') {
+            Some(index) => index
+                .parse()
+                .map_err(|_| usage_message("not-a-token-index", &[&index]))?,
+            None => {
+                let offset = arg
+                    .parse()
+                    .map_err(|_| usage_message("not-a-source-offset", &[&arg]))?;
+                interpreter
+                    .token_at(offset)
+                    .ok_or_else(|| usage_message("no-code-at", &[&offset]))?
+            }
+        },
+        _ => return Err(usage("run-to")),
+    };
+    if target >= interpreter.tokens().len() {
+        return Err(usage_message("no-token", &[&target]));
+    }
+    paused(interpreter, |i| i.run_to(target), out)
+}
+
+// Move the cursor of a paused program without running anything
+fn jump(
+    interpreter: &mut Interpreter,
+    to: fn(&mut Interpreter) -> bool,
+    out: &mut String,
+) -> Result<(), String> {
+    if interpreter.is_finished() {
+        return Err(usage_message("nothing-to-run", &[]));
+    }
+    if !to(interpreter) {
+        return Err(usage_message("not-in-a-loop", &[]));
+    }
+    say!(out, "{}", interpreter.render_position());
+    Ok(())
+}
+
+// Move a paused program along, then show where it stopped
+fn paused<F>(interpreter: &mut Interpreter, go: F, out: &mut String) -> Result<(), String>
+where
+    F: FnOnce(&mut Interpreter) -> Result<(), RuntimeError>,
+{
+    if interpreter.is_finished() {
+        return Err(usage_message("nothing-to-run", &[]));
+    }
+    let result = go(interpreter);
+    if let Some(changes) = interpreter.render_watch_changes() {
+        say!(out, "{}", changes);
+    }
+    say!(out, "{}", interpreter.render_position());
+    say!(out, "{}", interpreter.render_state());
+    result.map_err(|e| coded(e.code(), e))
+}
+
+// Inclusive byte bounds for a value, a range, or a quoted char
+fn parse_byte_range(arg: &str) -> Result<(u8, u8), String> {
+    if let Some(i) = arg.find("..") {
+        let lo = parse_byte(arg[..i].trim())?;
+        let rest = &arg[i + 2..];
+        let hi = if let Some(inclusive) = rest.strip_prefix('=') {
+            parse_byte(inclusive.trim())?
+        } else {
+            match parse_byte(rest.trim())?.checked_sub(1) {
+                Some(hi) => hi,
+                None => return Err(usage_message("empty-range", &[&arg])),
+            }
+        };
+        if lo > hi {
+            return Err(usage_message("empty-range", &[&arg]));
+        }
+        return Ok((lo, hi));
+    }
+    let n = parse_byte(arg)?;
+    Ok((n, n))
+}
+
+fn parse_byte(arg: &str) -> Result<u8, String> {
+    let chars: Vec<char> = arg.chars().collect();
+    if chars.len() == 3 && chars[0] == '\'' && chars[2] == '\'' {
+        if (chars[1] as u32) < 256 {
+            return Ok(chars[1] as u8);
+        }
+        return Err(usage_message("too-big-for-a-cell", &[&chars[1]]));
+    }
+    arg.parse::<u8>()
+        .map_err(|_| usage_message("not-a-byte-value", &[&arg]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use super::super::Interpreter;
+
+    // Cells 0 to 2 holding 'a', ' ' and 'z'
+    fn tape() -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        for (i, &byte) in b"a z".iter().enumerate() {
+            interpreter.brain.set_ptr(i);
+            interpreter.brain.set_current(byte);
+        }
+        interpreter
+    }
+
+    // What :find said, less the symbol in front
+    fn find(pattern: &str) -> String {
+        let said = run(&format!(":find {}", pattern), &mut tape());
+        said.split_once("  ").map_or(String::new(), |(_, rest)| rest.trim_end().to_string())
+    }
+
+    #[test]
+    fn find_takes_the_whole_rest_of_the_line() {
+        assert_eq!(find("' '"), "1");
+        assert_eq!(find("'a' ..= 'z'"), "0, 2");
+        assert_eq!(find("32..=122"), "0, 1, 2");
+        assert_eq!(find("'b'"), "No cells match 'b'");
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::Path;
+
+use super::command::{self, Arg, COMMANDS};
+
+// Candidates for the word under the cursor of a partly typed line
+// Returns where that word starts, so an editor knows what to replace
+//
+//     complete(":pro", 4)            -> (0, [":profile"])
+//     complete(":format c", 9)       -> (8, ["compact"])
+//     complete(":export csv s", 13)  -> (12, ["sessions.csv", "src/"])
+pub fn complete(line: &str, pos: usize) -> (usize, Vec<String>) {
+    let line = match line.get(..pos) {
+        Some(line) if command::is_command(line) => line,
+        _ => return (pos, Vec::new()),
+    };
+
+    let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &line[start..];
+    if start == 0 {
+        let candidates = COMMANDS
+            .iter()
+            .map(|command| format!(":{}", command.name))
+            .filter(|name| name.starts_with(word))
+            .collect();
+        return (0, candidates);
+    }
+
+    let mut words = line[1..start].split_whitespace();
+    let name = words.next().unwrap_or("");
+    let n = words.count();
+    let arg = command::lookup(name).and_then(|command| command.args.get(n));
+
+    let candidates = match arg {
+        Some(&Arg::Words(choices)) => choices
+            .iter()
+            .filter(|choice| choice.starts_with(word))
+            .map(|choice| choice.to_string())
+            .collect(),
+        Some(&Arg::Command) => COMMANDS
+            .iter()
+            .filter(|command| command.name.starts_with(word))
+            .map(|command| command.name.to_string())
+            .collect(),
+        Some(&Arg::Path) => paths(word),
+        _ => Vec::new(),
+    };
+    (start, candidates)
+}
+
+// Files and directories starting with word, directories with a trailing /
+// Dotfiles only show up once the name being typed starts with a dot
+fn paths(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..i + 1], &word[i + 1..]),
+        None => ("", word),
+    };
+    let search = if dir.is_empty() { Path::new(".") } else { Path::new(dir) };
+    let entries = match fs::read_dir(search) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+
+use super::{Brain, Interpreter};
+
+// A breakpoint condition like `cell[@] > 200 && ptr == 7`, checked after
+// every instruction while any are set
+//
+// Values are integers: numbers, 'c' for a char's byte, `ptr` (or `@`) for
+// the pointer, `cell` for the cell under it, `cell[i]` for any other and
+// `out` for the bytes written so far this run. On top of those there's
+// + -, the comparisons == != < <= > >=, ! && || and parentheses, with
+// comparisons coming out as 1 or 0 like in C
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+enum Expr {
+    Number(i64),
+    Ptr,
+    Output,
+    Cell(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, String> {
+        let mut parser = ExprParser {
+            chars: source.chars().collect(),
+            at: 0,
+        };
+        let expr = parser.or()?;
+        parser.skip_space();
+        if let Some(&c) = parser.chars.get(parser.at) {
+            return Err(format!("Didn't expect '{}' at column {}", c, parser.at + 1));
+        }
+        Ok(Condition {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, brain: &Brain, output: u64) -> bool {
+        self.value(brain, output) != 0
+    }
+
+    // The number itself rather than whether it's true, for watch::Watch
+    pub fn value(&self, brain: &Brain, output: u64) -> i64 {
+        self.expr.eval(brain, output)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl fmt::Debug for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Condition({:?})", self.source)
+    }
+}
+
+impl Expr {
+    fn eval(&self, brain: &Brain, output: u64) -> i64 {
+        match *self {
+            Expr::Number(n) => n,
+            Expr::Ptr => brain.ptr() as i64,
+            Expr::Output => output as i64,
+            // Cells left of 0 don't exist, so they read as 0
+            Expr::Cell(ref i) => match i.eval(brain, output) {
+                i if i < 0 => 0,
+                i => brain.get(i as usize) as i64,
+            },
+            Expr::Not(ref e) => (e.eval(brain, output) == 0) as i64,
+            Expr::Negate(ref e) => e.eval(brain, output).wrapping_neg(),
+            // Both sides of && and || only when they have to be
+            Expr::Binary(Op::And, ref l, ref r) => {
+                (l.eval(brain, output) != 0 && r.eval(brain, output) != 0) as i64
+            }
+            Expr::Binary(Op::Or, ref l, ref r) => {
+                (l.eval(brain, output) != 0 || r.eval(brain, output) != 0) as i64
+            }
+            Expr::Binary(op, ref l, ref r) => {
+                let (l, r) = (l.eval(brain, output), r.eval(brain, output));
+                match op {
+                    Op::Add => l.wrapping_add(r),
+                    Op::Sub => l.wrapping_sub(r),
+                    Op::Eq => (l == r) as i64,
+                    Op::Ne => (l != r) as i64,
+                    Op::Lt => (l < r) as i64,
+                    Op::Le => (l <= r) as i64,
+                    Op::Gt => (l > r) as i64,
+                    Op::Ge => (l >= r) as i64,
+                    Op::And | Op::Or => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+// Recursive descent, one function per precedence level from || down
+struct ExprParser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl ExprParser {
+    fn skip_space(&mut self) {
+        while self.chars.get(self.at).is_some_and(|c| c.is_whitespace()) {
+            self.at += 1;
+        }
+    }
+
+    // Consumes s if it's next
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_space();
+        let end = self.at + s.chars().count();
+        if end <= self.chars.len() && self.chars[self.at..end].iter().cloned().eq(s.chars()) {
+            self.at = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expected(&self, what: &str) -> String {
+        match self.chars.get(self.at) {
+            Some(c) => format!("Expected {} at column {}, found '{}'", what, self.at + 1, c),
+            None => format!("Expected {} at the end", what),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            left = Expr::Binary(Op::Or, Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.comparison()?;
+        while self.eat("&&") {
+            left = Expr::Binary(Op::And, Box::new(left), Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    // Comparisons don't chain, `a < b < c` is almost never what was meant
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        for &(s, op) in &ops {
+            if self.eat(s) {
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(self.sum()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("+") {
+                Op::Add
+            } else if self.eat("-") {
+                Op::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        // `!=` is a comparison, not a not
+        if !self.peek("!=") && self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn peek(&mut self, s: &str) -> bool {
+        let at = self.at;
+        let found = self.eat(s);
+        self.at = at;
+        found
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        self.skip_space();
+        let start = self.at;
+        match self.chars.get(self.at).cloned() {
+            Some('(') => {
+                self.at += 1;
+                let expr = self.or()?;
+                if !self.eat(")") {
+                    return Err(self.expected("')'"));
+                }
+                Ok(expr)
+            }
+            Some('@') => {
+                self.at += 1;
+                Ok(Expr::Ptr)
+            }
+            Some('\'') => match (self.chars.get(self.at + 1), self.chars.get(self.at + 2)) {
+                (Some(&c), Some('\'')) => {
+                    self.at += 3;
+                    Ok(Expr::Number(c as i64))
+                }
+                _ => Err(format!("Expected a char like 'a' at column {}", start + 1)),
+            },
+            Some(c) if c.is_ascii_digit() => {
+                while self.chars.get(self.at).is_some_and(|c| c.is_ascii_digit()) {
+                    self.at += 1;
+                }
+                let digits: String = self.chars[start..self.at].iter().collect();
+                digits
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("{} is too big", digits))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                while self.chars.get(self.at).is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    self.at += 1;
+                }
+                let word: String = self.chars[start..self.at].iter().collect();
+                match word.as_str() {
+                    "ptr" => Ok(Expr::Ptr),
+                    "out" => Ok(Expr::Output),
+                    "cell" if self.eat("[") => {
+                        let index = self.or()?;
+                        if !self.eat("]") {
+                            return Err(self.expected("']'"));
+                        }
+                        Ok(Expr::Cell(Box::new(index)))
+                    }
+                    "cell" => Ok(Expr::Cell(Box::new(Expr::Ptr))),
+                    _ => Err(format!(
+                        "'{}' at column {} isn't something a condition knows, try ptr, cell[i] or out",
+                        word,
+                        start + 1
+                    )),
+                }
+            }
+            _ => Err(self.expected("a value")),
+        }
+    }
+}
+
+impl Interpreter {
+    // Pause as soon as condition turns true. One that's already true when a
+    // run starts or carries on has to go false first, so :continue doesn't
+    // stop straight away on the same thing
+    pub fn add_break_condition(&mut self, condition: Condition) {
+        self.conditions.push((condition, false));
+    }
+
+    pub fn break_conditions(&self) -> impl Iterator<Item = &Condition> {
+        self.conditions.iter().map(|(condition, _)| condition)
+    }
+
+    pub fn clear_break_conditions(&mut self) {
+        self.conditions.clear();
+        self.condition_hit = None;
+    }
+
+    // The condition that paused the last run, if one did
+    pub fn condition_hit(&self) -> Option<&Condition> {
+        self.condition_hit.map(|i| &self.conditions[i].0)
+    }
+
+    // Called before a run so conditions only fire on changes from here on
+    pub(super) fn arm_conditions(&mut self) {
+        let (brain, output) = (&self.brain, self.output_count);
+        for (condition, was) in &mut self.conditions {
+            *was = condition.eval(brain, output);
+        }
+    }
+
+    // After each instruction: true when a condition has just turned true
+    pub(super) fn check_conditions(&mut self) -> bool {
+        let (brain, output) = (&self.brain, self.output_count);
+        for (i, (condition, was)) in self.conditions.iter_mut().enumerate() {
+            let now = condition.eval(brain, output);
+            if now && !*was && self.condition_hit.is_none() {
+                self.condition_hit = Some(i);
+            }
+            *was = now;
+        }
+        self.condition_hit.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Brain;
+    use super::Condition;
+
+    // Cells 1, 2, 3 with the pointer on the 3 at cell 2
+    fn brain() -> Brain {
+        let mut brain = Brain::new();
+        for (i, value) in [1, 2, 3].iter().enumerate() {
+            brain.set_ptr(i);
+            brain.set_current(*value);
+        }
+        brain
+    }
+
+    fn value(source: &str) -> i64 {
+        Condition::parse(source).unwrap().value(&brain(), 5)
+    }
+
+    fn error(source: &str) -> String {
+        Condition::parse(source).err().unwrap()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(value("1 || 1 && 0"), 1);
+        assert_eq!(value("0 && 1 || 1"), 1);
+        assert_eq!(value("(1 || 1) && 0"), 0);
+        assert_eq!(value("cell == 3 && ptr == 2 || out > 100"), 1);
+        assert_eq!(value("1 + 2 == 3"), 1);
+        assert_eq!(value("2 - 1 - 1"), 0);
+    }
+
+    #[test]
+    fn not_equal_isnt_not() {
+        assert_eq!(value("cell != 3"), 0);
+        assert_eq!(value("cell!=2"), 1);
+        assert_eq!(value("!cell"), 0);
+        assert_eq!(value("!!cell"), 1);
+        // ! goes with the value, then the comparison
+        assert_eq!(value("!cell == 1"), 0);
+        assert_eq!(value("!(cell == 1)"), 1);
+        assert_eq!(value("-cell + 3"), 0);
+    }
+
+    #[test]
+    fn cells_by_index_or_from_the_pointer() {
+        assert_eq!(value("cell"), 3);
+        assert_eq!(value("cell[@]"), 3);
+        assert_eq!(value("cell[ptr]"), 3);
+        assert_eq!(value("cell[@-1]"), 2);
+        assert_eq!(value("cell[@ - 2] + cell[0]"), 2);
+        assert_eq!(value("cell[cell[0]]"), 2);
+        // Left of 0 and past the end read as 0
+        assert_eq!(value("cell[@-5]"), 0);
+        assert_eq!(value("cell[1000]"), 0);
+        assert_eq!(value("cell == 'a' - 94"), 1);
+        assert_eq!(value("out"), 5);
+    }
+
+    #[test]
+    fn every_token_has_to_be_there_and_used() {
+        assert_eq!(error(""), "Expected a value at the end");
+        assert_eq!(error("cell =="), "Expected a value at the end");
+        assert_eq!(error("cell[@"), "Expected ']' at the end");
+        assert_eq!(error("(1 || 0"), "Expected ')' at the end");
+        assert_eq!(error("1 && "), "Expected a value at the end");
+        assert_eq!(error("cell[@] 2"), "Didn't expect '2' at column 9");
+        assert_eq!(error("1 < 2 < 3"), "Didn't expect '<' at column 7");
+        assert_eq!(error("1)"), "Didn't expect ')' at column 2");
+        assert_eq!(error("'ab' == 1"), "Expected a char like 'a' at column 1");
+        assert!(error("cells > 1").starts_with("'cells' at column 1 isn't something"));
+    }
+}
@@ -0,0 +1,449 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use super::check::{Level, Lints};
+use super::cli::{check_dialect, ErrorFormat, RunOptions};
+use super::error;
+use super::io::Eof;
+
+// Defaults from ~/.config/brainf/config.toml, then a .brainfrc in the
+// current directory on top, both optional. Flags go on top of both with
+// RunOptions::with_args
+//
+//     [run]
+//     max_steps = 10_000_000
+//     capacity_cap = 65536
+//     watchdog = 1_000_000
+//     history = 32
+//     dialect = "spoon"
+//     aliases = ["common"]
+//     strict_comments = true
+//     bang_input = true
+//     eof = "0"
+//     error_format = "json"
+//     max_download = 1_048_576
+//
+//     [repl]
+//     quiet = true
+//     no_emoji = true
+//     no_color = true
+//
+//     [lints]
+//     underflow = "deny"
+//     warnings = "deny"
+//
+// BRAINF_ and a setting's name in capitals sets it too, BRAINF_MAX_STEPS=1000
+// on top of the files and under flags, for CI and containers. Any other
+// BRAINF_ variable is most likely a typo, so it ends up in warnings, and
+// NO_COLOR works the way it does everywhere else
+//
+// It's the part of TOML that's needed for that: sections, `key = value`
+// with strings, whole numbers, booleans and lists of strings, and `#`
+// comments. Anything else is an error rather than quietly ignored
+#[derive(Debug, Default)]
+pub struct Config {
+    pub run: RunOptions,
+    pub quiet: bool,
+    pub no_emoji: bool,
+    pub no_color: bool,
+    // For check, under the flags
+    pub lints: Lints,
+    // What was set but not used, for whoever loaded this to print with
+    // error::CODE_UNKNOWN_SETTING
+    pub warnings: Vec<String>,
+}
+
+// What a BRAINF_ variable can set and where it goes, lints aren't included
+const REPL_KEYS: &[&str] = &["quiet", "no_emoji", "no_color"];
+const RUN_KEYS: &[&str] = &[
+    "max_steps",
+    "capacity_cap",
+    "watchdog",
+    "max_download",
+    "history",
+    "dialect",
+    "aliases",
+    "strict_comments",
+    "bang_input",
+    "error_format",
+    "eof",
+];
+
+// Switches, which in a variable can also be 1 or 0 the way NO_COLOR=1 is
+const SWITCH_KEYS: &[&str] = &["strict_comments", "bang_input", "quiet", "no_emoji", "no_color"];
+
+const ENV_PREFIX: &str = "BRAINF_";
+
+enum Value {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+impl Config {
+    // Every file that exists, in the order they're read
+    pub fn load() -> Result<Config, String> {
+        let mut config = Config::default();
+        for path in Config::paths() {
+            match fs::read_to_string(&path) {
+                Ok(text) => config.read(&text, &path.display().to_string())?,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(error::read_error(path.display(), e)),
+            }
+        }
+        config.read_env()?;
+        Ok(config)
+    }
+
+    // BRAINF_ variables on top of what's already here. Values are bare,
+    // BRAINF_DIALECT=spoon rather than "spoon", and aliases are split at spaces
+    pub fn read_env(&mut self) -> Result<(), String> {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            self.no_color = true;
+        }
+        let mut vars: Vec<(String, String)> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        // The same error first every time when there's more than one
+        vars.sort();
+        self.read_vars(vars)
+    }
+
+    // read_env with the variables already picked out, name and value
+    fn read_vars(&mut self, vars: Vec<(String, String)>) -> Result<(), String> {
+        for (name, text) in vars {
+            let key = name[ENV_PREFIX.len()..].to_lowercase();
+            let section = if REPL_KEYS.contains(&key.as_str()) {
+                "repl"
+            } else if RUN_KEYS.contains(&key.as_str()) {
+                "run"
+            } else {
+                self.warnings.push(match key.as_str() {
+                    "cell_width" => format!("{} isn't a setting, cells are always bytes", name),
+                    _ => format!("{} isn't a setting, it's ignored", name),
+                });
+                continue;
+            };
+            let switch = SWITCH_KEYS.contains(&key.as_str());
+            let value = match text.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "1" if switch => Value::Bool(true),
+                "0" | "" if switch => Value::Bool(false),
+                _ if key == "aliases" => Value::List(text.split_whitespace().map(String::from).collect()),
+                _ => match text.replace('_', "").parse() {
+                    Ok(n) => Value::Number(n),
+                    Err(_) => Value::Text(text),
+                },
+            };
+            self.set(section, &key, value).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    // $XDG_CONFIG_HOME is used instead of ~/.config when it's set
+    pub fn paths() -> Vec<PathBuf> {
+        let home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+        let mut paths: Vec<PathBuf> = home.map(|dir| dir.join("brainf").join("config.toml")).into_iter().collect();
+        paths.push(PathBuf::from(".brainfrc"));
+        paths
+    }
+
+    // The settings in text on top of what's already here. origin is where
+    // text came from, for error messages
+    pub fn read(&mut self, text: &str, origin: &str) -> Result<(), String> {
+        let mut section = String::new();
+        for (n, line) in text.lines().enumerate() {
+            let at = |e: String| format!("{}:{}: {}", origin, n + 1, e);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| at(format!("Expected `key = value`, not '{}'", line)))?;
+            let value = parse_value(value.trim()).map_err(at)?;
+            self.set(&section, key.trim(), value).map_err(at)?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: Value) -> Result<(), String> {
+        let run = &mut self.run;
+        match (section, key, value) {
+            ("run", "max_steps", Value::Number(n)) => run.max_steps = Some(n),
+            ("run", "capacity_cap", Value::Number(n)) => run.capacity_cap = Some(n as usize),
+            ("run", "watchdog", Value::Number(n)) => run.watchdog = Some(n),
+            ("run", "max_download", Value::Number(n)) => run.max_download = Some(n as usize),
+            ("run", "history", Value::Number(n)) => run.history = Some(n as usize),
+            ("run", "dialect", Value::Text(name)) => {
+                check_dialect(&name)?;
+                run.dialect = Some(name);
+            }
+            ("run", "aliases", Value::List(specs)) => {
+                for spec in specs {
+                    run.aliases.parse(&spec)?;
+                }
+            }
+            ("run", "strict_comments", Value::Bool(on)) => run.strict_comments = on,
+            ("run", "bang_input", Value::Bool(on)) => run.bang_input = on,
+            ("run", "error_format", Value::Text(name)) => run.error_format = ErrorFormat::parse(&name)?,
+            ("run", "eof", Value::Text(text)) => run.eof = Eof::parse(&text)?,
+            ("run", "eof", Value::Number(n)) => run.eof = Eof::parse(&n.to_string())?,
+            ("repl", "quiet", Value::Bool(on)) => self.quiet = on,
+            ("repl", "no_emoji", Value::Bool(on)) => self.no_emoji = on,
+            ("repl", "no_color", Value::Bool(on)) => self.no_color = on,
+            ("lints", lint, Value::Text(level)) => self.lints.set(lint, Level::parse(&level)?)?,
+            ("run", "max_steps", _)
+            | ("run", "capacity_cap", _)
+            | ("run", "watchdog", _)
+            | ("run", "max_download", _)
+            | ("run", "history", _) => return Err(format!("{} needs a number", key)),
+            ("run", "dialect", _) | ("run", "error_format", _) | ("run", "eof", _) => return Err(format!("{} needs a string", key)),
+            ("run", "aliases", _) => return Err(format!("{} needs a list of strings", key)),
+            ("run", "strict_comments", _)
+            | ("run", "bang_input", _)
+            | ("repl", "quiet", _)
+            | ("repl", "no_emoji", _)
+            | ("repl", "no_color", _) => return Err(format!("{} needs true or false", key)),
+            ("lints", _, _) => return Err(format!("{} needs \"allow\", \"warn\" or \"deny\"", key)),
+            ("", _, _) => return Err(format!("'{}' has to go under [run], [repl] or [lints]", key)),
+            _ => return Err(format!("There's no setting called '{}' in [{}]", key, section)),
+        }
+        Ok(())
+    }
+}
+
+// The settings in run that change how a program goes, as text read()
+// takes back, for archive::Archive. An alias with a quote in it can't be
+// written as a string, so it's left out
+pub fn write_run(run: &RunOptions) -> String {
+    let mut lines = vec!["[run]".to_string()];
+    let numbers = [
+        ("max_steps", run.max_steps),
+        ("capacity_cap", run.capacity_cap.map(|n| n as u64)),
+        ("watchdog", run.watchdog),
+        ("history", run.history.map(|n| n as u64)),
+    ];
+    for (key, value) in numbers.iter() {
+        if let Some(n) = value {
+            lines.push(format!("{} = {}", key, n));
+        }
+    }
+    if let Some(ref dialect) = run.dialect {
+        lines.push(format!("dialect = \"{}\"", dialect));
+    }
+    let aliases: Vec<String> = run
+        .aliases
+        .iter()
+        .filter(|&(from, to)| from != '"' && to != '"')
+        .map(|(from, to)| format!("\"{}={}\"", from, to))
+        .collect();
+    if !aliases.is_empty() {
+        lines.push(format!("aliases = [{}]", aliases.join(", ")));
+    }
+    lines.push(format!("strict_comments = {}", run.strict_comments));
+    lines.push(format!("eof = \"{}\"", run.eof.name()));
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+// Everything from a `#` that isn't inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    match text {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => (),
+    }
+    if let Some(items) = text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        return split_list(items)
+            .into_iter()
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(parse_string)
+            .collect::<Result<_, _>>()
+            .map(Value::List);
+    }
+    if text.starts_with('"') {
+        return parse_string(text).map(Value::Text);
+    }
+    text.replace('_', "")
+        .parse()
+        .map(Value::Number)
+        .map_err(|_| format!("'{}' isn't a string, a whole number, true, false or a list", text))
+}
+
+// At commas outside strings, since `,` is a command an alias can stand for
+fn split_list(items: &str) -> Vec<&str> {
+    let (mut parts, mut start, mut quoted) = (Vec::new(), 0, false);
+    for (i, c) in items.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&items[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&items[start..]);
+    parts
+}
+
+// No escapes, nothing a setting needs has a quote in it
+fn parse_string(text: &str) -> Result<String, String> {
+    match text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) if !inner.contains('"') => Ok(inner.to_string()),
+        _ => Err(format!("{} isn't a string, strings go in double quotes", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::check::Level;
+    use super::super::cli::{Args, ErrorFormat, RUN_SWITCHES, RUN_VALUES};
+    use super::super::error::CODE_UNDERFLOW;
+    use super::super::io::Eof;
+    use super::{parse_value, Config, Value};
+
+    fn read(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        config.read(text, "test.toml").map(|()| config)
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn reads_every_kind_of_value() {
+        let config = read(
+            "# The whole line
+            [run]
+            max_steps = 10_000  # after a value
+            dialect = \"spoon\"
+            aliases = [\"a=+\", \"#=,\"]
+            strict_comments = true
+            eof = 255
+            error_format = \"json\"
+
+            [ repl ]
+            no_emoji = true
+
+            [lints]
+            underflow = \"deny\"",
+        )
+        .unwrap();
+        assert_eq!(config.run.max_steps, Some(10_000));
+        assert_eq!(config.run.dialect.as_deref(), Some("spoon"));
+        assert_eq!(config.run.aliases.get('a'), Some('+'));
+        assert_eq!(config.run.aliases.get('#'), Some(','));
+        assert!(config.run.strict_comments);
+        assert_eq!(config.run.eof, Eof::Max);
+        assert_eq!(config.run.error_format, ErrorFormat::Json);
+        assert!(config.no_emoji && !config.quiet);
+        assert_eq!(config.lints.level(CODE_UNDERFLOW, Level::Allow), Level::Deny);
+    }
+
+    #[test]
+    fn values_are_quoted_or_not() {
+        assert!(matches!(parse_value("\"0\""), Ok(Value::Text(ref text)) if text == "0"));
+        assert!(matches!(parse_value("1_000"), Ok(Value::Number(1000))));
+        assert!(matches!(parse_value("false"), Ok(Value::Bool(false))));
+        assert!(matches!(parse_value("[]"), Ok(Value::List(ref items)) if items.is_empty()));
+        assert!(matches!(parse_value("[\"a=,\", \"b=.\"]"), Ok(Value::List(ref items)) if items == &["a=,", "b=."]));
+        // A string has to be in quotes, and a number whole
+        assert!(parse_value("spoon").is_err());
+        assert!(parse_value("1.5").is_err());
+        assert!(parse_value("-1").is_err());
+    }
+
+    #[test]
+    fn bad_lines_say_where() {
+        let error = |text: &str| read(text).err().unwrap();
+        assert_eq!(error("[run]\nmax_steps"), "test.toml:2: Expected `key = value`, not 'max_steps'");
+        assert_eq!(error("\n\nmax_steps = 1"), "test.toml:3: 'max_steps' has to go under [run], [repl] or [lints]");
+        assert_eq!(error("[run]\nmax_step = 1"), "test.toml:2: There's no setting called 'max_step' in [run]");
+        assert_eq!(error("[run]\nmax_steps = \"1\""), "test.toml:2: max_steps needs a number");
+        assert_eq!(error("[repl]\nquiet = 1"), "test.toml:2: quiet needs true or false");
+        assert!(error("[run]\ndialect = \"ook\"").starts_with("test.toml:2: No dialect called 'ook'"));
+    }
+
+    // Files in order, then the variables, then flags
+    #[test]
+    fn later_settings_win() {
+        let mut config = read("[run]\nmax_steps = 1\nwatchdog = 5\nhistory = 3").unwrap();
+        config.read("[run]\nmax_steps = 2", ".brainfrc").unwrap();
+        config.read_vars(vars(&[("BRAINF_MAX_STEPS", "3"), ("BRAINF_WATCHDOG", "6")])).unwrap();
+        assert_eq!((config.run.max_steps, config.run.watchdog), (Some(3), Some(6)));
+
+        let flags = ["--max-steps".to_string(), "4".to_string()];
+        let args = Args::parse(&flags, RUN_SWITCHES, RUN_VALUES).unwrap();
+        let run = config.run.with_args(&args).unwrap();
+        assert_eq!((run.max_steps, run.watchdog, run.history), (Some(4), Some(6), Some(3)));
+    }
+
+    #[test]
+    fn variables_are_bare() {
+        let mut config = Config::default();
+        config
+            .read_vars(vars(&[
+                ("BRAINF_ALIASES", "a=+ b=-"),
+                ("BRAINF_DIALECT", "boolfuck"),
+                ("BRAINF_EOF", "0"),
+                ("BRAINF_MAX_STEPS", "1_000"),
+                ("BRAINF_NO_COLOR", "1"),
+                ("BRAINF_QUIET", "0"),
+            ]))
+            .unwrap();
+        assert_eq!(config.run.aliases.get('b'), Some('-'));
+        assert_eq!(config.run.dialect.as_deref(), Some("boolfuck"));
+        assert_eq!(config.run.eof, Eof::Zero);
+        assert_eq!(config.run.max_steps, Some(1000));
+        assert!(config.no_color && !config.quiet);
+        assert!(config.warnings.is_empty());
+
+        let mut config = Config::default();
+        let error = config.read_vars(vars(&[("BRAINF_MAX_STEPS", "lots")])).err().unwrap();
+        assert_eq!(error, "BRAINF_MAX_STEPS: max_steps needs a number");
+    }
+
+    #[test]
+    fn unknown_variables_are_warned_about() {
+        let mut config = Config::default();
+        config
+            .read_vars(vars(&[("BRAINF_CELL_WIDTH", "16"), ("BRAINF_MAX_STEP", "10"), ("BRAINF_MAX_STEPS", "10")]))
+            .unwrap();
+        assert_eq!(config.run.max_steps, Some(10));
+        assert_eq!(
+            config.warnings,
+            [
+                "BRAINF_CELL_WIDTH isn't a setting, cells are always bytes",
+                "BRAINF_MAX_STEP isn't a setting, it's ignored",
+            ]
+        );
+    }
+}
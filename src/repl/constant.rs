@@ -0,0 +1,111 @@
+// A program that never reads input prints the same thing every time it
+// runs. Once one has run to the end, what it printed is kept, and the next
+// run of the same program under the same limits just writes that out
+// instead of running it all again. Watch mode running every program again
+// when one changes and a busy serve-http get the most out of it
+//
+// Only plain brainfuck, a dialect's instructions could do anything
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use super::error::RuntimeError;
+use super::{Interpreter, Token};
+
+// Past this many programs the cache starts over
+const MAX_PROGRAMS: usize = 256;
+
+// Output bigger than this isn't worth keeping around
+const MAX_OUTPUT: usize = 1 << 20;
+
+// The program's commands, max_steps and watchdog, which between them
+// decide how a run without input goes
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    commands: String,
+    max_steps: Option<u64>,
+    watchdog: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct Constant {
+    output: Vec<u8>,
+    steps: u64,
+}
+
+fn cache() -> &'static Mutex<HashMap<Key, Constant>> {
+    static CACHE: OnceLock<Mutex<HashMap<Key, Constant>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+impl Interpreter {
+    // interpret(), except a program that's finished before with nothing to
+    // read only has its output written again. The tape is left as it was
+    // then, so this is for runs where only the output and steps() matter,
+    // on a program that's just been loaded
+    pub fn interpret_constant(&mut self) -> Result<(), RuntimeError> {
+        let key = match self.constant_key() {
+            Some(key) => key,
+            None => return self.interpret(),
+        };
+        let hit = cache().lock().ok().and_then(|cache| cache.get(&key).cloned());
+        if let Some(constant) = hit {
+            self.begin_run();
+            for &byte in &constant.output {
+                self.io.write(byte);
+                self.outputs.push(byte);
+            }
+            self.io.flush();
+            self.output_count = constant.output.len() as u64;
+            self.steps = constant.steps;
+            self.cursor = self.tokens.len();
+            return match self.io.take_error() {
+                Some(e) => Err(RuntimeError::Io(e.to_string())),
+                None => Ok(()),
+            };
+        }
+
+        let result = self.interpret();
+        let output = self.outputs.get(1).unwrap_or(&[]);
+        // Output history turned off means there's nothing to keep
+        let kept = !self.outputs.is_empty() || self.output_count == 0;
+        if result.is_ok() && kept && output.len() <= MAX_OUTPUT {
+            let constant = Constant {
+                output: output.to_vec(),
+                steps: self.steps,
+            };
+            if let Ok(mut cache) = cache().lock() {
+                if cache.len() >= MAX_PROGRAMS {
+                    cache.clear();
+                }
+                cache.insert(key, constant);
+            }
+        }
+        result
+    }
+
+    // None unless the loaded program is sure to run the same way every
+    // time from the start
+    fn constant_key(&self) -> Option<Key> {
+        if self.dialect.is_some() || !self.loop_quotas.is_empty() || self.cursor != 0 {
+            return None;
+        }
+        let mut commands = String::with_capacity(self.tokens.len());
+        for token in &self.tokens {
+            commands.push(match *token {
+                Token::PointerIncrement => '>',
+                Token::PointerDecrement => '<',
+                Token::DataIncrement => '+',
+                Token::DataDecrement => '-',
+                Token::Output => '.',
+                Token::JumpForward(_) => '[',
+                Token::JumpBackward(_) => ']',
+                Token::Input | Token::Extension(_) => return None,
+            });
+        }
+        Some(Key {
+            commands,
+            max_steps: self.max_steps,
+            watchdog: self.watchdog,
+        })
+    }
+}
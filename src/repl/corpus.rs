@@ -0,0 +1,355 @@
+// Programs to judge straight out of a .zip, .tar or .tar.gz, nothing
+// extracted. Each `x.bf` runs on `x.in` from beside it, or on nothing, and
+// its output has to match `x.expected` when there is one
+//
+//     primes.bf  primes.in  primes.expected
+//     hello.bf              hello.expected
+//     loop.bf                                 only has to stop
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::cli::RunOptions;
+use super::inflate;
+use super::io::Buffer;
+use super::spec::Outcome;
+use super::{decode_source, strip_shebang, Interpreter};
+
+const EXTENSIONS: &[&str] = &[".bf", ".in", ".expected"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    // As it's named in the archive
+    pub name: String,
+    pub source: String,
+    pub input: Vec<u8>,
+    pub expected: Option<Vec<u8>>,
+}
+
+// Every program in the archive at path, by name
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<Program>> {
+    let data = fs::read(path)?;
+    let files = if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        unzip(&data)?
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        untar(&inflate::gunzip(&data)?)?
+    } else if data.get(257..262) == Some(b"ustar") {
+        untar(&data)?
+    } else {
+        return Err(invalid("isn't a zip, tar or tar.gz file".to_string()));
+    };
+    Ok(pair(files))
+}
+
+// Run one program the way options say, with its input
+pub fn judge(program: &Program, options: &RunOptions) -> Outcome {
+    let mut interpreter = Interpreter::new();
+    options.configure(&mut interpreter);
+    if let Err(e) = interpreter.load_source(&program.source) {
+        return Outcome::Error(e.to_string());
+    }
+    let buffer = Buffer::new(&program.input);
+    let output = buffer.output();
+    interpreter.set_io(Box::new(buffer));
+    if let Err(e) = interpreter.interpret() {
+        return Outcome::Error(e.to_string());
+    }
+    let actual = output.borrow().clone();
+    match program.expected {
+        Some(ref expected) if *expected != actual => Outcome::Fail {
+            expected: expected.clone(),
+            actual,
+        },
+        _ => Outcome::Pass,
+    }
+}
+
+// Files that belong to a program, name and contents
+type Files = Vec<(String, Vec<u8>)>;
+
+fn wanted(name: &str) -> bool {
+    EXTENSIONS.iter().any(|extension| name.ends_with(extension))
+}
+
+// What's turned up for one stem so far
+#[derive(Default)]
+struct Parts {
+    source: Option<Vec<u8>>,
+    input: Vec<u8>,
+    expected: Option<Vec<u8>>,
+}
+
+fn pair(files: Files) -> Vec<Program> {
+    let mut found: BTreeMap<String, Parts> = BTreeMap::new();
+    for (name, data) in files {
+        let name = name.trim_start_matches("./").to_string();
+        let extension = match EXTENSIONS.iter().find(|extension| name.ends_with(*extension)) {
+            Some(extension) => extension,
+            None => continue,
+        };
+        let stem = name[..name.len() - extension.len()].to_string();
+        let parts = found.entry(stem).or_default();
+        match *extension {
+            ".bf" => parts.source = Some(data),
+            ".in" => parts.input = data,
+            _ => parts.expected = Some(data),
+        }
+    }
+    found
+        .into_iter()
+        .filter_map(|(stem, parts)| {
+            Some(Program {
+                name: format!("{}.bf", stem),
+                source: strip_shebang(decode_source(&parts.source?)),
+                input: parts.input,
+                expected: parts.expected,
+            })
+        })
+        .collect()
+}
+
+// 512 byte headers each followed by the file rounded up to 512, until two
+// blocks of zeros. GNU long names and pax paths are understood, links and
+// the like are skipped
+fn untar(data: &[u8]) -> io::Result<Files> {
+    let mut files = Vec::new();
+    let mut long_name = None;
+    let mut at = 0;
+    while let Some(header) = data.get(at..at + 512) {
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = octal(&header[124..136])?;
+        let body = data
+            .get(at + 512..at + 512 + size)
+            .ok_or_else(|| invalid("tar entry is cut short".to_string()))?;
+        at += 512 + size.div_ceil(512) * 512;
+        match header[156] {
+            b'L' => long_name = Some(field(body)),
+            b'x' => long_name = pax_path(body).or(long_name),
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let (name, prefix) = (field(&header[..100]), field(&header[345..500]));
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                if wanted(&name) {
+                    files.push((name, body.to_vec()));
+                }
+            }
+            _ => long_name = None,
+        }
+    }
+    Ok(files)
+}
+
+// Up to the first zero byte
+fn field(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn octal(bytes: &[u8]) -> io::Result<usize> {
+    let text = field(bytes);
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(text, 8).map_err(|_| invalid(format!("tar size '{}' isn't octal", text)))
+}
+
+// `30 path=some/long/name.bf` records
+fn pax_path(body: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|record| record.split_once(' '))
+        .find_map(|(_, record)| record.strip_prefix("path=").map(|path| path.to_string()))
+}
+
+// Through the central directory at the end, which has the sizes even when
+// the local headers left them for after the data
+fn unzip(data: &[u8]) -> io::Result<Files> {
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|&i| data[i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| invalid("zip has no central directory".to_string()))?;
+    let count = u16_at(data, end + 10)? as usize;
+    let mut at = u32_at(data, end + 16)? as usize;
+    if at == 0xffff_ffff {
+        return Err(invalid("zip64 archives aren't supported".to_string()));
+    }
+
+    let mut files = Vec::new();
+    for _ in 0..count {
+        if !data[at.min(data.len())..].starts_with(b"PK\x01\x02") {
+            return Err(invalid("zip central directory is damaged".to_string()));
+        }
+        let flags = u16_at(data, at + 8)?;
+        let method = u16_at(data, at + 10)?;
+        let size = u32_at(data, at + 20)? as usize;
+        let name_len = u16_at(data, at + 28)? as usize;
+        let skip = u16_at(data, at + 30)? as usize + u16_at(data, at + 32)? as usize;
+        let local = u32_at(data, at + 42)? as usize;
+        let name = data
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("zip central directory is cut short".to_string()))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        at += 46 + name_len + skip;
+        if !wanted(&name) {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(invalid(format!("{} is encrypted", name)));
+        }
+
+        let start = local + 30 + u16_at(data, local + 26)? as usize + u16_at(data, local + 28)? as usize;
+        let stored = data
+            .get(start..start + size)
+            .ok_or_else(|| invalid(format!("{} is cut short", name)))?;
+        let contents = match method {
+            0 => stored.to_vec(),
+            8 => inflate::inflate(stored).map_err(|e| invalid(format!("{}: {}", name, e)))?,
+            _ => return Err(invalid(format!("{} uses compression method {}, only stored and deflate are supported", name, method))),
+        };
+        files.push((name, contents));
+    }
+    Ok(files)
+}
+
+fn u16_at(data: &[u8], at: usize) -> io::Result<u16> {
+    match data.get(at..at + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(invalid("zip is cut short".to_string())),
+    }
+}
+
+fn u32_at(data: &[u8], at: usize) -> io::Result<u32> {
+    match data.get(at..at + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(invalid("zip is cut short".to_string())),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pair, untar, unzip, Program};
+
+    // "+[>+]" as raw deflate
+    const DEFLATED: [u8; 7] = [0xd3, 0x8e, 0xb6, 0xd3, 0x8e, 0x05, 0x00];
+
+    // A ustar archive of (name, prefix, contents), only the fields untar reads
+    fn tar(files: &[(&str, &str, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &(name, prefix, contents) in files {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+            data.extend_from_slice(&header);
+            data.extend_from_slice(contents);
+            data.resize(data.len().div_ceil(512) * 512, 0);
+        }
+        data.resize(data.len() + 1024, 0);
+        data
+    }
+
+    // A zip of (name, method, stored bytes), crcs left at zero since unzip
+    // doesn't check them
+    fn zip(files: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut central = Vec::new();
+        for &(name, method, stored) in files {
+            let local = data.len() as u32;
+            data.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00");
+            data.extend_from_slice(&method.to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(&[0; 2]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(stored);
+
+            central.extend_from_slice(b"PK\x01\x02\x14\x00\x14\x00\x00\x00");
+            central.extend_from_slice(&method.to_le_bytes());
+            central.extend_from_slice(&[0; 8]);
+            central.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            central.extend_from_slice(&[0; 4]);
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&local.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let offset = data.len() as u32;
+        data.extend_from_slice(&central);
+        data.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    #[test]
+    fn tar_entries_pair_up() {
+        let data = tar(&[
+            ("echo.bf", "", b",[.,]"),
+            ("echo.in", "", b"hi"),
+            ("echo.expected", "", b"hi"),
+            ("loop.bf", "deep", b"+[-]"),
+            ("notes.txt", "", b"not a program"),
+        ]);
+        let programs = pair(untar(&data).unwrap());
+        assert_eq!(
+            programs,
+            vec![
+                Program {
+                    name: "deep/loop.bf".to_string(),
+                    source: "+[-]".to_string(),
+                    input: Vec::new(),
+                    expected: None,
+                },
+                Program {
+                    name: "echo.bf".to_string(),
+                    source: ",[.,]".to_string(),
+                    input: b"hi".to_vec(),
+                    expected: Some(b"hi".to_vec()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cut_short_tar_fails() {
+        let data = tar(&[("echo.bf", "", &[b'+'; 600])]);
+        assert!(untar(&data[..900]).is_err());
+    }
+
+    #[test]
+    fn zip_stored_and_deflated() {
+        let data = zip(&[("a.bf", 8, &DEFLATED), ("a.expected", 0, b"")]);
+        let files = unzip(&data).unwrap();
+        assert_eq!(files, vec![("a.bf".to_string(), b"+[>+]".to_vec()), ("a.expected".to_string(), Vec::new())]);
+    }
+
+    #[test]
+    fn damaged_zip_fails() {
+        let data = zip(&[("a.bf", 8, &DEFLATED)]);
+        for end in 0..data.len() {
+            assert!(unzip(&data[..end]).is_err(), "{} of {} bytes unzipped", end, data.len());
+        }
+        let data = zip(&[("a.bf", 8, &DEFLATED[..4])]);
+        assert!(unzip(&data).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+// Step mode: evaluated code is loaded but waits for :step, :next, :finish
+// or :continue instead of running straight away
+use super::error::RuntimeError;
+use super::{symbol_from_prompt, Interpreter, Prompt, Token};
+
+impl Interpreter {
+    // Leave freshly loaded code paused at its first instruction
+    pub fn set_step_mode(&mut self, on: bool) {
+        self.step_mode = on;
+    }
+
+    pub fn step_mode(&self) -> bool {
+        self.step_mode
+    }
+
+    // Index of the next instruction to run
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Byte offset into source() of the next instruction, the end of it
+    // once there's nothing left to run
+    pub fn source_offset(&self) -> usize {
+        self.spans.get(self.cursor).cloned().unwrap_or(self.source.len())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.tokens.len()
+    }
+
+    // Run n instructions, fewer if the program ends first
+    pub fn step(&mut self, n: u64) -> Result<(), RuntimeError> {
+        let mut left = n;
+        self.run_while(|_| {
+            if left == 0 {
+                return false;
+            }
+            left -= 1;
+            true
+        })
+    }
+
+    // One step, except a `[` runs its whole loop as a single step
+    pub fn step_over(&mut self) -> Result<(), RuntimeError> {
+        match self.tokens.get(self.cursor) {
+            Some(&Token::JumpForward(close)) => self.run_while(|i| i.cursor != close + 1),
+            _ => self.step(1),
+        }
+    }
+
+    // Run until the innermost loop around the cursor exits
+    // Outside any loop that's the rest of the program
+    pub fn finish_loop(&mut self) -> Result<(), RuntimeError> {
+        match self.enclosing_loop(self.cursor) {
+            Some((_, close)) => self.run_while(|i| i.cursor != close + 1),
+            None => self.run_while(|_| true),
+        }
+    }
+
+    // Run until the cursor reaches token target, a breakpoint that goes
+    // away once hit. Already being there means coming back around to it
+    pub fn run_to(&mut self, target: usize) -> Result<(), RuntimeError> {
+        let mut first = true;
+        self.run_while(|i| {
+            let go = first || i.cursor != target;
+            first = false;
+            go
+        })
+    }
+
+    // The first token at or after a byte offset into source()
+    pub fn token_at(&self, offset: usize) -> Option<usize> {
+        let i = self.spans.partition_point(|&span| span < offset);
+        if i < self.tokens.len() {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    // The loop a paused `[` is about to enter, otherwise the one around the cursor
+    pub fn current_loop(&self) -> Option<(usize, usize)> {
+        match self.tokens.get(self.cursor) {
+            Some(&Token::JumpForward(close)) => Some((self.cursor, close)),
+            _ => self.enclosing_loop(self.cursor),
+        }
+    }
+
+    // Jump straight past the current loop's `]` without running the rest of it
+    // False when the cursor isn't in a loop
+    pub fn skip_loop(&mut self) -> bool {
+        match self.current_loop() {
+            Some((_, close)) => {
+                self.cursor = close + 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Jump back to the current loop's `[`, which tests its cell again
+    pub fn restart_loop(&mut self) -> bool {
+        match self.current_loop() {
+            Some((open, _)) => {
+                self.cursor = open;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Token indices of the `[` and `]` of the innermost loop around token i
+    // A `[` isn't inside its own loop, it's the test on the way in
+    pub fn enclosing_loop(&self, i: usize) -> Option<(usize, usize)> {
+        let mut depth = 0;
+        for j in (0..i.min(self.tokens.len())).rev() {
+            match self.tokens[j] {
+                Token::JumpBackward(_) => depth += 1,
+                Token::JumpForward(close) if depth == 0 => return Some((j, close)),
+                Token::JumpForward(_) => depth -= 1,
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // Where step mode is paused, e.g. `🐛  #3 '[' at offset 3, loop #3..#8`
+    pub fn print_position(&self) {
+        println!("{}", self.render_position());
+    }
+
+    pub fn render_position(&self) -> String {
+        let prompt = symbol_from_prompt(Prompt::Debug);
+        if self.is_finished() {
+            return format!("{}  Finished", prompt);
+        }
+
+        let token = self.tokens[self.cursor];
+        let mut line = format!(
+            "{}  #{} '{}' at offset {}",
+            prompt,
+            self.cursor,
+            token,
+            self.spans[self.cursor]
+        );
+        if let Some((open, close)) = self.enclosing_loop(self.cursor) {
+            line.push_str(&format!(", loop #{}..#{}", open, close));
+        }
+        if let Some(condition) = self.condition_hit() {
+            line.push_str(&format!(", {} is true", condition));
+        }
+        line
+    }
+}
@@ -0,0 +1,271 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use super::io::IoHandler;
+use super::nested::Limits;
+use super::{Brain, Token};
+
+// A brainfuck variant: which characters mean what, plus instructions of
+// its own. Parser::set_dialect and Interpreter::set_dialect plug one in
+//
+//     struct Zero;
+//
+//     impl Dialect for Zero {
+//         fn name(&self) -> &str { "zero" }
+//         fn token(&self, c: char) -> Option<Token> {
+//             match c {
+//                 '0' => Some(Token::Extension('0')),
+//                 _ => Token::from_char(c),
+//             }
+//         }
+//         fn execute(&self, _c: char, brain: &mut Brain, _io: &mut dyn IoHandler) {
+//             brain.set_current(0);
+//         }
+//     }
+pub trait Dialect {
+    fn name(&self) -> &str;
+
+    // What a source character stands for, brainfuck's commands unless
+    // overridden. Instructions of the dialect's own are Token::Extension
+    fn token(&self, c: char) -> Option<Token> {
+        Token::from_char(c)
+    }
+
+    // Commands spelled with more than one character go here instead, and the
+    // parser takes the longest one matching at each point rather than
+    // asking token(). Anything that starts no command is skipped
+    fn words(&self) -> &[(&'static str, Token)] {
+        &[]
+    }
+
+    // Runs every Token::Extension that token() handed out
+    fn execute(&self, _c: char, _brain: &mut Brain, _io: &mut dyn IoHandler) {}
+
+    // What the interpreter actually calls, with what's left of the run's
+    // limits. Returns how many steps the instruction cost on top of its
+    // own, for one that runs other programs like nested::Host
+    fn execute_limited(
+        &self,
+        c: char,
+        brain: &mut Brain,
+        io: &mut dyn IoHandler,
+        _limits: &Limits,
+    ) -> u64 {
+        self.execute(c, brain, io);
+        0
+    }
+
+    // Called once a program has run to the end, for dialects that hold
+    // on to output
+    fn finish(&self, _io: &mut dyn IoHandler) {}
+}
+
+impl fmt::Debug for dyn Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Dialect({})", self.name())
+    }
+}
+
+// Plain brainfuck, what parsers and interpreters use without a dialect
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Brainfuck;
+
+impl Dialect for Brainfuck {
+    fn name(&self) -> &str {
+        "brainfuck"
+    }
+}
+
+// Boolfuck: every cell is a single bit. `+` flips it, `,` reads one bit
+// of input and `;` writes one, least significant bit of each byte first
+// There's no `-` or `.`. Cells stay bytes underneath, only ever 0 or 1
+#[derive(Debug, Default)]
+pub struct Boolfuck {
+    bits: RefCell<Bits>,
+}
+
+#[derive(Debug, Default)]
+struct Bits {
+    input: u8,
+    input_left: u8,
+    output: u8,
+    output_count: u8,
+}
+
+impl Boolfuck {
+    pub fn new() -> Boolfuck {
+        Boolfuck::default()
+    }
+}
+
+impl Dialect for Boolfuck {
+    fn name(&self) -> &str {
+        "boolfuck"
+    }
+
+    fn token(&self, c: char) -> Option<Token> {
+        match c {
+            '+' | ',' | ';' => Some(Token::Extension(c)),
+            '<' | '>' | '[' | ']' => Token::from_char(c),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, c: char, brain: &mut Brain, io: &mut dyn IoHandler) {
+        let mut bits = self.bits.borrow_mut();
+        match c {
+            '+' => {
+                let bit = brain.current() ^ 1;
+                brain.set_current(bit);
+            }
+            ',' => {
+                if bits.input_left == 0 {
+                    // Out of input reads as zeros
+                    bits.input = io.read().unwrap_or(0);
+                    bits.input_left = 8;
+                }
+                let bit = bits.input & 1;
+                bits.input >>= 1;
+                bits.input_left -= 1;
+                brain.set_current(bit);
+            }
+            _ => {
+                bits.output |= brain.current() << bits.output_count;
+                bits.output_count += 1;
+                if bits.output_count == 8 {
+                    io.write(bits.output);
+                    bits.output = 0;
+                    bits.output_count = 0;
+                }
+            }
+        }
+    }
+
+    // A last partial byte goes out padded with zeros
+    fn finish(&self, io: &mut dyn IoHandler) {
+        let mut bits = self.bits.borrow_mut();
+        if bits.output_count > 0 {
+            io.write(bits.output);
+        }
+        *bits = Bits::default();
+    }
+}
+
+// Spoon: brainfuck spelled as a binary prefix code
+// Leaves out Spoon's debug and exit commands
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Spoon;
+
+const SPOON: &[(&str, Token)] = &[
+    ("1", Token::DataIncrement),
+    ("000", Token::DataDecrement),
+    ("010", Token::PointerIncrement),
+    ("011", Token::PointerDecrement),
+    ("0011", Token::JumpBackward(0)),
+    ("00100", Token::JumpForward(0)),
+    ("001010", Token::Output),
+    ("0010110", Token::Input),
+];
+
+impl Dialect for Spoon {
+    fn name(&self) -> &str {
+        "spoon"
+    }
+
+    fn words(&self) -> &[(&'static str, Token)] {
+        SPOON
+    }
+}
+
+// Dialects by name, so a front end can offer whatever has been registered
+#[derive(Debug)]
+pub struct Registry {
+    dialects: Vec<Rc<dyn Dialect>>,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+impl Registry {
+    // Knows the dialects in this module to begin with
+    pub fn new() -> Registry {
+        Registry {
+            dialects: vec![Rc::new(Brainfuck), Rc::new(Boolfuck::new()), Rc::new(Spoon)],
+        }
+    }
+
+    // Replaces any dialect already registered under the same name
+    pub fn register(&mut self, dialect: Rc<dyn Dialect>) {
+        self.dialects.retain(|d| d.name() != dialect.name());
+        self.dialects.push(dialect);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn Dialect>> {
+        self.dialects.iter().find(|d| d.name() == name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.dialects.iter().map(|d| d.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::super::io::Buffer;
+    use super::super::Interpreter;
+    use super::{Brainfuck, Dialect, Registry};
+
+    // The one on the esolangs wiki. It stops four bits into the '\n', so
+    // that byte only comes out padded by finish()
+    const HELLO: &str = ";;;+;+;;+;+;+;+;+;+;;+;;+;;;+;;+;+;;+;;;+;;+;+;;+;+;;;;+;+;;+;;;+;;+;+;+;;;;;;;+;+;;+;;;+;+;;;+;+;;;;+;+;;+;;+;+;;+;;;+;;;+;;+;+;;+;;;+;+;;+;;+;+;+;;;;+;+;;;+;+;+;";
+
+    fn run(dialect: &Rc<dyn Dialect>, source: &str, input: &[u8]) -> Vec<u8> {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_dialect(Some(Rc::clone(dialect)));
+        interpreter.load_source(source).unwrap();
+        let buffer = Buffer::new(input);
+        let output = buffer.output();
+        interpreter.set_io(Box::new(buffer));
+        interpreter.interpret().unwrap();
+        let bytes = output.borrow().clone();
+        bytes
+    }
+
+    #[test]
+    fn boolfuck_says_hello() {
+        let boolfuck = Registry::new().get("boolfuck").unwrap();
+        assert_eq!(run(&boolfuck, HELLO, b""), b"Hello, world!\n");
+        // The half written byte went with the last run rather than
+        // turning up at the start of this one
+        assert_eq!(run(&boolfuck, HELLO, b""), b"Hello, world!\n");
+    }
+
+    #[test]
+    fn boolfuck_reads_and_writes_low_bits_first() {
+        let boolfuck = Registry::new().get("boolfuck").unwrap();
+        let echo = ",;".repeat(16);
+        assert_eq!(run(&boolfuck, &echo, b"hi"), b"hi");
+        // The low three bits of 'h' are 0, and past the input is zeros
+        assert_eq!(run(&boolfuck, ",;,;,;", b"h"), [0]);
+        assert_eq!(run(&boolfuck, &",;".repeat(8), b""), [0]);
+        // A set bit then three clear ones, padded out to 0b0000_0001
+        assert_eq!(run(&boolfuck, "+;+;;;", b""), [1]);
+    }
+
+    #[test]
+    fn registry_knows_only_what_it_was_given() {
+        let mut registry = Registry::new();
+        assert_eq!(registry.names(), ["brainfuck", "boolfuck", "spoon"]);
+        assert!(registry.get("ook").is_none());
+        assert!(registry.get("Brainfuck").is_none());
+
+        registry.register(Rc::new(Brainfuck));
+        assert_eq!(registry.names(), ["boolfuck", "spoon", "brainfuck"]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+use super::error::coded;
+use super::{load_program, Token};
+
+// Each source line followed by the tokens it turned into, with runs of the
+// same token folded and jump targets resolved
+//
+//        1 | +++[>+<-]
+//            0000  +  x3
+//            0003  [  -> 0008
+pub fn listing(source: &str) -> Result<String, String> {
+    let interpreter = load_program(source).map_err(|e| coded(e.code(), e))?;
+    let tokens = interpreter.tokens();
+    let spans = interpreter.spans();
+
+    let mut out = String::new();
+    let mut i = 0;
+    let mut line_start = 0;
+    for (n, line) in source.split('\n').enumerate() {
+        let line_end = line_start + line.len();
+        if line_end == source.len() && line.is_empty() && n > 0 {
+            break;
+        }
+        let _ = writeln!(out, "{:>6} | {}", n + 1, line.trim_end_matches('\r'));
+
+        while i < tokens.len() && spans[i] < line_end {
+            let run = run_length(tokens, spans, i, line_end);
+            let _ = writeln!(out, "         {:04}  {}", i, describe(tokens[i], run));
+            i += run;
+        }
+        line_start = line_end + 1;
+    }
+    Ok(out)
+}
+
+// How many copies of tokens[i] follow on the same line
+fn run_length(tokens: &[Token], spans: &[usize], i: usize, line_end: usize) -> usize {
+    if let Token::JumpForward(_) | Token::JumpBackward(_) = tokens[i] {
+        return 1;
+    }
+    let first = tokens[i].to_char();
+    tokens[i..]
+        .iter()
+        .zip(&spans[i..])
+        .take_while(|&(&token, &span)| span < line_end && token.to_char() == first)
+        .count()
+}
+
+fn describe(token: Token, run: usize) -> String {
+    match token {
+        Token::JumpForward(i) | Token::JumpBackward(i) => format!("{}  -> {:04}", token.to_char(), i),
+        _ if run > 1 => format!("{}  x{}", token.to_char(), run),
+        _ => token.to_char().to_string(),
+    }
+}
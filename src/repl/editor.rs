@@ -0,0 +1,134 @@
+// Line editing for the REPL on a terminal, by rustyline: arrow keys and
+// Home/End move around the line, Up and Down go through the lines typed this
+// session, Ctrl-A, Ctrl-E and the rest of the usual emacs keys edit it, Tab
+// runs complete::complete and brackets get hint::hint as they're typed
+//
+// It's behind the editor feature. Without that, or when stdin and stdout
+// aren't both a terminal, for_terminal is None and the REPL reads a plain
+// line at a time, so a program's own output and `,` see it the way they
+// always have
+#[cfg(feature = "editor")]
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+#[cfg(feature = "editor")]
+use rustyline::error::ReadlineError;
+#[cfg(feature = "editor")]
+use rustyline::Context;
+
+#[cfg(feature = "editor")]
+use super::{complete, hint, paint};
+
+// Lines kept for Up to go back through
+#[cfg(feature = "editor")]
+const MAX_HISTORY: usize = 1000;
+
+pub struct Editor {
+    #[cfg(feature = "editor")]
+    line: rustyline::Editor<Helper, rustyline::history::DefaultHistory>,
+}
+
+#[cfg(feature = "editor")]
+impl Editor {
+    // None unless stdin and stdout are both a terminal
+    pub fn for_terminal() -> Option<Editor> {
+        use std::io::IsTerminal;
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return None;
+        }
+        let config = rustyline::Config::builder()
+            .max_history_size(MAX_HISTORY)
+            .ok()?
+            .auto_add_history(false)
+            .build();
+        let mut line = rustyline::Editor::with_config(config).ok()?;
+        line.set_helper(Some(Helper));
+        Some(Editor { line })
+    }
+
+    // Read a line after prompt, None at the end of input and an empty line
+    // for Ctrl-C. Lines worth going back to should be handed to remember()
+    // afterwards. out is flushed first, rustyline draws on the terminal itself
+    pub fn read_line(&mut self, prompt: &str, out: &mut dyn Write) -> io::Result<Option<String>> {
+        out.flush()?;
+        match self.line.readline(prompt) {
+            Ok(line) => Ok(Some(line.trim().to_string())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    pub fn remember(&mut self, line: &str) {
+        if !line.is_empty() {
+            let _ = self.line.add_history_entry(line);
+        }
+    }
+}
+
+// Never made, for_terminal is always None
+#[cfg(not(feature = "editor"))]
+impl Editor {
+    pub fn for_terminal() -> Option<Editor> {
+        None
+    }
+
+    pub fn read_line(&mut self, _prompt: &str, _out: &mut dyn Write) -> io::Result<Option<String>> {
+        unreachable!("there's no editor without the editor feature")
+    }
+
+    pub fn remember(&mut self, _line: &str) {}
+}
+
+// What rustyline asks about the line being typed
+#[cfg(feature = "editor")]
+struct Helper;
+
+#[cfg(feature = "editor")]
+impl rustyline::Helper for Helper {}
+
+#[cfg(feature = "editor")]
+impl rustyline::validate::Validator for Helper {}
+
+#[cfg(feature = "editor")]
+impl rustyline::completion::Completer for Helper {
+    type Candidate = String;
+
+    // A space after the only candidate, unless it's a directory to go on into
+    fn complete(&self, line: &str, pos: usize, _: &Context) -> rustyline::Result<(usize, Vec<String>)> {
+        let (start, mut candidates) = complete::complete(line, pos);
+        if let [ref mut only] = candidates[..] {
+            if !only.ends_with('/') {
+                only.push(' ');
+            }
+        }
+        Ok((start, candidates))
+    }
+}
+
+#[cfg(feature = "editor")]
+impl rustyline::hint::Hinter for Helper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, _: usize, _: &Context) -> Option<String> {
+        hint::hint(line, 0)
+    }
+}
+
+// A stray `]` in red and the hint greyed out
+#[cfg(feature = "editor")]
+impl rustyline::highlight::Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+        Cow::Owned(hint::highlight(line, 0))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(paint("90", hint))
+    }
+
+    // Any key can make a `]` stray or not
+    fn highlight_char(&self, _: &str, _: usize, _: rustyline::highlight::CmdKind) -> bool {
+        true
+    }
+}
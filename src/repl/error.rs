@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fmt;
+
+use super::catalog::with_catalog;
+use super::json::Json;
+
+// Process exit statuses, so scripts can tell what went wrong
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_PARSE: i32 = 3;
+// A --strict run did something plain brainfuck lets slide, like moving
+// left of cell 0
+pub const EXIT_FAULT: i32 = 4;
+pub const EXIT_LIMIT: i32 = 5;
+pub const EXIT_IO: i32 = 6;
+
+// Codes for every diagnostic the CLI gives, for tools to tell failures
+// apart by instead of the wording, which can change. A code is never
+// reused for something else
+//
+//     E00xx  the program doesn't parse, see ParseError::code
+//     E02xx  a run stopped before the end, see RuntimeError::code
+//     E03xx  reading and writing files
+//     E04xx  the command line doesn't make sense
+//     W01xx  warnings, from check and about what a run was given
+pub const CODE_READ: &str = "E0301";
+pub const CODE_WRITE: &str = "E0302";
+// A test spec that doesn't parse, it exits EXIT_PARSE rather than EXIT_IO
+pub const CODE_SPEC: &str = "E0303";
+// serve and serve-http couldn't listen, or take a connection
+pub const CODE_LISTEN: &str = "E0304";
+// Someone was turned away at --max-clients
+pub const CODE_BUSY: &str = "E0305";
+// xcheck's reference interpreter didn't run
+pub const CODE_REFERENCE: &str = "E0306";
+// The REPL lost its terminal
+pub const CODE_TERMINAL: &str = "E0307";
+pub const CODE_USAGE: &str = "E0401";
+pub const CODE_UNDERFLOW: &str = "W0101";
+// --fix-brackets ran something other than what was written
+pub const CODE_REPAIRED: &str = "W0102";
+// A BRAINF_ variable that isn't a setting, see Config::warnings
+pub const CODE_UNKNOWN_SETTING: &str = "W0103";
+// An --alias character in the program that was read as a command
+pub const CODE_ALIASED: &str = "W0104";
+
+// Why a run stopped before the program was done
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeError {
+    StepLimit(u64),
+    // The watchdog saw this many steps go by without output or a cell
+    // changing
+    Stalled(u64),
+    // A loop with a quota, by the source offset of its `[`, used it all up
+    LoopQuota { offset: usize, limit: u64 },
+    // The io handler couldn't write output
+    Io(String),
+    // Waiting on a channel no machine is ever going to touch again, see
+    // channel::Pair
+    Deadlock,
+    // `<` on cell 0 with Interpreter::set_strict on
+    PointerUnderflow,
+}
+
+impl RuntimeError {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            RuntimeError::StepLimit(_)
+            | RuntimeError::Stalled(_)
+            | RuntimeError::LoopQuota { .. }
+            | RuntimeError::Deadlock => EXIT_LIMIT,
+            RuntimeError::Io(_) => EXIT_IO,
+            RuntimeError::PointerUnderflow => EXIT_FAULT,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match *self {
+            RuntimeError::Stalled(_) => "E0201",
+            RuntimeError::LoopQuota { .. } => "E0202",
+            RuntimeError::StepLimit(_) => "E0203",
+            RuntimeError::Deadlock => "E0204",
+            RuntimeError::PointerUnderflow => "E0205",
+            RuntimeError::Io(_) => CODE_WRITE,
+        }
+    }
+
+    // offset is where in source the run stopped
+    pub fn to_json(&self, source: &str, offset: usize) -> Json {
+        to_json("runtime", self.code(), &self.to_string(), Some((source, offset)), self.exit_code())
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&with_catalog(|catalog| catalog.runtime_error(self)))
+    }
+}
+
+impl Error for RuntimeError {}
+
+// A whole program that didn't load or didn't finish, for the subcommands
+// that run one without an interpreter of their own and only need to say why
+// and exit with the right status
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    // The first ParseError
+    Parse(ParseError),
+    // Already worded, a dialect that isn't there in a replay file
+    Load(String),
+    Run(RuntimeError),
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Failure::Parse(_) | Failure::Load(_) => EXIT_PARSE,
+            Failure::Run(ref e) => e.exit_code(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Failure::Parse(ref e) => e.code(),
+            Failure::Load(_) => CODE_READ,
+            Failure::Run(ref e) => e.code(),
+        }
+    }
+}
+
+impl From<ParseError> for Failure {
+    fn from(e: ParseError) -> Failure {
+        Failure::Parse(e)
+    }
+}
+
+impl From<RuntimeError> for Failure {
+    fn from(e: RuntimeError) -> Failure {
+        Failure::Run(e)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Parse(ref e) => e.fmt(f),
+            Failure::Load(ref message) => f.write_str(message),
+            Failure::Run(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for Failure {}
+
+// Why a program couldn't be loaded. Offsets are bytes into its source
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    // A `]` with nothing to close
+    UnbalancedClose(usize),
+    // A `[` that's never closed
+    Unclosed(usize),
+    // Strict comments found something that isn't code or whitespace
+    NotCode(usize, char),
+}
+
+impl ParseError {
+    pub fn offset(&self) -> usize {
+        match *self {
+            ParseError::UnbalancedClose(offset)
+            | ParseError::Unclosed(offset)
+            | ParseError::NotCode(offset, _) => offset,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseError::UnbalancedClose(_) => "E0001",
+            ParseError::Unclosed(_) => "E0002",
+            ParseError::NotCode(..) => "E0003",
+        }
+    }
+
+    // What's wrong without saying where, for when the position is shown
+    // some other way
+    pub fn message(&self) -> String {
+        with_catalog(|catalog| catalog.parse_message(self))
+    }
+
+    pub fn to_json(&self, source: &str) -> Json {
+        to_json("parse", self.code(), &self.message(), Some((source, self.offset())), EXIT_PARSE)
+    }
+
+    pub(super) fn shifted_back(self, by: usize) -> ParseError {
+        match self {
+            ParseError::UnbalancedClose(offset) => ParseError::UnbalancedClose(offset - by),
+            ParseError::Unclosed(offset) => ParseError::Unclosed(offset - by),
+            ParseError::NotCode(offset, c) => ParseError::NotCode(offset - by, c),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&with_catalog(|catalog| catalog.parse_error(self)))
+    }
+}
+
+impl Error for ParseError {}
+
+// 1-based line and column of a byte offset into source, columns counting
+// chars rather than bytes, the way editors do
+pub fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[start..].chars().count() + 1)
+}
+
+// One error the way `--error-format json` and serve-http give it. Every key
+// is always there, null when it doesn't apply, so the shape never changes:
+//
+//     {"kind": "parse", "code": "E0002", "message": "Unclosed '['", "offset": 4, "line": 1, "column": 5, "exit_code": 3}
+//
+// kind is "parse", "runtime", "io", "usage" or "warning", code one of the
+// codes above. at is the source and a byte
+// offset into it, line and column come from position. `run --history`
+// puts a "history" on the end of runtime errors, see Interpreter::recent_json
+pub fn to_json(kind: &str, code: &str, message: &str, at: Option<(&str, usize)>, exit_code: i32) -> Json {
+    let (offset, line, column) = match at {
+        Some((source, offset)) => {
+            let (line, column) = position(source, offset);
+            (Json::from(offset), Json::from(line), Json::from(column))
+        }
+        None => (Json::Null, Json::Null, Json::Null),
+    };
+    Json::object(vec![
+        ("kind", Json::from(kind)),
+        ("code", Json::from(code)),
+        ("message", Json::from(message)),
+        ("offset", offset),
+        ("line", line),
+        ("column", column),
+        ("exit_code", Json::from(exit_code as u64)),
+    ])
+}
+
+// The same as text, "error[E0203]: Stopped after the 1,000 step limit"
+pub fn coded<T: fmt::Display>(code: &str, message: T) -> String {
+    format!("error[{}]: {}", code, message)
+}
+
+// The same for a W code, "warning[W0102]: ..."
+pub fn warned<T: fmt::Display>(code: &str, message: T) -> String {
+    format!("warning[{}]: {}", code, message)
+}
+
+// The wording for CODE_READ and CODE_WRITE from the catalog, what being a
+// path or "stdin"
+pub fn read_error<T: fmt::Display, E: fmt::Display>(what: T, error: E) -> String {
+    with_catalog(|catalog| catalog.read_error(&what.to_string(), &error.to_string()))
+}
+
+pub fn write_error<T: fmt::Display, E: fmt::Display>(what: T, error: E) -> String {
+    with_catalog(|catalog| catalog.write_error(&what.to_string(), &error.to_string()))
+}
@@ -0,0 +1,50 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::Brain;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Delimited {
+    Csv,
+    Tsv,
+}
+
+impl Delimited {
+    fn separator(self) -> char {
+        match self {
+            Delimited::Csv => ',',
+            Delimited::Tsv => '\t',
+        }
+    }
+}
+
+// index,value,ascii rows for every nonzero cell, for spreadsheets and scripts
+pub fn write_table<W: Write>(brain: &Brain, kind: Delimited, mut out: W) -> io::Result<()> {
+    let sep = kind.separator();
+    writeln!(out, "index{}value{}ascii", sep, sep)?;
+    for i in 0..brain.len() {
+        let cell = brain.get(i);
+        if cell != 0 {
+            writeln!(out, "{}{}{}{}{}", i, sep, cell, sep, ascii(cell, kind))?;
+        }
+    }
+    out.flush()
+}
+
+pub fn export<P: AsRef<Path>>(brain: &Brain, kind: Delimited, path: P) -> io::Result<()> {
+    write_table(brain, kind, BufWriter::new(File::create(path)?))
+}
+
+// Printable ASCII only, quoted when CSV would trip over it
+fn ascii(cell: u8, kind: Delimited) -> String {
+    let c = cell as char;
+    if !(c == ' ' || c.is_ascii_graphic()) {
+        return String::new();
+    }
+    match (kind, c) {
+        (Delimited::Csv, '"') => "\"\"\"\"".to_string(),
+        (Delimited::Csv, ',') | (Delimited::Csv, ' ') => format!("\"{}\"", c),
+        _ => c.to_string(),
+    }
+}
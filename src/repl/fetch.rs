@@ -0,0 +1,142 @@
+// Programs straight from http:// and https:// URLs, so a published one can
+// be tried without saving it first. Plain HTTP is spoken here, HTTPS goes
+// through curl since there's no TLS in here to speak it with
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use super::decode_source;
+
+// Biggest download unless --max-download says otherwise, far more than
+// any program worth running needs
+pub const MAX_DOWNLOAD: usize = 1 << 20;
+
+// Redirects followed before giving up on a URL
+const MAX_REDIRECTS: usize = 5;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+// The body of url as program text, no more than max_bytes of it, see
+// decode_source
+pub fn fetch(url: &str, max_bytes: usize) -> io::Result<String> {
+    let text = |body: Vec<u8>| Ok(decode_source(&body));
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        if url.starts_with("https://") {
+            return curl(&url, max_bytes).and_then(text);
+        }
+        match get(&url, max_bytes)? {
+            Response::Body(body) => return text(body),
+            Response::Redirect(location) => url = resolve(&url, &location),
+        }
+    }
+    Err(io::Error::other(format!("Gave up after {} redirects", MAX_REDIRECTS)))
+}
+
+enum Response {
+    Body(Vec<u8>),
+    Redirect(String),
+}
+
+// (host and maybe port, path) of an http:// URL
+fn split(url: &str) -> (&str, &str) {
+    let rest = url.trim_start_matches("http://");
+    let rest = rest.split('#').next().unwrap_or(rest);
+    match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    }
+}
+
+// A Location header against the URL it came from, which can leave off
+// everything but the path
+fn resolve(url: &str, location: &str) -> String {
+    if is_url(location) {
+        location.to_string()
+    } else if location.starts_with('/') {
+        format!("http://{}{}", split(url).0, location)
+    } else {
+        let base = url.rfind('/').filter(|&i| i > "http://".len()).map_or(url, |i| &url[..i]);
+        format!("{}/{}", base, location)
+    }
+}
+
+// HTTP/1.0 so the body comes as it is, read until the server hangs up
+fn get(url: &str, max_bytes: usize) -> io::Result<Response> {
+    let (host, path) = split(url);
+    if host.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} has no host", url)));
+    }
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: brainf\r\nConnection: close\r\n\r\n",
+        path, host
+    )?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let code: u16 = status.split_whitespace().nth(1).and_then(|code| code.parse().ok()).unwrap_or(0);
+    let mut location = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("location") {
+                location = Some(value.trim().to_string());
+            }
+        }
+    }
+    match (code, location) {
+        (301 | 302 | 303 | 307 | 308, Some(location)) => Ok(Response::Redirect(location)),
+        (200..=299, _) => read_capped(reader, max_bytes).map(Response::Body),
+        _ => Err(io::Error::other(format!("The server said {}", status.trim()))),
+    }
+}
+
+fn curl(url: &str, max_bytes: usize) -> io::Result<Vec<u8>> {
+    let mut child = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--proto", "=http,https", "--max-time", &TIMEOUT.as_secs().to_string()])
+        .args(["--max-filesize", &max_bytes.to_string(), "--", url])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => io::Error::new(e.kind(), "https:// URLs need curl installed"),
+            _ => e,
+        })?;
+    let body = read_capped(child.stdout.take().expect("stdout was piped"), max_bytes);
+    if body.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    let body = body?;
+    if !status.success() {
+        return Err(io::Error::other(format!("curl failed with {}", status)));
+    }
+    Ok(body)
+}
+
+// Servers don't always say how big a body is, so it's counted on the way in
+fn read_capped<R: Read>(reader: R, max_bytes: usize) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    reader.take(max_bytes as u64 + 1).read_to_end(&mut body)?;
+    if body.len() > max_bytes {
+        return Err(io::Error::other(format!(
+            "It's over the {} byte limit, --max-download can raise it",
+            max_bytes
+        )));
+    }
+    Ok(body)
+}
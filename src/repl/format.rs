@@ -0,0 +1,186 @@
+use std::fmt;
+
+use super::catalog::usage_message;
+use super::Brain;
+
+// Turns the tape into text for print_brain
+// Implement this to render the tape however a front end likes
+pub trait BrainFormatter {
+    fn render(&self, brain: &Brain, out: &mut dyn fmt::Write) -> fmt::Result;
+}
+
+// Every cell, pointer in brackets: ` 0 65 [8]`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FullFormatter;
+
+impl BrainFormatter for FullFormatter {
+    fn render(&self, brain: &Brain, out: &mut dyn fmt::Write) -> fmt::Result {
+        render_cells(brain, 0, brain.len(), out)
+    }
+}
+
+// Like FullFormatter but big tapes only show limit cells around the pointer
+#[derive(Copy, Clone, Debug)]
+pub struct WindowFormatter {
+    pub limit: usize,
+}
+
+impl Default for WindowFormatter {
+    fn default() -> WindowFormatter {
+        WindowFormatter { limit: 64 }
+    }
+}
+
+impl BrainFormatter for WindowFormatter {
+    fn render(&self, brain: &Brain, out: &mut dyn fmt::Write) -> fmt::Result {
+        let len = brain.len();
+        if len <= self.limit {
+            return render_cells(brain, 0, len, out);
+        }
+
+        let limit = self.limit.max(1);
+        let start = brain.ptr().saturating_sub(limit / 2).min(len - limit);
+        let end = start + limit;
+
+        if start > 0 {
+            write!(out, " …")?;
+        }
+        render_cells(brain, start, end, out)?;
+        if end < len {
+            write!(out, " …")?;
+        }
+
+        let nonzero = (0..start)
+            .chain(end..len)
+            .filter(|&i| brain.get(i) != 0)
+            .count();
+        write!(
+            out,
+            " ({} more cells, {} nonzero)",
+            thousands(len - limit),
+            thousands(nonzero)
+        )
+    }
+}
+
+// Only the nonzero cells as index:value, plus where the pointer is
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CompactFormatter;
+
+impl BrainFormatter for CompactFormatter {
+    fn render(&self, brain: &Brain, out: &mut dyn fmt::Write) -> fmt::Result {
+        for i in 0..brain.len() {
+            let cell = brain.get(i);
+            if cell != 0 {
+                write!(out, " {}:{}", i, cell)?;
+            }
+        }
+        write!(out, " @{}", brain.ptr())
+    }
+}
+
+// How RangeFormatter writes each cell
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+}
+
+impl Radix {
+    // `hex` or `16` and so on
+    pub fn parse(name: &str) -> Result<Radix, String> {
+        match name {
+            "10" | "dec" => Ok(Radix::Decimal),
+            "16" | "hex" => Ok(Radix::Hex),
+            "8" | "oct" => Ok(Radix::Octal),
+            "2" | "bin" => Ok(Radix::Binary),
+            _ => Err(usage_message("radix", &[&name])),
+        }
+    }
+
+    // Padded to the widest a cell can be, so columns line up
+    fn write(self, n: u8, out: &mut dyn fmt::Write) -> fmt::Result {
+        match self {
+            Radix::Decimal => write!(out, "{}", n),
+            Radix::Hex => write!(out, "{:02x}", n),
+            Radix::Octal => write!(out, "{:03o}", n),
+            Radix::Binary => write!(out, "{:08b}", n),
+        }
+    }
+}
+
+// Cells from start up to end in any radix, pointer in brackets. No end
+// goes to the end of the tape, and cells past it show as the 0 they are
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RangeFormatter {
+    pub start: usize,
+    pub end: Option<usize>,
+    pub radix: Radix,
+}
+
+impl RangeFormatter {
+    // `4..12`, `4..=11`, `4..` or `..12`, like a Rust range
+    pub fn parse_range(&mut self, text: &str) -> Result<(), String> {
+        let bad = || usage_message("range", &[&text]);
+        let (start, end) = text.split_once("..").ok_or_else(bad)?;
+        let number = |n: &str| n.parse::<usize>().map_err(|_| bad());
+        self.start = if start.is_empty() { 0 } else { number(start)? };
+        self.end = match end.strip_prefix('=') {
+            Some(last) => Some(number(last)? + 1),
+            None if end.is_empty() => None,
+            None => Some(number(end)?),
+        };
+        if self.end.is_some_and(|end| end < self.start) {
+            return Err(bad());
+        }
+        Ok(())
+    }
+}
+
+impl BrainFormatter for RangeFormatter {
+    fn render(&self, brain: &Brain, out: &mut dyn fmt::Write) -> fmt::Result {
+        let end = self.end.unwrap_or_else(|| brain.len().max(self.start));
+        if self.start > 0 {
+            write!(out, " {}:", self.start)?;
+        }
+        for i in self.start..end {
+            out.write_str(if brain.ptr() == i { " [" } else { " " })?;
+            self.radix.write(brain.get(i), out)?;
+            if brain.ptr() == i {
+                out.write_str("]")?;
+            }
+        }
+        // Otherwise there'd be no telling where it was
+        if brain.ptr() < self.start || brain.ptr() >= end {
+            write!(out, " @{}", brain.ptr())?;
+        }
+        Ok(())
+    }
+}
+
+fn render_cells(brain: &Brain, start: usize, end: usize, out: &mut dyn fmt::Write) -> fmt::Result {
+    for i in start..end {
+        if brain.ptr() == i {
+            write!(out, " [{}]", brain.get(i))?;
+        } else {
+            write!(out, " {}", brain.get(i))?;
+        }
+    }
+    Ok(())
+}
+
+// 4972 -> "4,972"
+pub fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,113 @@
+// Throw generated inputs at a program to find the ones it doesn't cope
+// with: runs that hit a limit, which is usually a hang waiting on input
+// it'll never get, runs that fail some other way, and the input that got
+// the most output out of it
+use super::error::RuntimeError;
+use super::io::Buffer;
+use super::random::Rng;
+use super::Interpreter;
+
+#[derive(Copy, Clone, Debug)]
+pub struct FuzzOptions {
+    pub runs: usize,
+    pub seed: u64,
+    // Longest input to try, in bytes
+    pub max_len: usize,
+}
+
+impl Default for FuzzOptions {
+    fn default() -> FuzzOptions {
+        FuzzOptions {
+            runs: 1000,
+            seed: 0,
+            max_len: 64,
+        }
+    }
+}
+
+// Inputs that broke the program, one group per way it broke
+#[derive(Debug)]
+pub struct Failure {
+    pub error: RuntimeError,
+    pub count: usize,
+    // The shortest input that did it
+    pub input: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub runs: usize,
+    pub failures: Vec<Failure>,
+    // The input that got the most output and how much there was
+    pub most_output: Option<(Vec<u8>, usize)>,
+}
+
+// interpreter should have the program loaded and its limits set, each run
+// starts over from a blank tape
+pub fn fuzz(interpreter: &mut Interpreter, options: &FuzzOptions) -> Report {
+    let mut rng = Rng::new(options.seed);
+    let mut report = Report::default();
+    for _ in 0..options.runs {
+        let input = generate(&mut rng, options.max_len);
+        interpreter.rewind();
+        let buffer = Buffer::new(&input);
+        let output = buffer.output();
+        interpreter.set_io(Box::new(buffer));
+        let result = interpreter.interpret();
+        report.runs += 1;
+
+        let written = output.borrow().len();
+        // The shortest input when several get the same amount out
+        let better = report.most_output.as_ref().is_none_or(|&(ref best, most)| {
+            written > most || (written == most && input.len() < best.len())
+        });
+        if better {
+            report.most_output = Some((input.clone(), written));
+        }
+        if let Err(error) = result {
+            report.add(error, input);
+        }
+    }
+    report
+}
+
+impl Report {
+    fn add(&mut self, error: RuntimeError, input: Vec<u8>) {
+        // Grouped by kind of error, not the exact numbers in it
+        let same = |a: &RuntimeError| std::mem::discriminant(a) == std::mem::discriminant(&error);
+        match self.failures.iter_mut().find(|failure| same(&failure.error)) {
+            Some(failure) => {
+                failure.count += 1;
+                if input.len() < failure.input.len() {
+                    failure.input = input;
+                    failure.error = error;
+                }
+            }
+            None => self.failures.push(Failure {
+                error,
+                count: 1,
+                input,
+            }),
+        }
+    }
+}
+
+// Different shapes of input catch different mistakes: nothing at all,
+// text with and without line endings, numbers, the edge bytes 0, 10 and
+// 255 that programs tend to treat specially, and plain noise
+pub fn generate(rng: &mut Rng, max_len: usize) -> Vec<u8> {
+    let len = rng.below(max_len as u64 + 1) as usize;
+    match rng.below(7) {
+        0 => Vec::new(),
+        1 => (0..len).map(|_| b' ' + rng.below(95) as u8).collect(),
+        2 => {
+            let mut line: Vec<u8> = (0..len).map(|_| b'a' + rng.below(26) as u8).collect();
+            line.push(b'\n');
+            line
+        }
+        3 => (0..len.max(1)).map(|_| b'0' + rng.below(10) as u8).collect(),
+        4 => (0..len).map(|_| *rng.pick(&[0, 10, 13, 255, 127, 128])).collect(),
+        5 => vec![rng.byte(); len],
+        _ => (0..len).map(|_| rng.byte()).collect(),
+    }
+}
@@ -0,0 +1,76 @@
+// Random programs that always parse, for throwing at two ways of running
+// the same code and checking they agree. Plenty of them never finish, so
+// run them under a step limit
+use super::random::Rng;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GenerateOptions {
+    pub seed: u64,
+    // About how many commands, brackets included
+    pub size: usize,
+    // How deep loops can nest
+    pub max_depth: usize,
+    // Percent of commands that are `,` or `.`
+    pub io_percent: u64,
+    // Percent chance of opening a loop wherever there's room for one
+    pub loop_percent: u64,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> GenerateOptions {
+        GenerateOptions {
+            seed: 0,
+            size: 100,
+            max_depth: 3,
+            io_percent: 10,
+            loop_percent: 10,
+        }
+    }
+}
+
+// The same options always give the same program
+pub fn generate(options: &GenerateOptions) -> String {
+    let mut generator = Generator {
+        rng: Rng::new(options.seed),
+        options: *options,
+        out: String::new(),
+    };
+    generator.body(options.size, 0);
+    generator.out
+}
+
+struct Generator {
+    rng: Rng,
+    options: GenerateOptions,
+    out: String,
+}
+
+impl Generator {
+    // About budget commands at this depth, loops and all
+    fn body(&mut self, budget: usize, depth: usize) {
+        let mut left = budget;
+        while left > 0 {
+            // A loop needs room for its brackets and something inside
+            if left >= 3 && depth < self.options.max_depth && self.rng.chance(self.options.loop_percent) {
+                let inner = 1 + self.rng.below((left - 2) as u64) as usize;
+                self.out.push('[');
+                self.body(inner, depth + 1);
+                // Counting down before going round again makes loops that
+                // finish a lot more common than they'd otherwise be
+                if self.rng.chance(50) {
+                    self.out.push('-');
+                }
+                self.out.push(']');
+                left -= inner + 2;
+            } else {
+                let c = if self.rng.chance(self.options.io_percent) {
+                    *self.rng.pick(&[',', '.'])
+                } else {
+                    *self.rng.pick(&['+', '-', '<', '>'])
+                };
+                self.out.push(c);
+                left -= 1;
+            }
+        }
+    }
+}
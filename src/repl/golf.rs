@@ -0,0 +1,445 @@
+// Make a program shorter without changing what it prints. Each pass is a
+// known rewrite, and each one's result is run against the program before
+// it, on the same generated inputs fuzz-io uses, and only kept if every
+// output matches:
+//
+// - comments: everything that isn't one of the eight commands goes
+// - cancellation: `+-`, `-+` and `><` next to each other, and loops that
+//   can never run because they come first or straight after another loop
+// - constants: a long run of `+` or `-` becomes a multiplying loop like
+//   `>++++++++[<++++++++>-]<`, using a neighbour that's known to be 0
+// - re-rolling: a block repeated over and over becomes a counted loop
+//   around one copy, with a spare cell as the counter
+//
+// The last two follow what's on the tape from the start, running loops
+// that only touch known cells, and stop once it can't be known
+use std::collections::HashMap;
+
+use super::fuzz;
+use super::random::Rng;
+
+// Steps the program before a pass gets on each input, the one after gets
+// twice that since multiplying loops take longer than what they replace
+pub const VERIFY_STEPS: u64 = 1_000_000;
+
+// How many generated inputs each pass is checked on, for programs with
+// `,` in them. Programs without one are just run once
+const VERIFY_INPUTS: usize = 32;
+
+// Code in, shorter code out
+type Rewrite = fn(&[u8]) -> Vec<u8>;
+
+const PASSES: &[(&str, Rewrite)] = &[
+    ("comments", strip_comments),
+    ("cancellation", cancel),
+    ("constants", |code: &[u8]| rewrite(code, true, false)),
+    ("re-rolling", |code: &[u8]| rewrite(code, false, true)),
+];
+
+#[derive(Debug)]
+pub struct Pass {
+    pub name: &'static str,
+    // Bytes shorter than the program before it
+    pub saved: usize,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    // Same output on this many inputs
+    Verified(usize),
+    // The program before the pass didn't finish on any input within
+    // VERIFY_STEPS, so there was nothing to compare. It's kept anyway
+    Unverified,
+    // Printed something else on this input, so the pass was thrown away
+    Differs(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct Golfed {
+    pub program: String,
+    pub passes: Vec<Pass>,
+}
+
+pub fn golf(source: &str) -> Golfed {
+    let inputs = inputs(source);
+    let mut code = source.as_bytes().to_vec();
+    let mut passes = Vec::new();
+    for &(name, pass) in PASSES {
+        let shorter = pass(&code);
+        let outcome = verify(&code, &shorter, &inputs);
+        let saved = code.len() - shorter.len().min(code.len());
+        match outcome {
+            Outcome::Differs(_) => passes.push(Pass { name, saved: 0, outcome }),
+            _ => {
+                passes.push(Pass { name, saved, outcome });
+                if shorter.len() < code.len() {
+                    code = shorter;
+                }
+            }
+        }
+    }
+    Golfed {
+        program: String::from_utf8_lossy(&code).into_owned(),
+        passes,
+    }
+}
+
+fn inputs(source: &str) -> Vec<Vec<u8>> {
+    if !source.contains(',') {
+        return vec![Vec::new()];
+    }
+    let mut rng = Rng::new(0);
+    let mut inputs = vec![Vec::new()];
+    inputs.extend((1..VERIFY_INPUTS).map(|_| fuzz::generate(&mut rng, 64)));
+    inputs
+}
+
+fn verify(before: &[u8], after: &[u8], inputs: &[Vec<u8>]) -> Outcome {
+    let (before, after) = (String::from_utf8_lossy(before), String::from_utf8_lossy(after));
+    let mut compared = 0;
+    for input in inputs {
+        let expected = match super::capture_within(&before, input, Some(VERIFY_STEPS)) {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        match super::capture_within(&after, input, Some(VERIFY_STEPS * 2)) {
+            Ok(ref output) if *output == expected => compared += 1,
+            _ => return Outcome::Differs(input.clone()),
+        }
+    }
+    if compared == 0 {
+        Outcome::Unverified
+    } else {
+        Outcome::Verified(compared)
+    }
+}
+
+fn strip_comments(code: &[u8]) -> Vec<u8> {
+    code.iter().cloned().filter(|c| b"+-<>[].,".contains(c)).collect()
+}
+
+// `<>` isn't on the list, `<` on cell 0 stays put so the `>` still moves
+fn cancel(code: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(code.len());
+    let mut i = 0;
+    while i < code.len() {
+        let c = code[i];
+        match (out.last(), c) {
+            (Some(b'+'), b'-') | (Some(b'-'), b'+') | (Some(b'>'), b'<') => {
+                out.pop();
+            }
+            // The cell is 0 at the start and after every loop
+            (None, b'[') | (Some(b']'), b'[') => i = partner(code, i),
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+// The `]` for the `[` at open. Every pass gets a balanced program, golf is
+// only run on ones that load
+fn partner(code: &[u8], open: usize) -> usize {
+    let mut depth = 0;
+    for (i, &c) in code.iter().enumerate().skip(open) {
+        match c {
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => (),
+        }
+    }
+    code.len() - 1
+}
+
+// The constants and re-rolling passes, which both need to know which cells
+// are 0. Only code outside loops is rewritten
+fn rewrite(code: &[u8], constants: bool, reroll: bool) -> Vec<u8> {
+    let mut tape = Tape::start();
+    let mut out = Vec::with_capacity(code.len());
+    let mut i = 0;
+    while i < code.len() {
+        let c = code[i];
+        if reroll {
+            if let Some((len, times, rolled)) = roll(&code[i..], &tape) {
+                for _ in 0..times {
+                    tape.run(&code[i..i + len]);
+                }
+                out.extend(rolled);
+                i += len * times;
+                continue;
+            }
+        }
+        if constants && (c == b'+' || c == b'-') {
+            let end = i + code[i..].iter().take_while(|&&c| c == b'+' || c == b'-').count();
+            out.extend(add(&tape, net(&code[i..end]), &code[i..end]));
+            tape.run(&code[i..end]);
+            i = end;
+            continue;
+        }
+        let end = if c == b'[' { partner(code, i) + 1 } else { i + 1 };
+        tape.run(&code[i..end]);
+        out.extend(&code[i..end]);
+        i = end;
+    }
+    out
+}
+
+// What a run of `+` and `-` adds to the cell, wrapping
+fn net(run: &[u8]) -> u8 {
+    run.iter().fold(0u8, |n, &c| if c == b'+' { n.wrapping_add(1) } else { n.wrapping_sub(1) })
+}
+
+fn repeat(c: u8, n: usize) -> Vec<u8> {
+    vec![c; n]
+}
+
+// The shortest way to add n, going the other way round past 0 if it's
+// closer
+fn direct(n: u8) -> Vec<u8> {
+    if n <= 128 {
+        repeat(b'+', n as usize)
+    } else {
+        repeat(b'-', 256 - n as usize)
+    }
+}
+
+// The shortest code that adds n to the current cell, original included
+// The counter can go either side, as long as it's known to be 0 there
+fn add(tape: &Tape, n: u8, original: &[u8]) -> Vec<u8> {
+    let mut best = direct(n);
+    if original.len() < best.len() {
+        best = original.to_vec();
+    }
+    let sides = [(1, b'>', b'<'), (-1, b'<', b'>')];
+    for &(side, there, back) in &sides {
+        if tape.get(tape.ptr + side) != Some(0) {
+            continue;
+        }
+        // a times round adding or taking b, then whatever's left over
+        for a in 2..=48u8 {
+            for b in 1..=48u8 {
+                for &sign in b"+-" {
+                    let product = a.wrapping_mul(b);
+                    let product = if sign == b'+' { product } else { product.wrapping_neg() };
+                    let rest = direct(n.wrapping_sub(product));
+                    if 6 + a as usize + b as usize + rest.len() >= best.len() {
+                        continue;
+                    }
+                    let mut code = vec![there];
+                    code.extend(repeat(b'+', a as usize));
+                    code.extend(&[b'[', back]);
+                    code.extend(repeat(sign, b as usize));
+                    code.extend(&[there, b'-', b']', back]);
+                    code.extend(rest);
+                    best = code;
+                }
+            }
+        }
+    }
+    best
+}
+
+// Whether code starts with a block repeated often enough that looping it is
+// shorter: (length of the block, how many times, the loop). The block has
+// to come back to the cell it started on and leave a cell past either end
+// of where it goes that's 0, for the counter
+fn roll(code: &[u8], tape: &Tape) -> Option<(usize, usize, Vec<u8>)> {
+    let mut best: Option<(usize, usize, Vec<u8>)> = None;
+    for len in 2..=code.len().min(32) {
+        let block = &code[..len];
+        if block.contains(&b'[') || block.contains(&b']') || block.iter().all(|&c| c == block[0]) {
+            continue;
+        }
+        let times = code.chunks(len).take_while(|&chunk| chunk == block).count().min(255);
+        if times < 2 {
+            continue;
+        }
+        let (mut at, mut lo, mut hi) = (0i64, 0i64, 0i64);
+        for &c in block {
+            match c {
+                b'>' => at += 1,
+                b'<' => at -= 1,
+                _ => (),
+            }
+            lo = lo.min(at);
+            hi = hi.max(at);
+        }
+        if at != 0 || tape.ptr + lo < 0 {
+            continue;
+        }
+        let sides = [(hi + 1, b'>', b'<'), (lo - 1, b'<', b'>')];
+        for &(counter, there, back) in &sides {
+            if tape.get(tape.ptr + counter) != Some(0) {
+                continue;
+            }
+            let moves = counter.unsigned_abs() as usize;
+            let mut rolled = repeat(there, moves);
+            rolled.extend(direct(times as u8));
+            rolled.push(b'[');
+            rolled.extend(repeat(back, moves));
+            rolled.extend(block);
+            rolled.extend(repeat(there, moves));
+            rolled.extend(b"-]");
+            rolled.extend(repeat(back, moves));
+            let shorter = best.as_ref().map_or(len * times, |(_, _, code)| code.len());
+            if rolled.len() < shorter && rolled.len() < len * times {
+                best = Some((len, times, rolled));
+            }
+        }
+    }
+    best
+}
+
+// What's known about the tape partway through a program. Cells not in the
+// map are 0 until lost is set, after which nothing is known at all
+#[derive(Clone)]
+struct Tape {
+    cells: HashMap<i64, Option<u8>>,
+    ptr: i64,
+    lost: bool,
+}
+
+// Steps a loop gets to be run for real before it's worked out roughly
+const LOOP_BUDGET: usize = 100_000;
+
+impl Tape {
+    fn start() -> Tape {
+        Tape {
+            cells: HashMap::new(),
+            ptr: 0,
+            lost: false,
+        }
+    }
+
+    fn get(&self, at: i64) -> Option<u8> {
+        if self.lost || at < 0 {
+            return None;
+        }
+        self.cells.get(&at).cloned().unwrap_or(Some(0))
+    }
+
+    fn set(&mut self, at: i64, value: Option<u8>) {
+        self.cells.insert(at, value);
+    }
+
+    fn run(&mut self, code: &[u8]) {
+        let mut i = 0;
+        while i < code.len() && !self.lost {
+            match code[i] {
+                b'[' => {
+                    let close = partner(code, i);
+                    self.run_loop(&code[i + 1..close]);
+                    i = close;
+                }
+                c => self.step(c),
+            }
+            i += 1;
+        }
+    }
+
+    fn step(&mut self, c: u8) {
+        let ptr = self.ptr;
+        match c {
+            b'+' => self.set(ptr, self.get(ptr).map(|n| n.wrapping_add(1))),
+            b'-' => self.set(ptr, self.get(ptr).map(|n| n.wrapping_sub(1))),
+            b'>' => self.ptr += 1,
+            b'<' => self.ptr = (ptr - 1).max(0),
+            b',' => self.set(ptr, None),
+            _ => (),
+        }
+    }
+
+    fn run_loop(&mut self, body: &[u8]) {
+        if self.get(self.ptr) == Some(0) {
+            return;
+        }
+        let mut exact = self.clone();
+        let mut budget = LOOP_BUDGET;
+        if exact.exec_loop(body, &mut budget) {
+            *self = exact;
+            return;
+        }
+        // Every cell the loop can reach is anyone's guess afterwards, if it
+        // comes back to where it started. Otherwise so is the pointer
+        match reach(body) {
+            Some((lo, hi)) if self.ptr + lo >= 0 => {
+                for at in lo..=hi {
+                    self.set(self.ptr + at, None);
+                }
+                self.set(self.ptr, Some(0));
+            }
+            _ => self.lost = true,
+        }
+    }
+
+    // Run a loop for real. False if it reads input, a cell nobody knows or
+    // runs out of budget, leaving the tape half done
+    fn exec_loop(&mut self, body: &[u8], budget: &mut usize) -> bool {
+        loop {
+            match self.get(self.ptr) {
+                Some(0) => return true,
+                None => return false,
+                Some(_) => (),
+            }
+            let mut i = 0;
+            while i < body.len() {
+                if *budget == 0 {
+                    return false;
+                }
+                *budget -= 1;
+                match body[i] {
+                    b',' => return false,
+                    b'[' => {
+                        let close = partner(body, i);
+                        if !self.exec_loop(&body[i + 1..close], budget) {
+                            return false;
+                        }
+                        i = close;
+                    }
+                    c => {
+                        if (c == b'+' || c == b'-') && self.get(self.ptr).is_none() {
+                            return false;
+                        }
+                        self.step(c);
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+}
+
+// The furthest left and right of the start a loop body goes, if every pass
+// through it ends back where it started
+fn reach(body: &[u8]) -> Option<(i64, i64)> {
+    let (mut at, mut lo, mut hi) = (0i64, 0i64, 0i64);
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            b'>' => at += 1,
+            b'<' => at -= 1,
+            b'[' => {
+                let close = partner(body, i);
+                let (inner_lo, inner_hi) = reach(&body[i + 1..close])?;
+                lo = lo.min(at + inner_lo);
+                hi = hi.max(at + inner_hi);
+                i = close;
+            }
+            _ => (),
+        }
+        lo = lo.min(at);
+        hi = hi.max(at);
+        i += 1;
+    }
+    if at == 0 {
+        Some((lo, hi))
+    } else {
+        None
+    }
+}
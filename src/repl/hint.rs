@@ -0,0 +1,50 @@
+// Bracket feedback for a line that hasn't been entered yet
+// depth is how many `[` earlier continuation lines left open
+use super::{command, paint};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Balance {
+    // `[` still open at the end of the line
+    pub depth: usize,
+    // Byte offset of the first `]` with nothing left to close
+    pub excess: Option<usize>,
+}
+
+pub fn balance(line: &str, depth: usize) -> Balance {
+    let mut balance = Balance { depth, excess: None };
+    if command::is_command(line) {
+        return balance;
+    }
+    for (i, c) in line.char_indices() {
+        match c {
+            '[' => balance.depth += 1,
+            ']' if balance.depth == 0 => {
+                balance.excess = Some(i);
+                break;
+            }
+            ']' => balance.depth -= 1,
+            _ => (),
+        }
+    }
+    balance
+}
+
+// Greyed out text to show after the cursor, e.g. `  2 open [`
+pub fn hint(line: &str, depth: usize) -> Option<String> {
+    let balance = balance(line, depth);
+    if balance.excess.is_some() {
+        Some("  ] has no matching [".to_string())
+    } else if balance.depth > 0 {
+        Some(format!("  {} open [", balance.depth))
+    } else {
+        None
+    }
+}
+
+// The line with its first unmatched `]` in red
+pub fn highlight(line: &str, depth: usize) -> String {
+    match balance(line, depth).excess {
+        Some(i) => format!("{}{}{}", &line[..i], paint("31", "]"), &line[i + 1..]),
+        None => line.to_string(),
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::io;
+
+use super::io::IoHandler;
+
+// How many runs' output the REPL hangs on to unless told otherwise
+pub const DEFAULT_RUNS: usize = 16;
+
+// What each of the last few runs wrote, newest last. Interpreter::begin_run
+// starts a new entry and the oldest falls off once there are limit of them
+#[derive(Clone, Debug)]
+pub struct OutputHistory {
+    runs: VecDeque<Vec<u8>>,
+    limit: usize,
+}
+
+impl Default for OutputHistory {
+    fn default() -> OutputHistory {
+        OutputHistory::new(DEFAULT_RUNS)
+    }
+}
+
+impl OutputHistory {
+    pub fn new(limit: usize) -> OutputHistory {
+        OutputHistory {
+            runs: VecDeque::new(),
+            limit,
+        }
+    }
+
+    // A limit of 0 keeps nothing
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.runs.len() > limit {
+            self.runs.pop_front();
+        }
+    }
+
+    pub fn begin(&mut self) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.runs.len() == self.limit {
+            self.runs.pop_front();
+        }
+        self.runs.push_back(Vec::new());
+    }
+
+    // Output from before any begin() still lands in a run of its own
+    pub fn push(&mut self, byte: u8) {
+        if self.runs.is_empty() {
+            self.begin();
+        }
+        if let Some(run) = self.runs.back_mut() {
+            run.push(byte);
+        }
+    }
+
+    // 1 is the latest run, 2 the one before and so on
+    pub fn get(&self, back: usize) -> Option<&[u8]> {
+        if back == 0 || back > self.runs.len() {
+            return None;
+        }
+        self.runs.get(self.runs.len() - back).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+}
+
+// Passes everything through to io, keeping a copy of what's written
+pub(super) struct Capture<'a> {
+    pub io: &'a mut dyn IoHandler,
+    pub history: &'a mut OutputHistory,
+}
+
+impl<'a> IoHandler for Capture<'a> {
+    fn read(&mut self) -> Option<u8> {
+        self.io.read()
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.history.push(byte);
+        self.io.write(byte);
+    }
+
+    fn flush(&mut self) {
+        self.io.flush();
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.io.take_error()
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        self.io.feed(input);
+    }
+
+    fn rewind(&mut self) {
+        self.io.rewind();
+    }
+}
@@ -0,0 +1,312 @@
+// A tiny HTTP API for running programs, so a web playground can have a
+// backend without writing one. One request per connection:
+//
+//     POST /run
+//     {"program": "++[>+<-]>.", "input": "", "max_steps": 1000, "dialect": "brainfuck"}
+//
+// answers with the output, how the run went and anything that went wrong
+//
+//     {"output": "\u0002", "stats": {"steps": 13, "output_bytes": 1, "millis": 0}, "errors": []}
+//
+// Only program is needed. Limits in a request can lower the server's but
+// never raise them. `GET /ws` upgrades to a WebSocket that runs a program
+// live, see websocket
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Instant;
+
+use super::cli::check_dialect;
+use super::dialect::Registry;
+use super::io::Buffer;
+use super::json::Json;
+use super::serve::{accept, ServeOptions};
+use super::websocket;
+use super::Interpreter;
+
+// Bigger bodies than this are turned away before they're read
+pub const MAX_BODY: usize = 1 << 20;
+// The same for the request line and headers together, which nobody sends
+// more than a few hundred bytes of
+pub const MAX_HEADER: usize = 8 << 10;
+
+pub fn serve_http(listener: TcpListener, options: ServeOptions) -> io::Result<()> {
+    accept(listener, options, exchange, |stream| {
+        let _ = respond(stream, 503, &failure("Too busy right now, try again later"));
+    })
+}
+
+// One request in and one response out
+fn exchange(stream: TcpStream, options: &ServeOptions) -> io::Result<()> {
+    stream.set_read_timeout(options.idle)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let (status, body) = match read_request(&mut reader) {
+        Ok(Request {
+            ref path,
+            websocket: Some(ref key),
+            ..
+        }) if path == "/ws" => {
+            // A page watching a run can go quiet for as long as it likes,
+            // the session has its own idea of idle
+            stream.set_read_timeout(None)?;
+            return websocket::session(stream, reader, key, options);
+        }
+        Ok(request) => route(&request, options),
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            (408, failure("Took too long to send the request"))
+        }
+        Err(ref e) if e.get_ref().is_some_and(|e| e.is::<HeaderTooLarge>()) => (431, failure(&e.to_string())),
+        Err(ref e) if e.kind() == ErrorKind::InvalidData => (400, failure(&e.to_string())),
+        Err(e) => return Err(e),
+    };
+    respond(&mut stream, status, &body)
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+    // Sec-WebSocket-Key, when the request wants an upgrade
+    websocket: Option<String>,
+}
+
+fn bad(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// Past MAX_HEADER, a 431 rather than a 400
+#[derive(Debug)]
+struct HeaderTooLarge;
+
+impl fmt::Display for HeaderTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The request line and headers can be {} bytes at most", MAX_HEADER)
+    }
+}
+
+impl Error for HeaderTooLarge {}
+
+// A line of the request line and headers, out of the left of MAX_HEADER
+// there is, so a client can't send one that never ends
+fn read_header_line<R: BufRead>(reader: &mut R, left: &mut usize) -> io::Result<String> {
+    let too_large = || io::Error::new(ErrorKind::InvalidData, HeaderTooLarge);
+    if *left == 0 {
+        return Err(too_large());
+    }
+    let mut line = String::new();
+    let n = reader.by_ref().take(*left as u64).read_line(&mut line)?;
+    *left -= n;
+    if *left == 0 && !line.ends_with('\n') {
+        return Err(too_large());
+    }
+    Ok(line)
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut left = MAX_HEADER;
+    let line = read_header_line(reader, &mut left)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(bad("That isn't an HTTP request")),
+    };
+
+    let mut length = None;
+    let mut websocket = None;
+    loop {
+        let header = read_header_line(reader, &mut left)?;
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse().map_err(|_| bad("Content-Length isn't a number"))?);
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                websocket = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let length: usize = length.unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(bad(&format!("Bodies can be {} bytes at most", MAX_BODY)));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        body,
+        websocket,
+    })
+}
+
+fn route(request: &Request, options: &ServeOptions) -> (u16, Json) {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/run") => match run(&request.body, options) {
+            Ok(answer) => (200, answer),
+            Err(e) => (400, failure(&e)),
+        },
+        // Browsers ask first before posting JSON from another origin
+        ("OPTIONS", "/run") => (204, Json::Null),
+        (_, "/run") => (405, failure("/run only takes POST")),
+        (_, "/ws") => (400, failure("/ws is for WebSockets")),
+        _ => (404, failure("Nothing here, try POST /run")),
+    }
+}
+
+fn failure(message: &str) -> Json {
+    Json::object(vec![("error", Json::from(message))])
+}
+
+fn run(body: &[u8], options: &ServeOptions) -> Result<Json, String> {
+    let body = std::str::from_utf8(body).map_err(|_| "The body isn't UTF-8".to_string())?;
+    let request = Json::parse(body)?;
+    let program = request
+        .get("program")
+        .and_then(Json::as_str)
+        .ok_or_else(|| "Needs a \"program\" string".to_string())?;
+    let input = match request.get("input") {
+        None | Some(Json::Null) => "",
+        Some(input) => input.as_str().ok_or_else(|| "\"input\" has to be a string".to_string())?,
+    };
+
+    let mut interpreter = Interpreter::new();
+    if let Some(name) = request.get("dialect") {
+        let name = name.as_str().ok_or_else(|| "\"dialect\" has to be a string".to_string())?;
+        check_dialect(name)?;
+        interpreter.set_dialect(Registry::new().get(name));
+    }
+    interpreter.set_max_steps(lower(limit(&request, "max_steps")?, options.max_steps));
+    interpreter.set_watchdog(lower(limit(&request, "watchdog")?, options.watchdog));
+    interpreter.brain.set_capacity_cap(options.capacity_cap);
+
+    let errors = interpreter.parse_errors(program);
+    if !errors.is_empty() {
+        let errors = errors.iter().map(|e| e.to_json(program)).collect();
+        return Ok(answer(String::new(), &interpreter, 0, errors));
+    }
+    interpreter.load_source(program).map_err(|e| e.to_string())?;
+    let buffer = Buffer::new(input.as_bytes());
+    let output = buffer.output();
+    interpreter.set_io(Box::new(buffer));
+
+    let start = Instant::now();
+    let result = interpreter.interpret_constant();
+    let millis = start.elapsed().as_millis() as u64;
+    let errors = match result {
+        Ok(()) => Vec::new(),
+        Err(e) => vec![e.to_json(program, interpreter.source_offset())],
+    };
+    // A char per byte like the REPL prints them, so nothing gets lost on
+    // output that isn't UTF-8
+    let output = output.borrow().iter().map(|&byte| byte as char).collect();
+    Ok(answer(output, &interpreter, millis, errors))
+}
+
+// A whole number limit, if the request has one
+pub(super) fn limit(request: &Json, key: &str) -> Result<Option<u64>, String> {
+    match request.get(key) {
+        None | Some(Json::Null) => Ok(None),
+        Some(n) => n
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| format!("\"{}\" has to be a whole number", key)),
+    }
+}
+
+// The tighter of what was asked for and what the server allows
+pub(super) fn lower(asked: Option<u64>, allowed: Option<u64>) -> Option<u64> {
+    match (asked, allowed) {
+        (Some(asked), Some(allowed)) => Some(asked.min(allowed)),
+        (asked, allowed) => asked.or(allowed),
+    }
+}
+
+fn answer(output: String, interpreter: &Interpreter, millis: u64, errors: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("output", Json::from(output)),
+        (
+            "stats",
+            Json::object(vec![
+                ("steps", Json::from(interpreter.steps())),
+                ("output_bytes", Json::from(interpreter.output_count())),
+                ("millis", Json::from(millis)),
+            ]),
+        ),
+        ("errors", Json::Array(errors)),
+    ])
+}
+
+// Always closes, one request a connection keeps things simple. Anyone can
+// call the API from a page on any origin
+fn respond(stream: &mut TcpStream, status: u16, body: &Json) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    let body = if status == 204 { String::new() } else { body.to_string() };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_request, HeaderTooLarge, MAX_HEADER};
+
+    fn too_large(request: &[u8]) -> bool {
+        match read_request(&mut &request[..]) {
+            Err(e) => e.get_ref().is_some_and(|e| e.is::<HeaderTooLarge>()),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn reads_a_request() {
+        let request = read_request(&mut &b"POST /run HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}"[..]).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/run"));
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn headers_stop_at_the_limit() {
+        let endless = vec![b'a'; MAX_HEADER * 2];
+        assert!(too_large(&endless));
+
+        let mut headers = b"GET / HTTP/1.1\r\n".to_vec();
+        while headers.len() <= MAX_HEADER {
+            headers.extend_from_slice(b"X-Padding: 0123456789\r\n");
+        }
+        headers.extend_from_slice(b"\r\n");
+        assert!(too_large(&headers));
+
+        let mut fits = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        fits.resize(MAX_HEADER - 4, b'0');
+        fits.extend_from_slice(b"\r\n\r\n");
+        assert_eq!(fits.len(), MAX_HEADER);
+        assert!(!too_large(&fits));
+    }
+}
@@ -0,0 +1,296 @@
+// Deflate decoding, RFC 1951, for the zip entries and .tar.gz files batch
+// reads programs out of: stored, fixed and dynamic Huffman blocks, a bit at
+// a time the way zlib's puff does it. Corpora are small, so there's no
+// table lookup to make it fast
+use std::io;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// The order a dynamic block gives its code length code lengths in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// The whole of a raw deflate stream, without any zlib or gzip wrapping
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut bits = Bits { data, at: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bit()? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, &mut out)?,
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                codes(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic(&mut bits)?;
+                codes(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(invalid("deflate block of unknown type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// A .gz file, whatever the header says besides the method. The checksum
+// at the end isn't checked, a cut off file fails to inflate anyway
+pub fn gunzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 10 || data[..3] != [0x1f, 0x8b, 8] {
+        return Err(invalid("not a gzip file"));
+    }
+    let flags = data[3];
+    let mut at = 10;
+    if flags & 4 != 0 {
+        let extra = data.get(at..at + 2).ok_or_else(|| invalid("gzip header is cut short"))?;
+        at += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+    // The original name and a comment, both ending in a zero byte
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            let end = data[at.min(data.len())..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| invalid("gzip header is cut short"))?;
+            at += end + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        at += 2;
+    }
+    inflate(data.get(at..).ok_or_else(|| invalid("gzip header is cut short"))?)
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    // In bits, least significant first within each byte
+    at: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn bit(&mut self) -> io::Result<u32> {
+        let byte = *self.data.get(self.at / 8).ok_or_else(|| invalid("deflate data is cut short"))?;
+        let bit = (byte >> (self.at % 8)) & 1;
+        self.at += 1;
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, n: u8) -> io::Result<u32> {
+        let mut value = 0;
+        for i in 0..n {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+}
+
+// A canonical code from how long each symbol's code is, zero for unused
+struct Huffman {
+    // Codes of each length
+    counts: [u16; 16],
+    // Symbols in code order
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> io::Result<u16> {
+        // The code so far, the first code of this length and where its
+        // symbols start
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bit()? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("deflate code that isn't in the table"))
+    }
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>) -> io::Result<()> {
+    let at = bits.at.div_ceil(8);
+    let header = bits.data.get(at..at + 4).ok_or_else(|| invalid("deflate data is cut short"))?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    if len != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(invalid("stored deflate block with a bad length"));
+    }
+    let start = at + 4;
+    let block = bits
+        .data
+        .get(start..start + len as usize)
+        .ok_or_else(|| invalid("deflate data is cut short"))?;
+    out.extend_from_slice(block);
+    bits.at = (start + len as usize) * 8;
+    Ok(())
+}
+
+fn dynamic(bits: &mut Bits) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let length_count = bits.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..length_count] {
+        code_lengths[i] = bits.bits(3)? as u8;
+    }
+    let lengths_code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, times) = match lengths_code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| invalid("deflate repeat with nothing before it"))?;
+                (previous, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        for _ in 0..times {
+            lengths.push(len);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid("deflate code lengths run past the end"));
+    }
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(invalid("deflate length out of range"));
+        }
+        let len = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i])? as usize;
+        let i = distances.decode(bits)? as usize;
+        if i >= DISTANCE_BASE.len() {
+            return Err(invalid("deflate distance out of range"));
+        }
+        let distance = DISTANCE_BASE[i] as usize + bits.bits(DISTANCE_EXTRA[i])? as usize;
+        if distance > out.len() {
+            return Err(invalid("deflate distance goes back past the start"));
+        }
+        // One at a time, since the copy can overlap what it's making
+        let from = out.len() - distance;
+        for j in 0..len {
+            out.push(out[from + j]);
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gunzip, inflate};
+
+    // Vectors from zlib, raw deflate with wbits -15
+
+    // Level 0: a stored block, its length and the length's complement
+    const STORED: [u8; 10] = [0x01, 0x05, 0x00, 0xfa, 0xff, b'+', b'[', b'>', b'+', b']'];
+
+    // Z_FIXED, with a couple of back references
+    const FIXED: [u8; 16] = [
+        0xd3, 0xd6, 0x86, 0x80, 0x68, 0x3b, 0x28, 0x43, 0xdb, 0x46, 0x37, 0xd6, 0x4e, 0x5b, 0x0f, 0x00,
+    ];
+
+    // A dynamic table for 40 random instructions, then the first 20 again
+    const DYNAMIC: [u8; 39] = [
+        0x6d, 0xca, 0x41, 0x0d, 0x00, 0x30, 0x0c, 0x03, 0x31, 0x40, 0xd7, 0x14, 0x41, 0x14, 0x22, 0x55,
+        0xf8, 0xd3, 0x18, 0x81, 0xf9, 0x6d, 0x8a, 0x6f, 0x09, 0x58, 0x78, 0x41, 0xa4, 0x2e, 0xd2, 0x5c,
+        0x33, 0x1c, 0x31, 0xd9, 0xf0, 0x79, 0x0f,
+    ];
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(inflate(&STORED).unwrap(), b"+[>+]");
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!(inflate(&FIXED).unwrap(), b"++++++++[>++++++++<-]>+.");
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        let expected = "+]+<[.+>++<-+<.++-+>]<]+--,[]>,+[+><+>.>+]+<[.+>++<-+<.++-+>";
+        assert_eq!(inflate(&DYNAMIC).unwrap(), expected.as_bytes());
+    }
+
+    // Every way of cutting one short is an error rather than a panic or
+    // a partial answer
+    #[test]
+    fn truncated_streams_fail() {
+        for data in [&STORED[..], &FIXED[..], &DYNAMIC[..]] {
+            for end in 0..data.len() {
+                assert!(inflate(&data[..end]).is_err(), "{} of {} bytes inflated", end, data.len());
+            }
+        }
+    }
+
+    #[test]
+    fn stored_length_has_to_match_its_complement() {
+        let mut bad = STORED;
+        bad[3] = 0;
+        assert!(inflate(&bad).is_err());
+    }
+
+    #[test]
+    fn gzip_with_a_name() {
+        let data = [
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, b'x', b'.', b'b', b'f', 0x00, 0xd3, 0x8e,
+            0xb6, 0xd3, 0x8e, 0x05, 0x00, 0x11, 0x79, 0x60, 0x21, 0x05, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(gunzip(&data).unwrap(), b"+[>+]");
+        assert!(gunzip(&data[..12]).is_err());
+    }
+}
@@ -0,0 +1,478 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, stdin, stdout, BufWriter, Read, Stdout, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use super::catalog::usage_message;
+use super::shell::Console;
+use super::Prompt;
+
+// What `,` leaves in the cell once there's nothing left to read. Programs
+// are written for one or another of these, so it's up to whoever runs them
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Eof {
+    #[default]
+    Unchanged,
+    Zero,
+    // 255, which programs written for signed cells see as -1
+    Max,
+}
+
+impl Eof {
+    pub fn parse(text: &str) -> Result<Eof, String> {
+        match text {
+            "unchanged" => Ok(Eof::Unchanged),
+            "0" => Ok(Eof::Zero),
+            "255" | "-1" => Ok(Eof::Max),
+            _ => Err(usage_message("eof", &[&text])),
+        }
+    }
+
+    // What parse takes back
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Eof::Unchanged => "unchanged",
+            Eof::Zero => "0",
+            Eof::Max => "255",
+        }
+    }
+}
+
+// Where `,` gets its bytes and `.` sends them
+pub trait IoHandler {
+    // None once there's nothing left to read
+    fn read(&mut self) -> Option<u8>;
+    fn write(&mut self, byte: u8);
+
+    // Called when the interpreter finishes a run
+    fn flush(&mut self) {}
+
+    // Hand over the error that stopped output, if there was one
+    fn take_error(&mut self) -> Option<io::Error> {
+        None
+    }
+
+    // Bytes to hand out before anything else, handlers that can't take
+    // extra input just ignore them
+    fn feed(&mut self, _input: &[u8]) {}
+
+    // Start over for Interpreter::rewind, dropping any output written so
+    // far that hasn't gone anywhere yet
+    fn rewind(&mut self) {}
+}
+
+// The REPL's own handler: prompts for each input byte and prints the
+// output as one line once the evaluation is done, on stdin and stdout or
+// a REPL's console
+#[derive(Default)]
+pub struct Terminal {
+    output: String,
+    fed: VecDeque<u8>,
+    console: Option<Rc<RefCell<Console>>>,
+    error: Option<io::Error>,
+}
+
+impl Terminal {
+    pub fn new() -> Terminal {
+        Terminal::default()
+    }
+
+    pub fn with_console(console: Rc<RefCell<Console>>) -> Terminal {
+        Terminal {
+            console: Some(console),
+            ..Terminal::default()
+        }
+    }
+
+    fn console<T, F>(&mut self, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Console) -> io::Result<T>,
+    {
+        let result = match self.console {
+            Some(ref console) => f(&mut console.borrow_mut()),
+            None => f(&mut Console::stdio()),
+        };
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.error.get_or_insert(e);
+                None
+            }
+        }
+    }
+}
+
+impl IoHandler for Terminal {
+    fn read(&mut self) -> Option<u8> {
+        if let Some(byte) = self.fed.pop_front() {
+            return Some(byte);
+        }
+        // I don't know if this is good or bad
+        self.console(|console| console.read_line(Prompt::Byte))
+            .and_then(|line| line?.chars().next())
+            .map(|c| c as u8)
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.push(byte as char);
+    }
+
+    fn flush(&mut self) {
+        if !self.output.is_empty() {
+            let output = std::mem::take(&mut self.output);
+            self.console(|console| console.print(&output));
+        }
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        self.fed.extend(input);
+    }
+
+    fn rewind(&mut self) {
+        self.output.clear();
+    }
+}
+
+// Input from memory and output into a buffer the caller keeps a handle on
+#[derive(Default, Debug)]
+pub struct Buffer {
+    input: VecDeque<u8>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Buffer {
+    pub fn new(input: &[u8]) -> Buffer {
+        Buffer {
+            input: input.iter().cloned().collect(),
+            output: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    // Shared with the handler, so it still works after the handler is boxed
+    pub fn output(&self) -> Rc<RefCell<Vec<u8>>> {
+        Rc::clone(&self.output)
+    }
+}
+
+impl IoHandler for Buffer {
+    fn read(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.borrow_mut().push(byte);
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        let rest: Vec<u8> = self.input.drain(..).collect();
+        self.input.extend(input);
+        self.input.extend(rest);
+    }
+
+    fn rewind(&mut self) {
+        self.output.borrow_mut().clear();
+    }
+}
+
+// Bytes straight from stdin and straight to stdout, no prompts, no newline
+// tacked on and no char conversion, so `> out.bin` gets exactly what the
+// program wrote. Output is flushed before every read and at the end, and
+// the first write that fails stops the rest
+#[derive(Debug)]
+pub struct Stdio {
+    out: BufWriter<Stdout>,
+    error: Option<io::Error>,
+    fed: VecDeque<u8>,
+    // Whether to read stdin once fed runs out
+    stdin: bool,
+}
+
+impl Default for Stdio {
+    fn default() -> Stdio {
+        Stdio::new()
+    }
+}
+
+impl Stdio {
+    pub fn new() -> Stdio {
+        Stdio {
+            out: BufWriter::new(stdout()),
+            error: None,
+            fed: VecDeque::new(),
+            stdin: true,
+        }
+    }
+
+    // Input comes from memory instead, for when stdin is busy being the program
+    pub fn with_input(input: &[u8]) -> Stdio {
+        Stdio {
+            fed: input.iter().cloned().collect(),
+            stdin: false,
+            ..Stdio::new()
+        }
+    }
+
+    fn check(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+impl IoHandler for Stdio {
+    fn read(&mut self) -> Option<u8> {
+        self.flush();
+        if let Some(byte) = self.fed.pop_front() {
+            return Some(byte);
+        }
+        if !self.stdin {
+            return None;
+        }
+        let mut byte = [0];
+        match stdin().lock().read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, byte: u8) {
+        if self.error.is_none() {
+            let result = self.out.write_all(&[byte]);
+            self.check(result);
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.error.is_none() {
+            let result = self.out.flush();
+            self.check(result);
+        }
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        let rest: Vec<u8> = self.fed.drain(..).collect();
+        self.fed.extend(input);
+        self.fed.extend(rest);
+    }
+}
+
+// Output to any io::Write and no input, e.g. a file to keep a byte exact
+// copy of everything a program printed. Write errors work like Stdio's
+pub struct Sink<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Sink<W> {
+    pub fn new(out: W) -> Sink<W> {
+        Sink { out, error: None }
+    }
+}
+
+impl Sink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Sink<BufWriter<File>>> {
+        Ok(Sink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> IoHandler for Sink<W> {
+    fn read(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn write(&mut self, byte: u8) {
+        if self.error.is_none() {
+            if let Err(e) = self.out.write_all(&[byte]) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.error.is_none() {
+            if let Err(e) = self.out.flush() {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+// Several handlers at once: input comes from the first, output goes to
+// every one of them, so a program can be watched on the terminal while a
+// file and a Buffer keep copies
+//
+//     let tee = Tee::new(Box::new(Stdio::new()))
+//         .with(Box::new(Sink::create("out.bin")?))
+//         .with(Box::new(buffer));
+pub struct Tee {
+    handlers: Vec<Box<dyn IoHandler>>,
+}
+
+impl Tee {
+    pub fn new(first: Box<dyn IoHandler>) -> Tee {
+        Tee {
+            handlers: vec![first],
+        }
+    }
+
+    pub fn with(mut self, handler: Box<dyn IoHandler>) -> Tee {
+        self.handlers.push(handler);
+        self
+    }
+
+    pub fn add(&mut self, handler: Box<dyn IoHandler>) {
+        self.handlers.push(handler);
+    }
+}
+
+impl IoHandler for Tee {
+    // The copies are flushed before waiting on input, like Stdio does
+    fn read(&mut self) -> Option<u8> {
+        for handler in &mut self.handlers[1..] {
+            handler.flush();
+        }
+        self.handlers[0].read()
+    }
+
+    fn write(&mut self, byte: u8) {
+        for handler in &mut self.handlers {
+            handler.write(byte);
+        }
+    }
+
+    fn flush(&mut self) {
+        for handler in &mut self.handlers {
+            handler.flush();
+        }
+    }
+
+    // The first handler's error if there's more than one
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.handlers
+            .iter_mut()
+            .filter_map(|handler| handler.take_error())
+            .next()
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        self.handlers[0].feed(input);
+    }
+
+    fn rewind(&mut self) {
+        for handler in &mut self.handlers {
+            handler.rewind();
+        }
+    }
+}
+
+// Input from one handler and output to another, for programs whose output
+// belongs in a file while `,` still reads stdin or the terminal
+pub struct Split {
+    input: Box<dyn IoHandler>,
+    output: Box<dyn IoHandler>,
+}
+
+impl Split {
+    pub fn new(input: Box<dyn IoHandler>, output: Box<dyn IoHandler>) -> Split {
+        Split { input, output }
+    }
+}
+
+impl IoHandler for Split {
+    fn read(&mut self) -> Option<u8> {
+        self.output.flush();
+        self.input.read()
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.write(byte);
+    }
+
+    fn flush(&mut self) {
+        self.output.flush();
+        self.input.flush();
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.output.take_error().or_else(|| self.input.take_error())
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        self.input.feed(input);
+    }
+
+    fn rewind(&mut self) {
+        self.output.rewind();
+        self.input.rewind();
+    }
+}
+
+// Wraps another handler and copies every byte `,` actually got to log,
+// whether it was fed, typed or read from stdin. The log is raw bytes, so
+// it works as --input-file for `brainf record`
+pub struct Transcript<W: Write> {
+    inner: Box<dyn IoHandler>,
+    log: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Transcript<W> {
+    pub fn new(inner: Box<dyn IoHandler>, log: W) -> Transcript<W> {
+        Transcript {
+            inner,
+            log,
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> IoHandler for Transcript<W> {
+    fn read(&mut self) -> Option<u8> {
+        let byte = self.inner.read();
+        if let (Some(byte), None) = (byte, self.error.as_ref()) {
+            if let Err(e) = self.log.write_all(&[byte]) {
+                self.error = Some(e);
+            }
+        }
+        byte
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.inner.write(byte);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+        if self.error.is_none() {
+            if let Err(e) = self.log.flush() {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.inner.take_error().or_else(|| self.error.take())
+    }
+
+    fn feed(&mut self, input: &[u8]) {
+        self.inner.feed(input);
+    }
+
+    fn rewind(&mut self) {
+        self.inner.rewind();
+    }
+}
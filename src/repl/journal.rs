@@ -0,0 +1,48 @@
+// Every cell a run changed, in order, so you can see what some code
+// actually did to the tape. Steps count from 0 like trace::Trace does
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CellWrite {
+    pub step: u64,
+    pub index: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    writes: Vec<CellWrite>,
+    steps: u64,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal::default()
+    }
+
+    // Called once per step with the cell under the pointer from before the
+    // step and what that cell holds after it. Only changes get written down
+    pub fn step(&mut self, index: usize, old: u8, new: u8) {
+        if old != new {
+            self.writes.push(CellWrite {
+                step: self.steps,
+                index,
+                old,
+                new,
+            });
+        }
+        self.steps += 1;
+    }
+
+    pub fn writes(&self) -> &[CellWrite] {
+        &self.writes
+    }
+
+    // Steps seen, whether they wrote anything or not
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn clear(&mut self) {
+        *self = Journal::new();
+    }
+}
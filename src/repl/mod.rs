@@ -0,0 +1,1694 @@
+use std::fmt;
+
+pub mod alias;
+pub mod archive;
+pub mod bisect;
+pub mod catalog;
+pub mod channel;
+pub mod check;
+pub mod cli;
+pub mod command;
+pub mod condition;
+pub mod config;
+pub mod constant;
+#[cfg(feature = "corpus")]
+pub mod corpus;
+pub mod complete;
+pub mod debug;
+pub mod dialect;
+pub mod editor;
+pub mod disasm;
+pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod format;
+pub mod fuzz;
+pub mod generate;
+pub mod golf;
+pub mod hint;
+pub mod history;
+pub mod http;
+#[cfg(feature = "corpus")]
+pub mod inflate;
+pub mod io;
+pub mod journal;
+pub mod json;
+pub mod nested;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod pipe;
+pub mod profile;
+pub mod progress;
+pub mod random;
+pub mod recent;
+pub mod reduce;
+pub mod replay;
+pub mod script;
+pub mod serve;
+pub mod shell;
+pub mod snapshot;
+pub mod spec;
+pub mod tape;
+pub mod trace;
+pub mod transform;
+pub mod watch;
+pub mod websocket;
+pub mod xcheck;
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use self::alias::Aliases;
+use self::condition::Condition;
+use self::dialect::Dialect;
+use self::error::{Failure, ParseError, RuntimeError};
+use self::io::{Eof, IoHandler, Terminal};
+use self::format::{BrainFormatter, FullFormatter, RangeFormatter, WindowFormatter};
+use self::history::{Capture, OutputHistory};
+use self::journal::Journal;
+use self::nested::{Limits, MAX_DEPTH};
+use self::profile::Profiler;
+use self::progress::Progress;
+use self::recent::Recent;
+use self::snapshot::{Recovery, Snapshot};
+use self::tape::{DenseTape, Tape};
+use self::trace::{Trace, TraceEntry};
+use self::watch::{Watch, WatchChange};
+
+// Enums for shell prompt symbols
+#[derive(Copy, Clone, Debug)]
+enum Prompt {
+    Input,
+    Continue,
+    Byte,
+    State,
+    Debug,
+    Warning,
+    Error,
+}
+
+// Errors get the siren like everything else the REPL complains about
+pub fn print_error<E: fmt::Display>(error: E) {
+    println!("{}  {}", symbol_from_prompt(Prompt::Error), error);
+}
+
+// Returns symbols defined for prompt, emoji to be annoying and plain ASCII
+// for terminals and logs that mangle them
+fn prompt_symbols(prompt: Prompt) -> (&'static str, &'static str) {
+    match prompt {
+        Prompt::Input => ("👉", ">"),
+        Prompt::Continue => ("💦", "..."),
+        Prompt::Byte => ("🍴", "byte?"),
+        Prompt::State => ("🙏", "="),
+        Prompt::Debug => ("🐛", "dbg"),
+        Prompt::Warning => ("⚠", "!"),
+        Prompt::Error => ("🚨", "!!"),
+    }
+}
+
+// Emoji unless --no-emoji, for every prompt the process prints
+static EMOJI: AtomicBool = AtomicBool::new(true);
+
+pub fn set_emoji(on: bool) {
+    EMOJI.store(on, Ordering::Relaxed);
+}
+
+// Colour unless --no-color or NO_COLOR, for the bracket hints and :match
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+pub fn set_color(on: bool) {
+    COLOR.store(on, Ordering::Relaxed);
+}
+
+// text in the SGR colour or style code, e.g. "31" for red, or left plain
+// when colour is off
+fn paint(code: &str, text: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn symbol_from_prompt(prompt: Prompt) -> &'static str {
+    let (emoji, ascii) = prompt_symbols(prompt);
+    if EMOJI.load(Ordering::Relaxed) {
+        emoji
+    } else {
+        ascii
+    }
+}
+
+// Tokens that compromise our language
+// Usize is used to index the Jump tokens
+#[derive(Copy, Clone, PartialEq)]
+pub enum Token {
+    PointerIncrement,
+    PointerDecrement,
+    DataIncrement,
+    DataDecrement,
+    Input,
+    Output,
+    JumpForward(usize),
+    JumpBackward(usize),
+    // An instruction a dialect added, see dialect::Dialect
+    Extension(char),
+}
+
+impl Token {
+    // The command a source character stands for, jumps still unresolved
+    pub fn from_char(c: char) -> Option<Token> {
+        match c {
+            '>' => Some(Token::PointerIncrement),
+            '<' => Some(Token::PointerDecrement),
+            '+' => Some(Token::DataIncrement),
+            '-' => Some(Token::DataDecrement),
+            '.' => Some(Token::Output),
+            ',' => Some(Token::Input),
+            '[' => Some(Token::JumpForward(0)),
+            ']' => Some(Token::JumpBackward(0)),
+            _ => None,
+        }
+    }
+
+    // The character it was written as
+    pub fn to_char(self) -> char {
+        match self {
+            Token::PointerIncrement => '>',
+            Token::PointerDecrement => '<',
+            Token::DataIncrement => '+',
+            Token::DataDecrement => '-',
+            Token::Input => ',',
+            Token::Output => '.',
+            Token::JumpForward(_) => '[',
+            Token::JumpBackward(_) => ']',
+            Token::Extension(c) => c,
+        }
+    }
+}
+
+// Tokens written back out as source, width commands to a line, whatever
+// dialect or aliases they were read with. Brackets say everything the jump
+// targets do, so loading it gives the same tokens again
+pub fn to_source(tokens: &[Token], width: usize) -> String {
+    let mut source = String::new();
+    for line in tokens.chunks(width.max(1)) {
+        source.extend(line.iter().map(|token| token.to_char()));
+        source.push('\n');
+    }
+    source
+}
+
+// Tokens print as the characters a brainfuck programmer would write,
+// Debug adds where a jump goes, so `[` to #9 is `[9`
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::JumpForward(i) | Token::JumpBackward(i) => write!(f, "{}{}", self.to_char(), i),
+            _ => write!(f, "{}", self.to_char()),
+        }
+    }
+}
+
+// A run of tokens printed back as source, e.g. `++[>+<-]`
+#[derive(Copy, Clone)]
+pub struct Tokens<'a>(pub &'a [Token]);
+
+impl<'a> fmt::Display for Tokens<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.0 {
+            write!(f, "{}", token)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for Tokens<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+// Parser to tokenize
+// spans holds the byte offset of each token in the session's source and
+// ends where it stops, since an alias or a dialect's word can be more than
+// one byte. source is the text fed in since the last reset
+#[derive(Default, Debug)]
+pub struct Parser {
+    pub tokens: Vec<Token>,
+    pub spans: Vec<usize>,
+    pub ends: Vec<usize>,
+    pub source: String,
+    pub match_stack: Vec<usize>,
+    // Every ']' with nothing to close and everything strict comments
+    // didn't like, in order. Whole programs keep going after one so they
+    // can all be reported, unclosed '['s are whatever match_stack still has
+    pub errors: Vec<ParseError>,
+    // Offset and character of every alias read since the last reset
+    pub aliased: Vec<(usize, char)>,
+    // Someone typing, who gets messages instead of a list of errors
+    interactive: bool,
+    // Warnings and errors for whoever is typing, see take_messages
+    messages: Vec<String>,
+    // None is plain brainfuck
+    dialect: Option<Rc<dyn Dialect>>,
+    aliases: Option<Rc<Aliases>>,
+    strict_comments: bool,
+    cursor: usize,
+    prev_cursor: usize,
+    source_start: usize,
+    span: usize,
+    span_len: usize,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser {
+            tokens: Vec::new(),
+            spans: Vec::new(),
+            ends: Vec::new(),
+            source: String::new(),
+            match_stack: Vec::new(),
+            errors: Vec::new(),
+            aliased: Vec::new(),
+            interactive: true,
+            messages: Vec::new(),
+            dialect: None,
+            aliases: None,
+            strict_comments: false,
+            cursor: 0,
+            prev_cursor: 0,
+            source_start: 0,
+            span: 0,
+            span_len: 0,
+        }
+    }
+
+    // For whole programs from files and the like
+    pub fn program() -> Parser {
+        Parser {
+            interactive: false,
+            ..Parser::new()
+        }
+    }
+
+    // An interactive parser whose tokens go after what the interpreter
+    // already holds, so jump targets still line up once they're loaded
+    // It speaks the interpreter's dialect too, aliases and all
+    pub fn after(interpreter: &Interpreter) -> Parser {
+        Parser {
+            prev_cursor: interpreter.tokens.len(),
+            source_start: interpreter.source.len(),
+            dialect: interpreter.dialect.clone(),
+            aliases: interpreter.aliases.clone(),
+            strict_comments: interpreter.strict_comments,
+            ..Parser::new()
+        }
+    }
+
+    // Read source as some other variant, see dialect::Dialect
+    pub fn set_dialect(&mut self, dialect: Option<Rc<dyn Dialect>>) {
+        self.dialect = dialect;
+    }
+
+    // Read extra characters as commands, see alias::Aliases
+    pub fn set_aliases(&mut self, aliases: Option<Rc<Aliases>>) {
+        self.aliases = aliases;
+    }
+
+    // Make anything other than code and whitespace an error, for catching
+    // programs that got cut off or mangled on the way
+    pub fn set_strict_comments(&mut self, on: bool) {
+        self.strict_comments = on;
+    }
+
+    pub fn tokenize(&mut self, input: &str) {
+        let base = self.source_start + self.source.len();
+        self.source.push_str(input);
+
+        #[cfg(feature = "parallel")]
+        {
+            if input.len() >= parallel::THRESHOLD
+                && self.dialect.is_none()
+                && self.aliases.is_none()
+                && !self.strict_comments
+            {
+                return self.tokenize_parallel(base, input);
+            }
+        }
+
+        if let Some(dialect) = self.dialect.clone() {
+            if !dialect.words().is_empty() {
+                return self.tokenize_words(base, input, dialect.words());
+            }
+        }
+
+        for (i, n) in input.char_indices() {
+            self.span = base + i;
+            self.span_len = n.len_utf8();
+            let n = match self.aliases.as_ref().and_then(|aliases| aliases.get(n)) {
+                Some(command) => {
+                    self.alias(n, command);
+                    command
+                }
+                None => n,
+            };
+            let token = match self.dialect {
+                Some(ref dialect) => dialect.token(n),
+                None => Token::from_char(n),
+            };
+            match token {
+                Some(token) => {
+                    if self.push_match(token).is_err() {
+                        return;
+                    }
+                }
+                None => {
+                    if self.strict_comments && !n.is_whitespace() && self.stray(n).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    // Longest match first, for dialects with multi-character commands
+    fn tokenize_words(&mut self, base: usize, input: &str, words: &[(&str, Token)]) {
+        let mut i = 0;
+        while i < input.len() {
+            let rest = &input[i..];
+            self.span = base + i;
+            let longest = words
+                .iter()
+                .filter(|&&(word, _)| rest.starts_with(word))
+                .max_by_key(|&&(word, _)| word.len());
+            match longest {
+                Some(&(word, token)) => {
+                    self.span_len = word.len();
+                    if self.push_match(token).is_err() {
+                        return;
+                    }
+                    i += word.len();
+                }
+                None => {
+                    let c = rest.chars().next().unwrap_or(' ');
+                    if self.strict_comments && !c.is_whitespace() && self.stray(c).is_err() {
+                        return;
+                    }
+                    i += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    // Lex chunks on every core, then match brackets in one sequential pass
+    #[cfg(feature = "parallel")]
+    fn tokenize_parallel(&mut self, base: usize, input: &str) {
+        for chunk in parallel::lex(base, input) {
+            let offset = self.cursor;
+            self.tokens.extend_from_slice(&chunk.tokens);
+            self.spans.extend_from_slice(&chunk.spans);
+            // Plain brainfuck, every command is a byte
+            self.ends.extend(chunk.spans.iter().map(|&span| span + 1));
+            self.cursor += chunk.tokens.len();
+
+            for &local in &chunk.brackets {
+                let i = offset + local;
+                match self.tokens[i] {
+                    Token::JumpForward(_) => self.match_stack.push(i),
+                    _ => match self.match_stack.pop() {
+                        Some(open) => {
+                            self.tokens[open] = Token::JumpForward(i + self.prev_cursor);
+                            self.tokens[i] = Token::JumpBackward(open + self.prev_cursor);
+                        }
+                        None => {
+                            // Where the stray `]` is, error() says it
+                            self.span = self.spans[i];
+                            self.span_len = 1;
+                            if self.error().is_err() {
+                                return;
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    fn push_token(&mut self, token: Token) {
+        self.tokens.push(token);
+        self.spans.push(self.span);
+        self.ends.push(self.span + self.span_len);
+        self.cursor += 1;
+    }
+
+    fn push_match(&mut self, token: Token) -> Result<(), ()> {
+        match token {
+            Token::JumpForward(_) => {
+                // TODO: Figure this out
+                let cursor = self.cursor;
+                self.match_stack.push(cursor);
+                self.push_token(Token::JumpForward(0));
+            }
+            Token::JumpBackward(_) => {
+                let prev = self.match_stack.pop();
+                match prev {
+                    None => return self.error(),
+                    Some(i) => {
+                        let prev_cursor = self.prev_cursor;
+                        self.tokens[i] = Token::JumpForward(self.cursor + prev_cursor);
+                        self.push_token(Token::JumpBackward(i + prev_cursor));
+                    }
+                }
+            }
+            _ => self.push_token(token),
+        }
+        Ok(())
+    }
+
+    // Whole programs report stray_close to their caller instead
+    fn alias(&mut self, alias: char, command: char) {
+        if self.interactive && !self.aliased.iter().any(|&(_, c)| c == alias) {
+            self.messages.push(format!(
+                "{}  Read '{}' at offset {} as '{}'",
+                symbol_from_prompt(Prompt::Warning),
+                alias,
+                self.span,
+                command
+            ));
+        }
+        self.aliased.push((self.span, alias));
+    }
+
+    // Someone typing loses the whole line and has to start over, so that's
+    // an Err to stop tokenizing
+    fn stray(&mut self, c: char) -> Result<(), ()> {
+        self.errors.push(ParseError::NotCode(self.span, c));
+        if self.interactive {
+            let e = ParseError::NotCode(self.span, c);
+            let message = error::coded(e.code(), e.message());
+            self.messages.push(format!("{}  {}", symbol_from_prompt(Prompt::Error), message));
+            self.discard();
+            return Err(());
+        }
+        Ok(())
+    }
+
+    fn error(&mut self) -> Result<(), ()> {
+        self.errors.push(ParseError::UnbalancedClose(self.span));
+        if self.interactive {
+            let e = ParseError::UnbalancedClose(self.span);
+            let message = error::coded(e.code(), e.message());
+            self.messages.push(format!("{}  {}", symbol_from_prompt(Prompt::Error), message));
+            self.discard();
+            return Err(());
+        }
+        Ok(())
+    }
+
+    // Throw away everything since the last reset, as if it was never typed
+    fn discard(&mut self) {
+        self.tokens = Vec::new();
+        self.spans = Vec::new();
+        self.ends = Vec::new();
+        self.match_stack = Vec::new();
+        self.source.clear();
+        self.cursor = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.errors.clear();
+        self.tokens = Vec::new();
+        self.spans = Vec::new();
+        self.ends = Vec::new();
+        self.match_stack = Vec::new();
+        self.prev_cursor += self.cursor;
+        self.cursor = 0;
+        self.source_start += self.source.len();
+        self.source.clear();
+        self.aliased.clear();
+        self.messages.clear();
+    }
+
+    // What an interactive parser had to say about the input since this was
+    // last called, one line each with its prompt char
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+}
+
+// Program text from a file's bytes, however the editor that wrote it saved
+// it: a UTF-8 byte order mark is dropped, Windows line endings become
+// plain `\n` and bytes that aren't UTF-8, which can only be comments since
+// every command is ASCII, become U+FFFD. Offsets are into what this returns,
+// which is what an editor shows, so lines and columns come out right
+pub fn decode_source(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let text = String::from_utf8_lossy(bytes);
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text.into_owned()
+    }
+}
+
+// A `#!` first line is for the shell, so `#!/usr/bin/env brainf` scripts
+// can run as they are. It's blanked out rather than cut so offsets into
+// the rest still line up with the file
+pub fn strip_shebang(source: String) -> String {
+    if !source.starts_with("#!") {
+        return source;
+    }
+    let mut source = source;
+    let end = source.find('\n').unwrap_or(source.len());
+    source.replace_range(..end, &" ".repeat(end));
+    source
+}
+
+// A fresh interpreter with a whole program loaded, ready to interpret()
+pub fn load_program(source: &str) -> Result<Interpreter, ParseError> {
+    let mut interpreter = Interpreter::new();
+    interpreter.load_source(source)?;
+    Ok(interpreter)
+}
+
+// Run a whole program with the given input and hand back what it printed
+pub fn capture(source: &str, input: &[u8]) -> Result<Vec<u8>, Failure> {
+    capture_within(source, input, None)
+}
+
+// capture, stopping with an error after max_steps
+pub fn capture_within(source: &str, input: &[u8], max_steps: Option<u64>) -> Result<Vec<u8>, Failure> {
+    let mut interpreter = load_program(source)?;
+    interpreter.set_max_steps(max_steps);
+    let buffer = io::Buffer::new(input);
+    let output = buffer.output();
+    interpreter.set_io(Box::new(buffer));
+    interpreter.interpret()?;
+
+    let bytes = output.borrow().clone();
+    Ok(bytes)
+}
+
+// How often the dispatch loop looks up from its work, in steps
+const CHECKPOINT_MASK: u64 = 0xFFFF;
+
+// What a run did, see Interpreter::stats
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RunStats {
+    pub steps: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    // Cells, the most the tape held at once
+    pub peak_tape: usize,
+}
+
+// Interpreter reads tokens and executes their instructions
+pub struct Interpreter {
+    pub brain: Brain,
+    tokens: Vec<Token>,
+    spans: Vec<usize>,
+    ends: Vec<usize>,
+    source: String,
+    cursor: usize,
+    recovery: Option<Recovery>,
+    profiler: Option<Profiler>,
+    show_progress: bool,
+    max_steps: Option<u64>,
+    watchdog: Option<u64>,
+    // Steps without a change at the end of the last run_while, so a run
+    // that comes a slice at a time still trips the watchdog
+    quiet: u64,
+    strict: bool,
+    eof: Eof,
+    steps: u64,
+    step_mode: bool,
+    output_count: u64,
+    input_count: u64,
+    // Most cells the tape had at once this run, before any capacity cap
+    peak_tape: usize,
+    output_break: Option<u64>,
+    // Break conditions, each with whether it was true last time it was checked
+    conditions: Vec<(Condition, bool)>,
+    condition_hit: Option<usize>,
+    // Watch expressions and what they changed to in the last stretch of
+    // stepping, see watch::Watch
+    watches: Vec<Watch>,
+    watch_changes: Vec<WatchChange>,
+    watch_missed: usize,
+    // Iterations so far this run, indexed by the token index of each `[`
+    loop_counts: Vec<u64>,
+    // Most iterations allowed per run, by the source offset of each `[`
+    loop_quotas: BTreeMap<usize, u64>,
+    dialect: Option<Rc<dyn Dialect>>,
+    aliases: Option<Rc<Aliases>>,
+    strict_comments: bool,
+    // What load_source had to say that wasn't an error, see take_warnings
+    warnings: Vec<(usize, String)>,
+    trace: Option<Trace>,
+    recent: Option<Recent>,
+    journal: Option<Journal>,
+    // Tapes put aside by name, see Interpreter::save_state
+    savepoints: BTreeMap<String, Snapshot>,
+    // Levels of nested runs still allowed below this one
+    depth: u32,
+    outputs: OutputHistory,
+    formatter: Box<dyn BrainFormatter>,
+    io: Box<dyn IoHandler>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_brain(Brain::new())
+    }
+
+    // Start from an existing Brain, e.g. one backed by a SparseTape
+    pub fn with_brain(brain: Brain) -> Interpreter {
+        Interpreter {
+            brain,
+            tokens: Vec::new(),
+            spans: Vec::new(),
+            ends: Vec::new(),
+            source: String::new(),
+            cursor: 0,
+            warnings: Vec::new(),
+            recovery: None,
+            profiler: None,
+            show_progress: false,
+            max_steps: None,
+            watchdog: None,
+            quiet: 0,
+            strict: false,
+            eof: Eof::Unchanged,
+            steps: 0,
+            step_mode: false,
+            output_count: 0,
+            input_count: 0,
+            peak_tape: 0,
+            output_break: None,
+            conditions: Vec::new(),
+            condition_hit: None,
+            watches: Vec::new(),
+            watch_changes: Vec::new(),
+            watch_missed: 0,
+            loop_counts: Vec::new(),
+            loop_quotas: BTreeMap::new(),
+            dialect: None,
+            aliases: None,
+            strict_comments: false,
+            trace: None,
+            recent: None,
+            journal: None,
+            savepoints: BTreeMap::new(),
+            depth: MAX_DEPTH,
+            outputs: OutputHistory::default(),
+            formatter: Box::new(WindowFormatter::default()),
+            io: Box::new(Terminal::new()),
+        }
+    }
+
+    // Printing the memory cell state as a REPL feature
+    pub fn print_brain(&self) {
+        println!("{}", self.render_state());
+    }
+
+    // print_brain's line, prompt and all
+    pub fn render_state(&self) -> String {
+        format!("{} {}", symbol_from_prompt(Prompt::State), self.render_brain())
+    }
+
+    // The brain as print_brain would show it
+    pub fn render_brain(&self) -> String {
+        let mut out = String::new();
+        self.formatter
+            .render(&self.brain, &mut out)
+            .expect("formatting into a String can't fail");
+        out
+    }
+
+    // Where `,` and `.` go, the terminal unless told otherwise
+    pub fn set_io(&mut self, io: Box<dyn IoHandler>) {
+        self.io = io;
+    }
+
+    pub fn set_formatter(&mut self, formatter: Box<dyn BrainFormatter>) {
+        self.formatter = formatter;
+    }
+
+    // Run a dialect's own instructions, and parse anything loaded from
+    // now on as that dialect. None goes back to plain brainfuck
+    pub fn set_dialect(&mut self, dialect: Option<Rc<dyn Dialect>>) {
+        self.dialect = dialect;
+    }
+
+    pub fn dialect(&self) -> Option<&Rc<dyn Dialect>> {
+        self.dialect.as_ref()
+    }
+
+    // Extra characters to read as commands in anything loaded from now on
+    pub fn set_aliases(&mut self, aliases: Option<Rc<Aliases>>) {
+        self.aliases = aliases;
+    }
+
+    pub fn aliases(&self) -> Option<&Rc<Aliases>> {
+        self.aliases.as_ref()
+    }
+
+    // Refuse source with anything but code and whitespace in it from now
+    // on, see Parser::set_strict_comments
+    pub fn set_strict_comments(&mut self, on: bool) {
+        self.strict_comments = on;
+    }
+
+    pub fn strict_comments(&self) -> bool {
+        self.strict_comments
+    }
+
+    // Queue up bytes for `,` to read before it asks the io handler's usual source
+    pub fn feed(&mut self, input: &[u8]) {
+        self.io.feed(input);
+    }
+
+    // Checkpoint the tape while programs run, see snapshot::Recovery
+    pub fn set_recovery(&mut self, recovery: Option<Recovery>) {
+        self.recovery = recovery;
+    }
+
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
+    }
+
+    // Time every loop from now on, see profile::Profiler
+    pub fn set_profiling(&mut self, on: bool) {
+        self.profiler = if on { Some(Profiler::new()) } else { None };
+    }
+
+    // The profile of the last run, if profiling is on
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn print_profile(&self) {
+        if let Some(profile) = self.render_profile() {
+            println!("{}", profile);
+        }
+    }
+
+    // The table print_profile shows, None when profiling is off
+    pub fn render_profile(&self) -> Option<String> {
+        let report = self.profiler.as_ref()?.report();
+        if report.is_empty() {
+            return Some(format!("{}  No loops ran", symbol_from_prompt(Prompt::State)));
+        }
+
+        let mut lines = vec![format!(
+            "{}  {:>6} {:>14} {:>12}  source",
+            symbol_from_prompt(Prompt::State),
+            "loop",
+            "iterations",
+            "time"
+        )];
+        for stats in report {
+            lines.push(format!(
+                "    {:>6} {:>14} {:>12}  {}",
+                format!("#{}", stats.open),
+                format::thousands(stats.iterations as usize),
+                format!("{:.2?}", stats.time),
+                self.loop_source(stats.open, stats.close)
+            ));
+        }
+        Some(lines.join("\n"))
+    }
+
+    // (open, close, iterations) of every loop entered this run, busiest first
+    pub fn loop_counts(&self) -> Vec<(usize, usize, u64)> {
+        let mut loops: Vec<(usize, usize, u64)> = self.loop_counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &n)| n > 0)
+            .filter_map(|(open, &n)| self.partner(open).map(|close| (open, close, n)))
+            .collect();
+        loops.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        loops
+    }
+
+    pub fn print_loops(&self) {
+        println!("{}", self.render_loops());
+    }
+
+    pub fn render_loops(&self) -> String {
+        let loops = self.loop_counts();
+        if loops.is_empty() {
+            return format!("{}  No loops ran", symbol_from_prompt(Prompt::State));
+        }
+
+        let mut lines = vec![format!(
+            "{}  {:>6} {:>14}  source",
+            symbol_from_prompt(Prompt::State),
+            "loop",
+            "iterations"
+        )];
+        for (open, close, n) in loops {
+            lines.push(format!(
+                "    {:>6} {:>14}  {}",
+                format!("#{}", open),
+                format::thousands(n as usize),
+                self.loop_source(open, close)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    // The text of a bracket pair, shortened if it's long
+    fn loop_source(&self, open: usize, close: usize) -> String {
+        let text = match (self.spans.get(open), self.ends.get(close)) {
+            (Some(&start), Some(&end)) => self.source.get(start..end).unwrap_or(""),
+            _ => "",
+        };
+        if text.chars().count() > 32 {
+            let short: String = text.chars().take(31).collect();
+            format!("{}…", short)
+        } else {
+            text.to_string()
+        }
+    }
+
+    // Record every step of each run, see trace::Trace
+    pub fn set_trace(&mut self, trace: Option<Trace>) {
+        self.trace = trace;
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    // Write down every cell change of each run, see journal::Journal
+    pub fn set_journal(&mut self, journal: Option<Journal>) {
+        self.journal = journal;
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    // What the last few runs wrote, see history::OutputHistory
+    pub fn outputs(&self) -> &OutputHistory {
+        &self.outputs
+    }
+
+    pub fn set_output_history(&mut self, runs: usize) {
+        self.outputs.set_limit(runs);
+    }
+
+    // Keep a status line with steps and speed going during long runs
+    // Only makes sense when stderr is a terminal
+    pub fn set_show_progress(&mut self, on: bool) {
+        self.show_progress = on;
+    }
+
+    // Give up on a run after this many instructions
+    pub fn set_max_steps(&mut self, max: Option<u64>) {
+        self.max_steps = max;
+    }
+
+    // Let the loop whose `[` is at this source offset go round at most
+    // limit times a run, None lifts it. Other loops run freely
+    pub fn set_loop_quota(&mut self, offset: usize, limit: Option<u64>) {
+        match limit {
+            Some(limit) => self.loop_quotas.insert(offset, limit),
+            None => self.loop_quotas.remove(&offset),
+        };
+    }
+
+    // (offset of `[`, limit) of every loop with a quota
+    pub fn loop_quotas(&self) -> Vec<(usize, u64)> {
+        self.loop_quotas.iter().map(|(&offset, &limit)| (offset, limit)).collect()
+    }
+
+    pub fn clear_loop_quotas(&mut self) {
+        self.loop_quotas.clear();
+    }
+
+    // Stop a run that goes this many steps without writing anything or
+    // changing a cell, since it's probably stuck. Unlike max_steps it lets
+    // long runs go on as long as they're getting somewhere. The stopped
+    // run can be picked up again like one that hit the step limit
+    pub fn set_watchdog(&mut self, quiet: Option<u64>) {
+        self.watchdog = quiet.filter(|&quiet| quiet > 0);
+    }
+
+    // Make `<` on cell 0 stop the run with RuntimeError::PointerUnderflow
+    // rather than stay put
+    pub fn set_strict(&mut self, on: bool) {
+        self.strict = on;
+    }
+
+    pub fn watchdog(&self) -> Option<u64> {
+        self.watchdog
+    }
+
+    // What `,` does at the end of the input, see io::Eof
+    pub fn set_eof(&mut self, eof: Eof) {
+        self.eof = eof;
+    }
+
+    pub fn eof(&self) -> Eof {
+        self.eof
+    }
+
+    // Step limit, tape capacity cap and nesting depth all at once
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.max_steps = limits.steps;
+        self.brain.set_capacity_cap(limits.capacity_cap);
+        self.depth = limits.depth;
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            steps: self.max_steps,
+            capacity_cap: self.brain.capacity_cap(),
+            depth: self.depth,
+        }
+    }
+
+    // Pause a run right after it writes its nth byte
+    pub fn set_output_break(&mut self, n: Option<u64>) {
+        self.output_break = n;
+    }
+
+    pub fn output_break(&self) -> Option<u64> {
+        self.output_break
+    }
+
+    // Bytes written since interpret() last started a run
+    pub fn output_count(&self) -> u64 {
+        self.output_count
+    }
+
+    // How many instructions the last run executed
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // Everything counted about the last run, the way --time shows it
+    pub fn stats(&self) -> RunStats {
+        RunStats {
+            steps: self.steps,
+            input_bytes: self.input_count,
+            output_bytes: self.output_count,
+            peak_tape: self.peak_tape.max(self.brain.len()),
+        }
+    }
+
+    // Tokens without a parser have no source, so they all point at its end
+    pub fn take_tokens(&mut self, mut tokens: Vec<Token>) {
+        let end = self.source.len();
+        self.spans.extend(tokens.iter().map(|_| end));
+        self.ends.extend(tokens.iter().map(|_| end));
+        self.tokens.append(&mut tokens);
+    }
+
+    // Offsets into the source that was loaded and what's worth knowing
+    // about each, e.g. an alias read as a command, to give with
+    // error::CODE_ALIASED. Each comes out once
+    pub fn take_warnings(&mut self) -> Vec<(usize, String)> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Take everything the parser has finished with, spans and source included
+    // The parser still needs a reset() afterwards
+    pub fn load(&mut self, parser: &mut Parser) {
+        self.tokens.append(&mut parser.tokens);
+        self.spans.append(&mut parser.spans);
+        self.ends.append(&mut parser.ends);
+        self.source.push_str(&parser.source);
+    }
+
+    // Tokenize a whole program onto the end of what's loaded, `?` being a
+    // comment. Bracket errors give offsets into source itself, and so do
+    // warnings about aliases, which wait in take_warnings
+    pub fn load_source(&mut self, source: &str) -> Result<(), ParseError> {
+        let base = self.source.len();
+        let (mut parser, errors) = self.parse(source);
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
+        }
+        // One line per alias however often it turns up
+        let mut seen = Vec::new();
+        for &(offset, alias) in &parser.aliased {
+            if seen.contains(&alias) {
+                continue;
+            }
+            seen.push(alias);
+            let count = parser.aliased.iter().filter(|&&(_, c)| c == alias).count();
+            let command = self.aliases.as_ref().and_then(|aliases| aliases.get(alias));
+            let times = if count == 1 { "once".to_string() } else { format!("{} times", count) };
+            let message = format!("Read '{}' as '{}' {}", alias, command.unwrap_or(alias), times);
+            self.warnings.push((offset - base, message));
+        }
+        self.load(&mut parser);
+        Ok(())
+    }
+
+    // `code!input`, the way a lot of programs get handed around: the code
+    // before the first `!` that isn't part of an instruction, and what
+    // comes after it, which is input for `,` rather than code. No `!`
+    // means it's all code
+    pub fn split_input<'a>(&self, source: &'a str) -> (&'a str, Option<&'a str>) {
+        let consumed = |i: usize| {
+            if self.aliases.as_ref().is_some_and(|aliases| aliases.get('!').is_some()) {
+                return true;
+            }
+            self.dialect.as_ref().is_some_and(|dialect| {
+                dialect.token('!').is_some()
+                    || dialect.words().iter().any(|&(word, _)| {
+                        word.match_indices('!')
+                            .any(|(j, _)| i >= j && source[i - j..].starts_with(word))
+                    })
+            })
+        };
+        match source.match_indices('!').map(|(i, _)| i).find(|&i| !consumed(i)) {
+            Some(i) => (&source[..i], Some(&source[i + 1..])),
+            None => (source, None),
+        }
+    }
+
+    // Everything that would stop source loading, first to last, without
+    // loading any of it. Offsets are into source
+    pub fn parse_errors(&self, source: &str) -> Vec<ParseError> {
+        self.parse(source).1
+    }
+
+    // source with the `]` it's missing at the end, and how many that took,
+    // for a program that got cut off. None unless unclosed `[` are all
+    // that's wrong with it
+    pub fn fix_brackets(&self, source: &str) -> Option<(String, usize)> {
+        let errors = self.parse_errors(source);
+        if errors.is_empty() || !errors.iter().all(|e| matches!(e, ParseError::Unclosed(_))) {
+            return None;
+        }
+        let code = source.trim_end();
+        let fixed = format!("{}{}{}", code, "]".repeat(errors.len()), &source[code.len()..]);
+        // A dialect that spells `]` some other way can't be fixed like this
+        if !self.parse_errors(&fixed).is_empty() {
+            return None;
+        }
+        Some((fixed, errors.len()))
+    }
+
+    fn parse(&self, source: &str) -> (Parser, Vec<ParseError>) {
+        let base = self.source.len();
+        let mut parser = Parser {
+            interactive: false,
+            ..Parser::after(self)
+        };
+        parser.tokenize(source);
+        let unclosed = parser.match_stack.iter().map(|&open| ParseError::Unclosed(parser.spans[open]));
+        let mut errors: Vec<ParseError> = parser.errors.iter().cloned().chain(unclosed).collect();
+        errors.sort_by_key(ParseError::offset);
+        let errors = errors.into_iter().map(|e| e.shifted_back(base)).collect();
+        (parser, errors)
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    // Byte offset into source() of each token
+    pub fn spans(&self) -> &[usize] {
+        &self.spans
+    }
+
+    // Byte offset into source() just past each token, which is spans()
+    // plus however long the alias or word it was written as is
+    pub fn ends(&self) -> &[usize] {
+        &self.ends
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // The other half of the bracket at token i, straight from the jump table
+    pub fn partner(&self, i: usize) -> Option<usize> {
+        match self.tokens.get(i) {
+            Some(&Token::JumpForward(j)) | Some(&Token::JumpBackward(j)) => Some(j),
+            _ => None,
+        }
+    }
+
+    // Run until the program ends or a limit stops it
+    // A stopped run can pick up where it left off with another interpret()
+    pub fn interpret(&mut self) -> Result<(), RuntimeError> {
+        self.begin_run();
+        self.run_while(|_| true)
+    }
+
+    // Forget the profile and counters of the last run
+    // interpret() does this itself, stepping through code doesn't
+    pub fn begin_run(&mut self) {
+        self.outputs.begin();
+        self.clear_run();
+    }
+
+    // Back to a blank tape at the start of the program, as if it had just
+    // been loaded. The tokens and jump table stay as they are, so running
+    // the same program again and again only pays for parsing once
+    // Output the io handler is still holding on to goes too
+    pub fn rewind(&mut self) {
+        self.brain.clear();
+        self.cursor = 0;
+        self.steps = 0;
+        self.io.rewind();
+        self.clear_run();
+    }
+
+    // Back to how a new interpreter starts out, one zero cell and nothing
+    // loaded, without losing the settings, breakpoints and watches, or the
+    // io handler and any input queued on it
+    pub fn reset(&mut self) {
+        self.brain.clear();
+        self.tokens.clear();
+        self.spans.clear();
+        self.ends.clear();
+        self.source.clear();
+        self.cursor = 0;
+        self.steps = 0;
+        self.clear_run();
+    }
+
+    fn clear_run(&mut self) {
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.clear();
+        }
+        self.output_count = 0;
+        self.input_count = 0;
+        self.peak_tape = 0;
+        self.quiet = 0;
+        self.loop_counts.clear();
+        if let Some(ref mut trace) = self.trace {
+            trace.clear();
+        }
+        if let Some(ref mut recent) = self.recent {
+            recent.clear();
+        }
+        if let Some(ref mut journal) = self.journal {
+            journal.clear();
+        }
+    }
+
+    // Run at most n instructions, so a front end can draw between slices
+    // of a long program on one thread. True while there's more to run
+    pub fn interpret_chunk(&mut self, n: u64) -> Result<bool, RuntimeError> {
+        self.step(n)?;
+        Ok(!self.is_finished())
+    }
+
+    // Like interpret() but asks keep_going before every instruction
+    // and stops as soon as it says no
+    pub fn run_while<F>(&mut self, mut keep_going: F) -> Result<(), RuntimeError>
+    where
+        F: FnMut(&Interpreter) -> bool,
+    {
+        self.loop_counts.resize(self.tokens.len(), 0);
+        let mut result = Ok(());
+        let mut steps: u64 = 0;
+        // Last step that wrote output or might have changed a cell, 0 for
+        // none yet, which carries on from the quiet steps before
+        let mut last_change: u64 = 0;
+        let carried = self.quiet;
+        self.condition_hit = None;
+        if !self.conditions.is_empty() {
+            self.arm_conditions();
+        }
+        if !self.watches.is_empty() {
+            self.arm_watches();
+        }
+        let mut progress = if self.show_progress {
+            Some(Progress::start())
+        } else {
+            None
+        };
+        while self.cursor < self.tokens.len() && keep_going(self) {
+            if self.max_steps.is_some_and(|max| steps >= max) {
+                result = Err(RuntimeError::StepLimit(steps));
+                break;
+            }
+            if steps & CHECKPOINT_MASK == 0 {
+                let since = if last_change == 0 { carried + steps } else { steps - last_change };
+                if self.watchdog.is_some_and(|quiet| since >= quiet) {
+                    result = Err(RuntimeError::Stalled(since));
+                    break;
+                }
+            }
+            steps += 1;
+            if steps & CHECKPOINT_MASK == 0 {
+                if let Some(ref mut recovery) = self.recovery {
+                    recovery.tick(&self.brain);
+                }
+                if let Some(ref mut progress) = progress {
+                    progress.tick(steps);
+                }
+            }
+
+            let cursor = self.cursor;
+            let before = self.journal.as_ref().map(|_| (self.brain.ptr, self.brain.current()));
+            let mut pause = false;
+            // Where to go next, straight back to the `[` for a `]` so a loop
+            // at token 0 doesn't need cursor 0 - 1
+            let mut next = cursor + 1;
+            match self.tokens[cursor] {
+                Token::PointerIncrement => self.brain.ptr_right(),
+                Token::PointerDecrement if self.strict && self.brain.ptr == 0 => {
+                    // Nothing moved, so the step doesn't count
+                    steps -= 1;
+                    result = Err(RuntimeError::PointerUnderflow);
+                    break;
+                }
+                Token::PointerDecrement => self.brain.ptr_left(),
+                Token::DataIncrement => {
+                    self.brain.increment();
+                    last_change = steps;
+                }
+                Token::DataDecrement => {
+                    self.brain.decrement();
+                    last_change = steps;
+                }
+                Token::Output => {
+                    last_change = steps;
+                    self.io.write(self.brain.current());
+                    self.outputs.push(self.brain.current());
+                    self.output_count += 1;
+                    pause = self.output_break == Some(self.output_count);
+                }
+                Token::Input => {
+                    self.input();
+                    last_change = steps;
+                }
+                Token::JumpForward(i) => match self.forward(i) {
+                    Ok(to) => next = to,
+                    Err(e) => {
+                        // The `[` never ran, so a resumed run tries it again
+                        steps -= 1;
+                        result = Err(e);
+                        break;
+                    }
+                },
+                Token::JumpBackward(i) => next = i,
+                Token::Extension(c) => {
+                    last_change = steps;
+                    if let Some(ref dialect) = self.dialect {
+                        let mut io = Capture {
+                            io: &mut *self.io,
+                            history: &mut self.outputs,
+                        };
+                        let limits = Limits {
+                            steps: self.max_steps.map(|max| max.saturating_sub(steps)),
+                            capacity_cap: self.brain.capacity_cap(),
+                            depth: self.depth,
+                        };
+                        steps += dialect.execute_limited(c, &mut self.brain, &mut io, &limits);
+                    }
+                }
+            }
+            if let Some(ref mut trace) = self.trace {
+                trace.push(TraceEntry {
+                    cursor,
+                    ptr: self.brain.ptr,
+                    cell: self.brain.current(),
+                });
+            }
+            if let Some(ref mut recent) = self.recent {
+                recent.push(cursor);
+            }
+            if let (Some(journal), Some((ptr, old))) = (self.journal.as_mut(), before) {
+                journal.step(ptr, old, self.brain.get(ptr));
+            }
+            if !self.watches.is_empty() {
+                self.check_watches(cursor);
+            }
+            self.cursor = next;
+            if pause || (!self.conditions.is_empty() && self.check_conditions()) {
+                break;
+            }
+        }
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+        if self.is_finished() {
+            if let Some(ref dialect) = self.dialect {
+                dialect.finish(&mut Capture {
+                    io: &mut *self.io,
+                    history: &mut self.outputs,
+                });
+            }
+        }
+        self.io.flush();
+        if let Some(e) = self.io.take_error() {
+            result = Err(RuntimeError::Io(e.to_string()));
+        }
+        self.peak_tape = self.peak_tape.max(self.brain.len());
+        self.brain.enforce_capacity_cap();
+        if let Some(ref mut recovery) = self.recovery {
+            recovery.write(&self.brain);
+        }
+        // Picking a stalled run back up starts the count again
+        self.quiet = match result {
+            Err(RuntimeError::Stalled(_)) => 0,
+            _ if last_change == 0 => carried + steps,
+            _ => steps - last_change,
+        };
+        self.steps = steps;
+        result
+    }
+
+    // Nothing left to read does whatever eof says
+    fn input(&mut self) {
+        match (self.io.read(), self.eof) {
+            (Some(byte), _) => {
+                self.brain.store(byte);
+                self.input_count += 1;
+            }
+            (None, Eof::Unchanged) => (),
+            (None, Eof::Zero) => self.brain.store(0),
+            (None, Eof::Max) => self.brain.store(255),
+        }
+    }
+
+    // Where to go from the `[` at the cursor that matches the `]` at i
+    fn forward(&mut self, i: usize) -> Result<usize, RuntimeError> {
+        let zero = self.brain.is_zero();
+        if !zero && !self.loop_quotas.is_empty() {
+            let offset = self.spans[self.cursor];
+            if let Some(&limit) = self.loop_quotas.get(&offset) {
+                if self.loop_counts[self.cursor] >= limit {
+                    return Err(RuntimeError::LoopQuota { offset, limit });
+                }
+            }
+        }
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.test(self.cursor, i, !zero);
+        }
+        if zero {
+            Ok(i + 1)
+        } else {
+            self.loop_counts[self.cursor] += 1;
+            Ok(self.cursor + 1)
+        }
+    }
+}
+
+// The data cells and cell pointer
+pub struct Brain {
+    tape: Box<dyn Tape>,
+    ptr: usize,
+    capacity_cap: Option<usize>,
+}
+
+impl Default for Brain {
+    fn default() -> Brain {
+        Brain::new()
+    }
+}
+
+impl Brain {
+    pub fn new() -> Brain {
+        Brain::with_tape(Box::new(DenseTape::new()))
+    }
+
+    // Cells start..end only, pointer in brackets wherever it is, for a
+    // tape too long to read all at once. See format::RangeFormatter
+    pub fn render_range(&self, start: usize, end: usize) -> String {
+        let formatter = RangeFormatter {
+            start,
+            end: Some(end),
+            ..RangeFormatter::default()
+        };
+        let mut out = String::new();
+        formatter
+            .render(self, &mut out)
+            .expect("formatting into a String can't fail");
+        out
+    }
+
+    // Swap in a different storage strategy, e.g. a SparseTape
+    pub fn with_tape(mut tape: Box<dyn Tape>) -> Brain {
+        tape.grow(1);
+        Brain {
+            tape,
+            ptr: 0,
+            capacity_cap: None,
+        }
+    }
+
+    fn ptr_right(&mut self) {
+        self.ptr += 1;
+        if self.ptr >= self.tape.len() {
+            self.tape.grow(self.ptr + 1);
+        }
+    }
+
+    fn ptr_left(&mut self) {
+        if self.ptr == 0 {
+            return;
+        }
+        self.ptr -= 1;
+    }
+
+    fn increment(&mut self) {
+        self.add(1)
+    }
+
+    fn decrement(&mut self) {
+        let cell = self.current();
+        self.tape.set(self.ptr, cell.wrapping_sub(1));
+    }
+
+    fn store(&mut self, n: u8) {
+        self.tape.set(self.ptr, n);
+    }
+
+    fn add(&mut self, n: u8) {
+        let cell = self.current();
+        self.tape.set(self.ptr, cell.wrapping_add(n));
+    }
+
+    fn is_zero(&self) -> bool {
+        self.current() == 0
+    }
+
+    pub fn ptr(&self) -> usize {
+        self.ptr
+    }
+
+    // For dialects, which need to change the tape in ways tokens don't
+    pub fn set_current(&mut self, value: u8) {
+        self.store(value);
+    }
+
+    pub fn set_ptr(&mut self, ptr: usize) {
+        self.ptr = ptr;
+        if self.ptr >= self.tape.len() {
+            self.tape.grow(self.ptr + 1);
+        }
+    }
+
+    pub fn get(&self, i: usize) -> u8 {
+        self.tape.get(i)
+    }
+
+    pub fn current(&self) -> u8 {
+        self.tape.get(self.ptr)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tape.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tape.is_empty()
+    }
+
+    // Every cell back to zero and the pointer to cell 0, keeping the memory
+    // for the next run
+    pub fn clear(&mut self) {
+        self.tape.truncate(0);
+        self.tape.grow(1);
+        self.ptr = 0;
+    }
+
+    // Drop trailing zero cells past the pointer and give the memory back
+    pub fn shrink(&mut self) {
+        let keep = self.tape
+            .last_nonzero()
+            .map_or(1, |i| i + 1)
+            .max(self.ptr + 1);
+        self.tape.truncate(keep);
+        self.tape.release(0);
+    }
+
+    // Cap how many cells worth of memory are kept around between runs
+    // None means the tape keeps whatever it grew to
+    pub fn set_capacity_cap(&mut self, cap: Option<usize>) {
+        self.capacity_cap = cap;
+        self.enforce_capacity_cap();
+    }
+
+    pub fn capacity_cap(&self) -> Option<usize> {
+        self.capacity_cap
+    }
+
+    fn enforce_capacity_cap(&mut self) {
+        if let Some(cap) = self.capacity_cap {
+            if self.tape.capacity() > cap {
+                self.shrink();
+                self.tape.release(cap);
+            }
+        }
+    }
+
+    // Indices of every cell whose value satisfies the predicate
+    pub fn find<F: Fn(u8) -> bool>(&self, pred: F) -> Vec<usize> {
+        (0..self.tape.len())
+            .filter(|&i| pred(self.tape.get(i)))
+            .collect()
+    }
+}
+
+// Custom display to indicate current memory state
+// Big tapes only show a window around the pointer, `{:#}` shows everything
+impl fmt::Display for Brain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            FullFormatter.render(self, f)
+        } else {
+            WindowFormatter::default().render(self, f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::alias::Aliases;
+    use super::error::{RuntimeError, EXIT_FAULT};
+    use super::Interpreter;
+    #[cfg(feature = "parallel")]
+    use super::{error::ParseError, Parser};
+
+    // A resumed tape can start with the cell set and the program's first
+    // token a `[`, whose `]` used to jump back to cursor 0 - 1
+    #[test]
+    fn loop_at_token_zero_on_a_set_cell() {
+        let mut interpreter = Interpreter::new();
+        interpreter.brain.set_current(3);
+        interpreter.load_source("[-]").unwrap();
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.brain.current(), 0);
+        assert_eq!(interpreter.steps(), 10);
+    }
+
+    // One warning per alias, at the first place it's read, and it's for
+    // the caller rather than stderr
+    #[test]
+    fn aliases_read_are_warned_about_once() {
+        let mut aliases = Aliases::default();
+        aliases.add('a', '+').unwrap();
+        aliases.add('b', '.').unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.load_source("++").unwrap();
+        interpreter.set_aliases(Some(Rc::new(aliases)));
+        interpreter.load_source("+a a b").unwrap();
+        assert_eq!(
+            interpreter.take_warnings(),
+            [(1, "Read 'a' as '+' 2 times".to_string()), (5, "Read 'b' as '.' once".to_string())]
+        );
+        assert!(interpreter.take_warnings().is_empty());
+    }
+
+    // strict_comments keeps a program on the sequential tokenizer, and
+    // changes nothing about one that's all commands
+    #[cfg(feature = "parallel")]
+    fn both_ways(source: &str) -> (Parser, Parser) {
+        let (mut parallel, mut sequential) = (Parser::program(), Parser::program());
+        assert!(source.len() >= super::parallel::THRESHOLD);
+        parallel.tokenize(source);
+        sequential.set_strict_comments(true);
+        sequential.tokenize(source);
+        (parallel, sequential)
+    }
+
+    // Brackets opened in one chunk and closed chunks later
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_tokenizer_matches_sequential() {
+        let mut source = "[>".repeat(500);
+        for i in 0..900_000 {
+            source.push_str(if i % 7 == 0 { "[-]" } else { "+" });
+        }
+        source.push_str(&"]<".repeat(500));
+        source.push_str(&"+".repeat(600_000));
+        let (parallel, sequential) = both_ways(&source);
+        assert!(parallel.errors.is_empty());
+        assert_eq!(parallel.tokens, sequential.tokens);
+        assert_eq!(parallel.spans, sequential.spans);
+        assert_eq!(parallel.ends, sequential.ends);
+        assert_eq!(parallel.match_stack, sequential.match_stack);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_tokenizer_finds_a_late_stray_close() {
+        let mut source = "[".to_string();
+        source.push_str(&"+".repeat(1_100_000));
+        source.push_str("]]+[");
+        let (parallel, sequential) = both_ways(&source);
+        assert_eq!(parallel.errors, vec![ParseError::UnbalancedClose(1_100_002)]);
+        assert_eq!(parallel.errors, sequential.errors);
+    }
+
+    // The way /ws runs a program, a slice of run_while at a time
+    #[test]
+    fn watchdog_counts_across_slices() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_watchdog(Some(200_000));
+        interpreter.load_source("+[]").unwrap();
+        interpreter.begin_run();
+        let mut stopped = Ok(());
+        for _ in 0..10 {
+            let mut left = 100_000;
+            stopped = interpreter.run_while(|_| {
+                left -= 1;
+                left > 0
+            });
+            if stopped.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(stopped, Err(RuntimeError::Stalled(quiet)) if quiet >= 200_000));
+    }
+
+    #[test]
+    fn strict_stops_on_the_left_edge() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_strict(true);
+        interpreter.load_source("+<+").unwrap();
+        assert_eq!(interpreter.interpret(), Err(RuntimeError::PointerUnderflow));
+        assert_eq!((interpreter.steps(), interpreter.cursor()), (1, 1));
+        assert_eq!(RuntimeError::PointerUnderflow.exit_code(), EXIT_FAULT);
+    }
+}
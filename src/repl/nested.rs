@@ -0,0 +1,150 @@
+use super::dialect::Dialect;
+use super::io::{Buffer, IoHandler};
+use super::{Brain, Interpreter, Token};
+
+// How many host calls deep programs can run each other
+pub const MAX_DEPTH: u32 = 16;
+
+// What a run is allowed, or has left. Anything a run starts on its own
+// behalf gets what's left rather than a fresh allowance, so a program
+// can't dodge max-steps by handing its work to a child
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Limits {
+    pub steps: Option<u64>,
+    pub capacity_cap: Option<usize>,
+    // How many more levels of nesting are allowed below this one
+    pub depth: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            steps: None,
+            capacity_cap: None,
+            depth: MAX_DEPTH,
+        }
+    }
+}
+
+impl Interpreter {
+    // A fresh interpreter for running something inside a run with these
+    // limits left, one level deeper
+    pub fn nested(limits: &Limits) -> Result<Interpreter, String> {
+        if limits.depth == 0 {
+            return Err(format!("Can't nest runs more than {} deep", MAX_DEPTH));
+        }
+        let mut child = Interpreter::new();
+        child.set_limits(Limits {
+            depth: limits.depth - 1,
+            ..*limits
+        });
+        Ok(child)
+    }
+}
+
+// The host dialect: brainfuck plus `%`, which calls back into the program
+// embedding the interpreter. The callback gets the machine and what's
+// left of the run's limits, and can run programs of its own on child
+// machines with them
+//
+//     let host = Host::new(|call: &mut HostCall| {
+//         let output = call.run("++++++[>++++++++<-]>.", b"").unwrap_or_default();
+//         call.brain.set_current(output.first().cloned().unwrap_or(0));
+//     });
+//     interpreter.set_dialect(Some(Rc::new(host)));
+pub struct Host<F> {
+    callback: F,
+}
+
+impl<F: Fn(&mut HostCall)> Host<F> {
+    pub fn new(callback: F) -> Host<F> {
+        Host { callback }
+    }
+}
+
+impl<F: Fn(&mut HostCall)> Dialect for Host<F> {
+    fn name(&self) -> &str {
+        "host"
+    }
+
+    fn token(&self, c: char) -> Option<Token> {
+        match c {
+            '%' => Some(Token::Extension(c)),
+            _ => Token::from_char(c),
+        }
+    }
+
+    fn execute_limited(
+        &self,
+        _c: char,
+        brain: &mut Brain,
+        io: &mut dyn IoHandler,
+        limits: &Limits,
+    ) -> u64 {
+        let mut call = HostCall {
+            brain,
+            io,
+            limits: *limits,
+            used: 0,
+        };
+        (self.callback)(&mut call);
+        call.used
+    }
+}
+
+// One `%`, as the host callback sees it. The steps child runs take are
+// charged to the run that made the call
+pub struct HostCall<'a> {
+    pub brain: &'a mut Brain,
+    pub io: &'a mut dyn IoHandler,
+    limits: Limits,
+    used: u64,
+}
+
+impl<'a> HostCall<'a> {
+    // What the next child run gets, less whatever earlier ones used up
+    pub fn limits(&self) -> Limits {
+        Limits {
+            steps: self.limits.steps.map(|steps| steps.saturating_sub(self.used)),
+            ..self.limits
+        }
+    }
+
+    // Steps child runs have taken so far
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    // A child machine to set up before handing it to run_child, e.g. with
+    // a dialect of its own
+    pub fn child(&self) -> Result<Interpreter, String> {
+        Interpreter::nested(&self.limits())
+    }
+
+    // Run a plain brainfuck program on a child machine and get its output
+    pub fn run(&mut self, source: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+        let mut child = self.child()?;
+        self.run_child(&mut child, source, input)
+    }
+
+    // The same on a child from child(), or one from another `run_child` with
+    // its tape still there. Either way it only gets the steps left now
+    pub fn run_child(
+        &mut self,
+        child: &mut Interpreter,
+        source: &str,
+        input: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        child.load_source(source).map_err(|e| e.to_string())?;
+        let buffer = Buffer::new(input);
+        let output = buffer.output();
+        child.set_io(Box::new(buffer));
+        child.set_max_steps(self.limits().steps);
+        let result = child.interpret();
+        self.used += child.steps();
+        result.map_err(|e| e.to_string())?;
+
+        let bytes = output.borrow().clone();
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,56 @@
+use rayon::prelude::*;
+
+use super::Token;
+
+// Inputs smaller than this aren't worth waking the thread pool for
+pub const THRESHOLD: usize = 1 << 20;
+
+const MIN_CHUNK: usize = 1 << 16;
+
+// A slice of the program lexed on its own
+// brackets are indices into tokens, their targets get filled in later
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub tokens: Vec<Token>,
+    pub spans: Vec<usize>,
+    pub brackets: Vec<usize>,
+}
+
+// Tokens for input in order, one Chunk per slice
+// base is the offset of input in the session source
+pub fn lex(base: usize, input: &str) -> Vec<Chunk> {
+    boundaries(input)
+        .par_iter()
+        .map(|&(start, end)| lex_chunk(base + start, &input[start..end]))
+        .collect()
+}
+
+fn lex_chunk(base: usize, input: &str) -> Chunk {
+    let mut chunk = Chunk::default();
+    for (i, c) in input.char_indices() {
+        if let Some(token) = Token::from_char(c) {
+            if let Token::JumpForward(_) | Token::JumpBackward(_) = token {
+                chunk.brackets.push(chunk.tokens.len());
+            }
+            chunk.tokens.push(token);
+            chunk.spans.push(base + i);
+        }
+    }
+    chunk
+}
+
+// Roughly even slices, a few per thread, cut on char boundaries
+fn boundaries(input: &str) -> Vec<(usize, usize)> {
+    let size = (input.len() / (rayon::current_num_threads() * 4)).max(MIN_CHUNK);
+    let mut bounds = Vec::new();
+    let mut start = 0;
+    while start < input.len() {
+        let mut end = (start + size).min(input.len());
+        while !input.is_char_boundary(end) {
+            end += 1;
+        }
+        bounds.push((start, end));
+        start = end;
+    }
+    bounds
+}
@@ -0,0 +1,169 @@
+// Programs joined up the way a shell pipe joins commands: what one writes
+// is what the next one reads, a chunk at a time as it's written rather
+// than once the first one is done. Each runs on a thread of its own, the
+// first reading the pipe's input and the last writing to stdout
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+use super::cli::RunOptions;
+use super::error::RuntimeError;
+use super::io::{IoHandler, Split, Stdio};
+use super::Interpreter;
+
+// Output is sent on once this much of it builds up, or when the program
+// reads or stops
+const CHUNK: usize = 4096;
+
+// Chunks in flight between two programs before the writer has to wait
+const DEPTH: usize = 16;
+
+// How one program in the pipe went
+#[derive(Debug)]
+pub struct Stage {
+    pub result: Result<(), RuntimeError>,
+    // The next program stopped reading, which stopped this one the way a
+    // closed pipe does, so it isn't a failure
+    pub hung_up: bool,
+}
+
+// Run every program at once, sources already known to load. input is for
+// the first one, stdin when there isn't any
+pub fn run(sources: &[String], options: &RunOptions, input: Option<&[u8]>) -> Vec<Stage> {
+    let mut receivers = Vec::new();
+    let mut senders = Vec::new();
+    for _ in 1..sources.len() {
+        let (tx, rx) = mpsc::sync_channel(DEPTH);
+        senders.push(Some(tx));
+        receivers.push(Some(rx));
+    }
+    senders.push(None);
+    receivers.insert(0, None);
+
+    thread::scope(|scope| {
+        let stages: Vec<_> = sources
+            .iter()
+            .zip(receivers.into_iter().zip(senders))
+            .map(|(source, (rx, tx))| scope.spawn(move || stage(source, options, input, rx, tx)))
+            .collect();
+        stages
+            .into_iter()
+            .map(|stage| stage.join().expect("a program in the pipe panicked"))
+            .collect()
+    })
+}
+
+fn stage(
+    source: &str,
+    options: &RunOptions,
+    input: Option<&[u8]>,
+    rx: Option<Receiver<Vec<u8>>>,
+    tx: Option<SyncSender<Vec<u8>>>,
+) -> Stage {
+    let mut interpreter = Interpreter::new();
+    options.configure(&mut interpreter);
+    interpreter
+        .load_source(source)
+        .expect("programs are checked before the pipe starts");
+    let from: Box<dyn IoHandler> = match (rx, input) {
+        (Some(rx), _) => Box::new(PipeIn::new(rx)),
+        (None, Some(input)) => Box::new(Stdio::with_input(input)),
+        (None, None) => Box::new(Stdio::new()),
+    };
+    let hung_up = Rc::new(Cell::new(false));
+    let to: Box<dyn IoHandler> = match tx {
+        Some(tx) => Box::new(PipeOut::new(tx, Rc::clone(&hung_up))),
+        None => Box::new(Stdio::with_input(&[])),
+    };
+    interpreter.set_io(Box::new(Split::new(from, to)));
+    interpreter.begin_run();
+    // Nobody's going to read any more, so there's no point going on
+    let result = interpreter.run_while(|_| !hung_up.get());
+    // Dropping the interpreter hangs up on the next program, so it sees
+    // the end of its input
+    drop(interpreter);
+    Stage {
+        result: if hung_up.get() { Ok(()) } else { result },
+        hung_up: hung_up.get(),
+    }
+}
+
+// The reading end, with nothing left once the program before is done
+struct PipeIn {
+    rx: Receiver<Vec<u8>>,
+    buffer: VecDeque<u8>,
+}
+
+impl PipeIn {
+    fn new(rx: Receiver<Vec<u8>>) -> PipeIn {
+        PipeIn {
+            rx,
+            buffer: VecDeque::new(),
+        }
+    }
+}
+
+impl IoHandler for PipeIn {
+    fn read(&mut self) -> Option<u8> {
+        while self.buffer.is_empty() {
+            self.buffer.extend(self.rx.recv().ok()?);
+        }
+        self.buffer.pop_front()
+    }
+
+    fn write(&mut self, _byte: u8) {}
+}
+
+// The writing end. Once the next program stops reading, so does this one
+struct PipeOut {
+    tx: SyncSender<Vec<u8>>,
+    buffer: Vec<u8>,
+    hung_up: Rc<Cell<bool>>,
+    error: Option<io::Error>,
+}
+
+impl PipeOut {
+    fn new(tx: SyncSender<Vec<u8>>, hung_up: Rc<Cell<bool>>) -> PipeOut {
+        PipeOut {
+            tx,
+            buffer: Vec::new(),
+            hung_up,
+            error: None,
+        }
+    }
+
+    fn send(&mut self) {
+        if self.buffer.is_empty() || self.error.is_some() {
+            return;
+        }
+        if self.tx.send(mem::take(&mut self.buffer)).is_err() {
+            self.hung_up.set(true);
+            self.error = Some(io::Error::new(io::ErrorKind::BrokenPipe, "the next program stopped reading"));
+        }
+    }
+}
+
+impl IoHandler for PipeOut {
+    fn read(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.buffer.push(byte);
+        if self.buffer.len() >= CHUNK {
+            self.send();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.send();
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
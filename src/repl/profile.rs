@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// What one bracket pair cost during a profiled run
+// open and close are token indices of its `[` and `]`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoopProfile {
+    pub open: usize,
+    pub close: usize,
+    pub iterations: u64,
+    pub time: Duration,
+}
+
+// Attributes wall time and iterations to each loop
+// Time is inclusive, so an outer loop includes everything nested in it
+#[derive(Default, Debug)]
+pub struct Profiler {
+    loops: HashMap<usize, LoopProfile>,
+    running: Vec<(usize, Instant)>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    // Called every time a `[` tests its cell, including the re-test after `]`
+    pub fn test(&mut self, open: usize, close: usize, entering: bool) {
+        let inside = self.running.last().map(|&(i, _)| i) == Some(open);
+        if entering {
+            if !inside {
+                self.running.push((open, Instant::now()));
+            }
+            self.entry(open, close).iterations += 1;
+        } else if inside {
+            let (_, started) = self.running.pop().expect("loop was just checked");
+            self.entry(open, close).time += started.elapsed();
+        }
+    }
+
+    fn entry(&mut self, open: usize, close: usize) -> &mut LoopProfile {
+        self.loops.entry(open).or_insert(LoopProfile {
+            open,
+            close,
+            iterations: 0,
+            time: Duration::new(0, 0),
+        })
+    }
+
+    // Slowest loop first
+    pub fn report(&self) -> Vec<LoopProfile> {
+        let mut loops: Vec<LoopProfile> = self.loops.values().cloned().collect();
+        loops.sort_by(|a, b| b.time.cmp(&a.time).then(a.open.cmp(&b.open)));
+        loops
+    }
+
+    pub fn clear(&mut self) {
+        self.loops.clear();
+        self.running.clear();
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use super::format::thousands;
+
+// Don't bother for runs that finish before a person would wonder
+const QUIET_FOR: Duration = Duration::from_secs(1);
+const REDRAW_EVERY: Duration = Duration::from_millis(250);
+
+// A single status line on stderr showing a long run is still alive
+#[derive(Debug)]
+pub struct Progress {
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn start() -> Progress {
+        Progress {
+            started: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    pub fn tick(&mut self, steps: u64) {
+        let elapsed = self.started.elapsed();
+        if elapsed < QUIET_FOR {
+            return;
+        }
+        if let Some(drawn) = self.drawn {
+            if drawn.elapsed() < REDRAW_EVERY {
+                return;
+            }
+        }
+
+        let secs = elapsed.as_secs_f64();
+        eprint!(
+            "\r\x1b[K⏳  {} steps  {}/s  {:.1}s",
+            thousands(steps as usize),
+            thousands((steps as f64 / secs) as usize),
+            secs
+        );
+        let _ = io::stderr().flush();
+        self.drawn = Some(Instant::now());
+    }
+
+    // Wipe the status line so program output doesn't land after it
+    pub fn finish(self) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// The last few instructions a run executed, for seeing how it got where
+// it stopped when a limit or an error cuts it short. Unlike trace::Trace
+// it never grows, each step just writes over the oldest one
+use super::error::position;
+use super::json::Json;
+use super::Interpreter;
+
+#[derive(Clone, Debug)]
+pub struct Recent {
+    // Token indexes, slots from next on are the oldest once it's full
+    cursors: Vec<usize>,
+    next: usize,
+    full: bool,
+}
+
+impl Recent {
+    // Room for the last capacity instructions, at least one
+    pub fn new(capacity: usize) -> Recent {
+        Recent {
+            cursors: vec![0; capacity.max(1)],
+            next: 0,
+            full: false,
+        }
+    }
+
+    pub fn push(&mut self, cursor: usize) {
+        self.cursors[self.next] = cursor;
+        self.next += 1;
+        if self.next == self.cursors.len() {
+            self.next = 0;
+            self.full = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.full = false;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cursors.len()
+    }
+
+    pub fn len(&self) -> usize {
+        if self.full {
+            self.cursors.len()
+        } else {
+            self.next
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Token indexes, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let oldest = if self.full { &self.cursors[self.next..] } else { &[][..] };
+        oldest.iter().chain(&self.cursors[..self.next]).cloned()
+    }
+}
+
+impl Interpreter {
+    // Keep the last few instructions of each run, see Recent
+    pub fn set_recent(&mut self, recent: Option<Recent>) {
+        self.recent = recent;
+    }
+
+    pub fn recent(&self) -> Option<&Recent> {
+        self.recent.as_ref()
+    }
+
+    // (offset, line, column, instruction) of each kept instruction, oldest
+    // first, the instruction as it was written in source
+    fn recent_steps(&self) -> Vec<(usize, usize, usize, char)> {
+        let recent = match self.recent {
+            Some(ref recent) => recent,
+            None => return Vec::new(),
+        };
+        recent
+            .iter()
+            .filter_map(|i| self.spans.get(i))
+            .filter_map(|&offset| {
+                let c = self.source.get(offset..)?.chars().next()?;
+                let (line, column) = position(&self.source, offset);
+                Some((offset, line, column, c))
+            })
+            .collect()
+    }
+
+    // A line for each instruction that led up to where the run is, for
+    // under an error. None when nothing's being kept
+    pub fn render_recent(&self) -> Option<String> {
+        let steps = self.recent_steps();
+        if steps.is_empty() {
+            return None;
+        }
+        let mut out = format!("Last {} instructions, oldest first:", steps.len());
+        for (offset, line, column, c) in steps {
+            out.push_str(&format!("\n  {}:{} offset {}  {}", line, column, offset, c));
+        }
+        Some(out)
+    }
+
+    // The same as an array of {"offset", "line", "column", "instruction"}
+    pub fn recent_json(&self) -> Option<Json> {
+        let steps = self.recent_steps();
+        if steps.is_empty() {
+            return None;
+        }
+        let steps = steps.into_iter().map(|(offset, line, column, c)| {
+            Json::object(vec![
+                ("offset", Json::from(offset)),
+                ("line", Json::from(line)),
+                ("column", Json::from(column)),
+                ("instruction", Json::from(c.to_string())),
+            ])
+        });
+        Some(Json::Array(steps.collect()))
+    }
+}
@@ -0,0 +1,128 @@
+// Shrink a program that shows a problem down to a small one that still
+// does. It's delta debugging over the program's commands: cut chunks of
+// half the program, then quarters and so on down to single commands,
+// keeping every cut the problem survives, then try unwrapping each loop to
+// just its body, and go round again until nothing more comes off. Cuts
+// that would leave a bracket unmatched are never tried, so every candidate
+// parses. Comments are the first thing to go, only the eight commands of
+// plain brainfuck are kept
+pub struct Reduction {
+    pub program: String,
+    // How many times fails was asked
+    pub tests: usize,
+}
+
+// fails says whether a candidate still has the problem. If the program
+// without its comments doesn't, it comes back just as it was
+pub fn reduce<F>(source: &str, fails: F) -> Reduction
+where
+    F: FnMut(&str) -> bool,
+{
+    let mut reducer = Reducer { fails, tests: 0 };
+    let mut tokens: Vec<char> = source.chars().filter(|c| "+-<>[].,".contains(*c)).collect();
+    if !reducer.test(&tokens) {
+        return Reduction {
+            program: source.to_string(),
+            tests: reducer.tests,
+        };
+    }
+    loop {
+        reducer.cut_chunks(&mut tokens);
+        if !reducer.unwrap_loops(&mut tokens) {
+            break;
+        }
+    }
+    Reduction {
+        program: tokens.into_iter().collect(),
+        tests: reducer.tests,
+    }
+}
+
+struct Reducer<F> {
+    fails: F,
+    tests: usize,
+}
+
+impl<F: FnMut(&str) -> bool> Reducer<F> {
+    fn test(&mut self, tokens: &[char]) -> bool {
+        self.tests += 1;
+        (self.fails)(&tokens.iter().collect::<String>())
+    }
+
+    fn cut_chunks(&mut self, tokens: &mut Vec<char>) {
+        let mut chunk = (tokens.len() / 2).max(1);
+        loop {
+            let mut cut = false;
+            let mut start = 0;
+            while start < tokens.len() {
+                let end = (start + chunk).min(tokens.len());
+                let candidate: Vec<char> = tokens[..start].iter().chain(&tokens[end..]).cloned().collect();
+                // What moves up into the gap gets a go on the next try
+                if balanced(&candidate) && self.test(&candidate) {
+                    *tokens = candidate;
+                    cut = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            if !cut {
+                if chunk == 1 {
+                    return;
+                }
+                chunk /= 2;
+            }
+        }
+    }
+
+    // A loop whose brackets can go while its body stays. True if any did
+    fn unwrap_loops(&mut self, tokens: &mut Vec<char>) -> bool {
+        let mut unwrapped = false;
+        let mut open = 0;
+        while open < tokens.len() {
+            if tokens[open] == '[' {
+                let close = partner(tokens, open);
+                let mut candidate = tokens.clone();
+                candidate.remove(close);
+                candidate.remove(open);
+                if self.test(&candidate) {
+                    *tokens = candidate;
+                    unwrapped = true;
+                    continue;
+                }
+            }
+            open += 1;
+        }
+        unwrapped
+    }
+}
+
+fn balanced(tokens: &[char]) -> bool {
+    let mut depth: usize = 0;
+    for &c in tokens {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return false,
+            ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    depth == 0
+}
+
+// The `]` for the `[` at open, tokens being balanced
+fn partner(tokens: &[char], open: usize) -> usize {
+    let mut depth = 0;
+    for (i, &c) in tokens.iter().enumerate().skip(open) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => (),
+        }
+    }
+    unreachable!("tokens are always balanced")
+}
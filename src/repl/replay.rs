@@ -0,0 +1,202 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::dialect::Registry;
+use super::error::Failure;
+use super::{io as handler, Interpreter};
+
+// Bump whenever the format changes. Files with a newer version are
+// refused rather than guessed at, older ones keep loading
+pub const VERSION: u32 = 1;
+
+// Everything needed to run a program again exactly as it ran once, and
+// what it printed that time. Plain text, bytes as hex:
+//
+//     brainf-replay 1
+//     hash 9f3c2e0a5d7b6e41
+//     dialect brainfuck
+//     max-steps 100000
+//     seed 0
+//     input 6869
+//     output 4869
+//     program 19
+//     ++++++++[>++++<-]>.
+//
+// program is followed by that many bytes of source, and has to come last
+// max-steps is left out when there's no limit. seed is kept for anything
+// random a run might do one day, nothing uses it yet
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub source: String,
+    pub dialect: String,
+    pub max_steps: Option<u64>,
+    pub seed: u64,
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+}
+
+impl Replay {
+    // Run the program once and remember how it went
+    pub fn record(
+        source: &str,
+        dialect: &str,
+        max_steps: Option<u64>,
+        input: &[u8],
+    ) -> Result<Replay, Failure> {
+        let mut replay = Replay {
+            source: source.to_string(),
+            dialect: dialect.to_string(),
+            max_steps,
+            seed: 0,
+            input: input.to_vec(),
+            output: Vec::new(),
+        };
+        replay.output = replay.run()?;
+        Ok(replay)
+    }
+
+    // Run it again, handing back what it printed this time
+    pub fn run(&self) -> Result<Vec<u8>, Failure> {
+        let dialect = Registry::new()
+            .get(&self.dialect)
+            .ok_or_else(|| Failure::Load(format!("No dialect called '{}'", self.dialect)))?;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_dialect(Some(dialect));
+        interpreter.load_source(&self.source)?;
+        let buffer = handler::Buffer::new(&self.input);
+        let output = buffer.output();
+        interpreter.set_io(Box::new(buffer));
+        interpreter.set_max_steps(self.max_steps);
+        interpreter.interpret()?;
+
+        let bytes = output.borrow().clone();
+        Ok(bytes)
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "brainf-replay {}", VERSION)?;
+        writeln!(w, "hash {:016x}", hash(self.source.as_bytes()))?;
+        writeln!(w, "dialect {}", self.dialect)?;
+        if let Some(max) = self.max_steps {
+            writeln!(w, "max-steps {}", max)?;
+        }
+        writeln!(w, "seed {}", self.seed)?;
+        writeln!(w, "input {}", to_hex(&self.input))?;
+        writeln!(w, "output {}", to_hex(&self.output))?;
+        writeln!(w, "program {}", self.source.len())?;
+        w.write_all(self.source.as_bytes())?;
+        writeln!(w)?;
+        w.flush()
+    }
+
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Replay> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+
+        let (header, mut rest) = split_line(&text);
+        let version = match header.strip_prefix("brainf-replay ") {
+            Some(version) => version
+                .parse::<u32>()
+                .map_err(|_| invalid(format!("bad replay version '{}'", version)))?,
+            None => return Err(invalid(format!("not a replay file, it starts '{}'", header))),
+        };
+        if version > VERSION {
+            return Err(invalid(format!(
+                "replay format version {} is newer than this brainf reads ({})",
+                version, VERSION
+            )));
+        }
+
+        let mut replay = Replay::default();
+        let mut expected_hash = None;
+        loop {
+            let (line, after) = split_line(rest);
+            rest = after;
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => (line, ""),
+            };
+            match key {
+                "" if rest.is_empty() => return Err(invalid("replay has no program".to_string())),
+                "" => (),
+                "hash" => expected_hash = Some(parse_hex_u64(value)?),
+                "dialect" => replay.dialect = value.to_string(),
+                "max-steps" => replay.max_steps = Some(parse(value)?),
+                "seed" => replay.seed = parse(value)?,
+                "input" => replay.input = from_hex(value)?,
+                "output" => replay.output = from_hex(value)?,
+                "program" => {
+                    let len: usize = parse(value)?;
+                    replay.source = rest
+                        .get(..len)
+                        .ok_or_else(|| invalid("replay program is cut short".to_string()))?
+                        .to_string();
+                    break;
+                }
+                _ => return Err(invalid(format!("unknown replay field '{}'", key))),
+            }
+        }
+
+        if let Some(expected) = expected_hash {
+            if expected != hash(replay.source.as_bytes()) {
+                return Err(invalid("program doesn't match its hash".to_string()));
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        Replay::read_from(fs::File::open(path)?)
+    }
+}
+
+// 64 bit FNV-1a, enough to notice a program changed
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn split_line(text: &str) -> (&str, &str) {
+    match text.find('\n') {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => (text, ""),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> io::Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid(format!("odd length hex '{}'", hex)));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| invalid(format!("bad hex '{}'", hex)))
+        })
+        .collect()
+}
+
+fn parse_hex_u64(hex: &str) -> io::Result<u64> {
+    u64::from_str_radix(hex, 16).map_err(|_| invalid(format!("bad hash '{}'", hex)))
+}
+
+fn parse<T: ::std::str::FromStr>(word: &str) -> io::Result<T> {
+    word.parse()
+        .map_err(|_| invalid(format!("'{}' isn't a number", word)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,120 @@
+// Session scripts: everything typed into the REPL, in order, so a session
+// can be replayed exactly. Lines typed at 👉 or 💦 start with `> ` and
+// answers to a 🍴 byte prompt start with `, `
+//
+//     brainf-script 1
+//     > :feed hi
+//     > ,.,.
+//     > +[,.]
+//     , x
+//     > ?
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use super::{print_error, Prompt};
+
+const HEADER: &str = "brainf-script 1";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Entry {
+    Line(String),
+    Byte(String),
+}
+
+impl Entry {
+    fn answers(&self, prompt: Prompt) -> bool {
+        match (self, prompt) {
+            (&Entry::Byte(_), Prompt::Byte) => true,
+            (&Entry::Line(_), Prompt::Byte) | (&Entry::Byte(_), _) => false,
+            (&Entry::Line(_), _) => true,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match *self {
+            Entry::Line(ref text) | Entry::Byte(ref text) => text,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, line)) if line == HEADER => (),
+        Some((_, line)) => return Err(format!("unknown script header '{}'", line)),
+        None => return Err("empty script".to_string()),
+    }
+
+    let mut entries = Vec::new();
+    for (n, line) in lines {
+        if let Some(text) = line.strip_prefix("> ").or_else(|| line.strip_prefix('>')) {
+            entries.push(Entry::Line(text.to_string()));
+        } else if let Some(text) = line.strip_prefix(", ").or_else(|| line.strip_prefix(',')) {
+            entries.push(Entry::Byte(text.to_string()));
+        } else if !line.is_empty() {
+            return Err(format!("line {}: bad script line '{}'", n + 1, line));
+        }
+    }
+    Ok(entries)
+}
+
+thread_local! {
+    static LOG: RefCell<Option<BufWriter<File>>> = const { RefCell::new(None) };
+    static REPLAY: RefCell<VecDeque<Entry>> = const { RefCell::new(VecDeque::new()) };
+}
+
+// Write every line read from now on to a new script at path
+pub fn record_to<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "{}", HEADER)?;
+    file.flush()?;
+    LOG.with(|log| *log.borrow_mut() = Some(file));
+    Ok(())
+}
+
+// Answer prompts from a script until it runs out, then go back to stdin
+pub fn replay_from<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let mut text = String::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        text.push_str(&line?);
+        text.push('\n');
+    }
+    let entries = parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    REPLAY.with(|replay| replay.borrow_mut().extend(entries));
+    Ok(())
+}
+
+// The next scripted answer for this prompt, if a replay is going
+// A script that asks for the wrong kind of answer has stopped matching
+// the session, so the rest of it is dropped
+pub(super) fn replayed(prompt: Prompt) -> Option<String> {
+    REPLAY.with(|replay| {
+        let mut replay = replay.borrow_mut();
+        let entry = replay.pop_front()?;
+        if !entry.answers(prompt) {
+            print_error(format!(
+                "Script has '{}' where the session wants something else, replay stopped",
+                entry.text()
+            ));
+            replay.clear();
+            return None;
+        }
+        Some(entry.text().to_string())
+    })
+}
+
+pub(super) fn record(prompt: Prompt, text: &str) {
+    LOG.with(|log| {
+        if let Some(ref mut file) = *log.borrow_mut() {
+            let marker = match prompt {
+                Prompt::Byte => ',',
+                _ => '>',
+            };
+            // Losing the log isn't worth interrupting the session for
+            let _ = writeln!(file, "{} {}", marker, text).and_then(|_| file.flush());
+        }
+    });
+}
@@ -0,0 +1,106 @@
+// The REPL over TCP, one machine per connection, so `nc` or telnet to a
+// demo server gets everyone their own tape
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::error::{coded, CODE_BUSY, CODE_LISTEN};
+use super::shell::Repl;
+
+// What each connection is allowed
+#[derive(Copy, Clone, Debug)]
+pub struct ServeOptions {
+    // Per evaluation, so nobody's `+[]` ties up a thread for good
+    pub max_steps: Option<u64>,
+    pub watchdog: Option<u64>,
+    // Cells of memory a tape keeps between evaluations
+    pub capacity_cap: Option<usize>,
+    // Hang up on connections quiet for this long
+    pub idle: Option<Duration>,
+    pub max_clients: usize,
+}
+
+impl Default for ServeOptions {
+    fn default() -> ServeOptions {
+        ServeOptions {
+            max_steps: Some(10_000_000),
+            watchdog: Some(1_000_000),
+            capacity_cap: Some(1 << 20),
+            idle: Some(Duration::from_secs(600)),
+            max_clients: 16,
+        }
+    }
+}
+
+// Take connections until the listener fails, each on its own thread
+// Comings and goings are logged to stderr
+pub fn serve(listener: TcpListener, options: ServeOptions) -> io::Result<()> {
+    accept(listener, options, session, |stream| {
+        let _ = writeln!(stream, "Too many people here right now, try again later");
+    })
+}
+
+// The loop behind serve and http::serve_http: handle runs on a thread of
+// its own for each connection, while busy gets the ones past max_clients
+pub(super) fn accept(
+    listener: TcpListener,
+    options: ServeOptions,
+    handle: fn(TcpStream, &ServeOptions) -> io::Result<()>,
+    busy: fn(&mut TcpStream),
+) -> io::Result<()> {
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("{}", coded(CODE_LISTEN, format!("Couldn't accept a connection: {}", e)));
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "someone".to_string(), |addr| addr.to_string());
+        if clients.load(Ordering::SeqCst) >= options.max_clients {
+            let message = format!("{} turned away, {} already connected", peer, options.max_clients);
+            eprintln!("{}", coded(CODE_BUSY, message));
+            busy(&mut stream);
+            continue;
+        }
+
+        clients.fetch_add(1, Ordering::SeqCst);
+        eprintln!("{} connected", peer);
+        let clients = Arc::clone(&clients);
+        thread::spawn(move || {
+            match handle(stream, &options) {
+                Ok(()) => eprintln!("{} left", peer),
+                Err(e) => eprintln!("{} dropped: {}", peer, e),
+            }
+            clients.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+// One REPL on one connection, built on this thread since a Repl can't
+// move between them
+fn session(stream: TcpStream, options: &ServeOptions) -> io::Result<()> {
+    stream.set_read_timeout(options.idle)?;
+    let input = BufReader::new(stream.try_clone()?);
+    let mut repl = Repl::over(Box::new(input), Box::new(BufWriter::new(stream.try_clone()?)));
+    repl.set_sandboxed(true);
+    repl.interpreter.set_max_steps(options.max_steps);
+    repl.interpreter.set_watchdog(options.watchdog);
+    repl.interpreter.brain.set_capacity_cap(options.capacity_cap);
+
+    match repl.run() {
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            let mut stream = stream;
+            let _ = writeln!(stream, "\nQuiet for too long, bye");
+            Ok(())
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,315 @@
+// The read-eval-print loop itself, on whatever input and output it's given,
+// so it can run on stdin and stdout, over a socket or against a test's
+// strings. Failures to read or write come back as errors rather than panics
+use std::cell::RefCell;
+use std::io::{self, stdin, stdout, BufRead, Write};
+use std::rc::Rc;
+
+use super::editor::Editor;
+use super::io::Terminal;
+use super::{symbol_from_prompt, command, error, script, Interpreter, Parser, Prompt};
+
+// Both ends of a REPL: where typed lines come from and where prompts and
+// results go. Terminal shares it, so `,` asks the same place for its bytes
+pub struct Console {
+    // None reads stdin, a line at a time so nothing else loses input to a buffer
+    input: Option<Box<dyn BufRead>>,
+    output: Box<dyn Write>,
+    // Line editing when stdin is a terminal
+    editor: Option<Editor>,
+}
+
+impl Console {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Console {
+        Console {
+            input: Some(input),
+            output,
+            editor: None,
+        }
+    }
+
+    pub fn stdio() -> Console {
+        Console {
+            input: None,
+            output: Box::new(stdout()),
+            editor: Editor::for_terminal(),
+        }
+    }
+
+    // Print the prompt and read a line, None once input runs out
+    // A replayed script answers first, and a log gets a copy of every answer
+    pub(super) fn read_line(&mut self, prompt: Prompt) -> io::Result<Option<String>> {
+        let shown = format!("{}  ", symbol_from_prompt(prompt));
+        let line = match (script::replayed(prompt), &mut self.editor) {
+            (Some(line), _) => {
+                writeln!(self.output, "{}{}", shown, line)?;
+                line
+            }
+            (None, Some(editor)) => {
+                let line = match editor.read_line(&shown, &mut self.output)? {
+                    Some(line) => line,
+                    None => return Ok(None),
+                };
+                // Not answers to `,`, which nobody wants to scroll back through
+                if !matches!(prompt, Prompt::Byte) {
+                    editor.remember(&line);
+                }
+                line
+            }
+            (None, None) => {
+                write!(self.output, "{}", shown)?;
+                self.output.flush()?;
+                let mut line = String::new();
+                let read = match self.input {
+                    Some(ref mut input) => input.read_line(&mut line)?,
+                    None => stdin().read_line(&mut line)?,
+                };
+                if read == 0 {
+                    // Leave whatever comes next off the prompt's line
+                    writeln!(self.output)?;
+                    return Ok(None);
+                }
+                line.trim().to_string()
+            }
+        };
+        script::record(prompt, &line);
+        Ok(Some(line))
+    }
+
+    // Flushed straight away, there's usually someone waiting on it
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.write_all(text.as_bytes())?;
+        self.output.flush()
+    }
+
+    // text and a newline
+    pub fn print(&mut self, text: &str) -> io::Result<()> {
+        self.write(text)?;
+        self.write("\n")
+    }
+}
+
+// What one turn of the loop did
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Turn {
+    // A `:` line ran
+    Command,
+    // Code was loaded and left paused for step mode
+    Loaded,
+    // Code ran, to the end or until something stopped it
+    Evaluated,
+    // `?` or the end of input
+    Quit,
+}
+
+pub struct Repl {
+    pub interpreter: Interpreter,
+    parser: Parser,
+    console: Rc<RefCell<Console>>,
+    sandboxed: bool,
+}
+
+impl Repl {
+    // interpreter should already be set up the way it's wanted, io and all,
+    // usually with a Terminal on the same console so `,` and `.` use it too
+    pub fn new(interpreter: Interpreter, console: Rc<RefCell<Console>>) -> Repl {
+        let parser = Parser::after(&interpreter);
+        Repl {
+            interpreter,
+            parser,
+            console,
+            sandboxed: false,
+        }
+    }
+
+    // A fresh REPL reading lines from input and writing everything,
+    // program output included, to output
+    //
+    //     let stream = TcpStream::connect("localhost:7777")?;
+    //     let input = BufReader::new(stream.try_clone()?);
+    //     Repl::over(Box::new(input), Box::new(stream)).run()?;
+    pub fn over(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Repl {
+        let console = Rc::new(RefCell::new(Console::new(input, output)));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_io(Box::new(Terminal::with_console(Rc::clone(&console))));
+        Repl::new(interpreter, console)
+    }
+
+    pub fn console(&self) -> Rc<RefCell<Console>> {
+        Rc::clone(&self.console)
+    }
+
+    // Turn off commands that write to the machine the REPL runs on, for
+    // when whoever is typing is somewhere else
+    pub fn set_sandboxed(&mut self, on: bool) {
+        self.sandboxed = on;
+    }
+
+    // Greet, then take turns until `?` or the end of input
+    pub fn run(&mut self) -> io::Result<()> {
+        self.greet()?;
+        while self.turn()? != Turn::Quit {}
+        Ok(())
+    }
+
+    pub fn greet(&mut self) -> io::Result<()> {
+        self.print("Starting BrainF REPL (type \":quit\" to quit, \":help\" for commands)")?;
+        if self.interpreter.step_mode() {
+            let position = self.interpreter.render_position();
+            self.print(&position)?;
+        }
+        Ok(())
+    }
+
+    // Read a line, and more while a `[` is still open, then evaluate and
+    // print the result
+    pub fn turn(&mut self) -> io::Result<Turn> {
+        // Read
+        let line = match self.read(Prompt::Input)? {
+            Some(line) => line,
+            None => return Ok(Turn::Quit),
+        };
+
+        if command::is_quit(&line) {
+            return Ok(Turn::Quit);
+        }
+        // `:` lines are REPL commands rather than code
+        if self.sandboxed && command::touches_files(&line) {
+            self.print(&format!("{}  That command is turned off here", symbol_from_prompt(Prompt::Error)))?;
+            return Ok(Turn::Command);
+        }
+        if command::is_command(&line) {
+            let said = command::run(&line, &mut self.interpreter);
+            self.console.borrow_mut().write(&said)?;
+            // It may have loaded code of its own, which what's typed next
+            // has to go after
+            self.parser = Parser::after(&self.interpreter);
+            return Ok(Turn::Command);
+        }
+
+        self.parser.set_dialect(self.interpreter.dialect().cloned());
+        self.parser.set_aliases(self.interpreter.aliases().cloned());
+        self.parser.tokenize(&line);
+        self.print_messages()?;
+
+        // If `[` is unclosed continue accepting input
+        while !self.parser.match_stack.is_empty() {
+            match self.read(Prompt::Continue)? {
+                Some(ref line) if command::is_quit(line) => return Ok(Turn::Quit),
+                Some(line) => self.parser.tokenize(&line),
+                None => return Ok(Turn::Quit),
+            }
+            self.print_messages()?;
+        }
+
+        // Evaluate
+        self.interpreter.load(&mut self.parser);
+        self.parser.reset();
+        if self.interpreter.step_mode() {
+            self.interpreter.begin_run();
+            let position = self.interpreter.render_position();
+            self.print(&position)?;
+            return Ok(Turn::Loaded);
+        }
+        if let Err(e) = self.interpreter.interpret() {
+            self.print(&format!("{}  {}", symbol_from_prompt(Prompt::Error), error::coded(e.code(), &e)))?;
+            if let Some(recent) = self.interpreter.render_recent() {
+                self.print(&recent)?;
+            }
+        }
+        // A breakpoint left it paused partway through
+        if !self.interpreter.is_finished() {
+            let position = self.interpreter.render_position();
+            self.print(&position)?;
+        }
+
+        // Print
+        let state = self.interpreter.render_state();
+        self.print(&state)?;
+        if let Some(profile) = self.interpreter.render_profile() {
+            self.print(&profile)?;
+        }
+        Ok(Turn::Evaluated)
+    }
+
+    fn read(&mut self, prompt: Prompt) -> io::Result<Option<String>> {
+        self.console.borrow_mut().read_line(prompt)
+    }
+
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        self.console.borrow_mut().print(text)
+    }
+
+    fn print_messages(&mut self) -> io::Result<()> {
+        for message in self.parser.take_messages() {
+            self.print(&message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Write};
+    use std::rc::Rc;
+
+    use super::Repl;
+
+    // Everything the REPL wrote, still there once it's done with it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // The lines typed, then the REPL afterwards and what it printed
+    fn session(lines: &str) -> (Repl, String) {
+        let output = Shared::default();
+        let input = Cursor::new(lines.as_bytes().to_vec());
+        let mut repl = Repl::over(Box::new(input), Box::new(output.clone()));
+        repl.run().unwrap();
+        let printed = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+        (repl, printed)
+    }
+
+    #[test]
+    fn prints_what_a_program_writes() {
+        let (repl, printed) = session("++++++++[>++++++++<-]>+.\n");
+        assert!(printed.contains('A'));
+        assert_eq!(repl.interpreter.brain.current(), 65);
+    }
+
+    #[test]
+    fn reset_clears_tape_and_code() {
+        let (repl, _) = session("+++>++\n:reset\n");
+        assert_eq!(repl.interpreter.brain.ptr, 0);
+        assert_eq!(repl.interpreter.brain.current(), 0);
+        assert!(repl.interpreter.tokens().is_empty());
+    }
+
+    #[test]
+    fn restores_a_saved_state() {
+        let (repl, _) = session("+++>+\n:save-state before\n[-]<[-]\n:restore-state before\n");
+        assert_eq!(repl.interpreter.brain.ptr, 1);
+        assert_eq!(repl.interpreter.brain.current(), 1);
+    }
+
+    // A restored cell under a `[` that is the first token, which used to
+    // jump back to cursor 0 - 1
+    #[test]
+    fn loop_at_token_zero_after_a_restore() {
+        let (repl, printed) = session("+++\n:save-state three\n:reset\n:restore-state three\n[-]\n");
+        assert_eq!(repl.interpreter.brain.current(), 0);
+        assert_eq!(repl.interpreter.steps(), 10);
+        assert!(!printed.contains("error["));
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{Brain, Interpreter};
+
+const HEADER: &str = "brainf-snapshot 1";
+
+// Longest tape a snapshot file can ask for, a GiB of cells, so a damaged
+// or hostile file can't have restore allocate whatever it likes
+pub const MAX_LEN: usize = 1 << 30;
+
+// A copy of the tape and pointer that can outlive the process
+// On disk it is plain text and only stores the nonzero cells:
+//
+//     brainf-snapshot 1
+//     ptr 3
+//     len 10
+//     cell 1 65
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub ptr: usize,
+    pub len: usize,
+    pub cells: Vec<(usize, u8)>,
+}
+
+impl Snapshot {
+    pub fn of(brain: &Brain) -> Snapshot {
+        Snapshot {
+            ptr: brain.ptr,
+            len: brain.tape.len(),
+            cells: (0..brain.tape.len())
+                .map(|i| (i, brain.tape.get(i)))
+                .filter(|&(_, cell)| cell != 0)
+                .collect(),
+        }
+    }
+
+    // Overwrite the brain with this snapshot, keeping its tape backend
+    pub fn restore(&self, brain: &mut Brain) {
+        brain.tape.truncate(0);
+        brain.tape.grow(self.len.max(self.ptr.saturating_add(1)));
+        for &(i, cell) in &self.cells {
+            brain.tape.set(i, cell);
+        }
+        brain.ptr = self.ptr;
+    }
+
+    // Every cell that holds something else in other, by index, for saying
+    // what changed between two points rather than showing both tapes
+    pub fn diff(&self, other: &Snapshot) -> Vec<CellDelta> {
+        let mut cells: BTreeMap<usize, (u8, u8)> = BTreeMap::new();
+        for &(i, cell) in &self.cells {
+            cells.entry(i).or_default().0 = cell;
+        }
+        for &(i, cell) in &other.cells {
+            cells.entry(i).or_default().1 = cell;
+        }
+        cells
+            .into_iter()
+            .filter(|&(_, (old, new))| old != new)
+            .map(|(index, (old, new))| CellDelta { index, old, new })
+            .collect()
+    }
+
+    // diff as a line a change plus one for the pointer if it moved, or
+    // a line saying nothing did
+    pub fn render_diff(&self, other: &Snapshot) -> String {
+        let mut lines: Vec<String> = self
+            .diff(other)
+            .into_iter()
+            .map(|delta| format!("cell {:>6}  {:>3} -> {}", delta.index, delta.old, delta.new))
+            .collect();
+        if self.ptr != other.ptr {
+            lines.push(format!("pointer {} -> {}", self.ptr, other.ptr));
+        }
+        if lines.is_empty() {
+            return "No cells changed".to_string();
+        }
+        lines.join("\n")
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{}", HEADER)?;
+        writeln!(w, "ptr {}", self.ptr)?;
+        writeln!(w, "len {}", self.len)?;
+        for &(i, cell) in &self.cells {
+            writeln!(w, "cell {} {}", i, cell)?;
+        }
+        w.flush()
+    }
+
+    pub fn read_from<R: Read>(r: R) -> io::Result<Snapshot> {
+        let mut lines = BufReader::new(r).lines();
+        match lines.next() {
+            Some(Ok(ref line)) if line == HEADER => (),
+            Some(Ok(line)) => return Err(invalid(format!("unknown snapshot header '{}'", line))),
+            Some(Err(e)) => return Err(e),
+            None => return Err(invalid("empty snapshot".to_string())),
+        }
+
+        let mut snapshot = Snapshot::default();
+        for line in lines {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => (),
+                ["ptr", n] => snapshot.ptr = parse(n)?,
+                ["len", n] => snapshot.len = parse(n)?,
+                ["cell", i, cell] => snapshot.cells.push((parse(i)?, parse(cell)?)),
+                _ => return Err(invalid(format!("bad snapshot line '{}'", line))),
+            }
+        }
+        // Nothing from the file gets trusted, restore would grow the tape to
+        // whatever these say
+        let len = snapshot.len;
+        if len > MAX_LEN {
+            return Err(invalid(format!("snapshot tape of {} cells is over the {} limit", len, MAX_LEN)));
+        }
+        if snapshot.ptr >= len {
+            return Err(invalid(format!("snapshot pointer {} is past its {} cells", snapshot.ptr, len)));
+        }
+        if let Some(&(i, _)) = snapshot.cells.iter().find(|&&(i, _)| i >= len) {
+            return Err(invalid(format!("snapshot cell {} is past its {} cells", i, len)));
+        }
+        Ok(snapshot)
+    }
+
+    // Written to a temporary file first so a crash never leaves half a snapshot
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        self.write_to(BufWriter::new(File::create(&tmp)?))?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot> {
+        Snapshot::read_from(File::open(path)?)
+    }
+}
+
+// One cell that's different in one snapshot from another, see Snapshot::diff
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CellDelta {
+    pub index: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+// Most savepoints one session can hold, each one being a whole tape
+pub const MAX_SAVEPOINTS: usize = 64;
+
+// Named savepoints, for trying more than one thing from the same tape in
+// a session. They only last as long as the interpreter does
+impl Interpreter {
+    // Save the tape and pointer as name, over any savepoint already called that
+    pub fn save_state(&mut self, name: &str) -> Result<(), String> {
+        if !self.savepoints.contains_key(name) && self.savepoints.len() >= MAX_SAVEPOINTS {
+            return Err(format!("There are already {} savepoints", MAX_SAVEPOINTS));
+        }
+        self.savepoints.insert(name.to_string(), Snapshot::of(&self.brain));
+        Ok(())
+    }
+
+    // Put the tape and pointer back the way name has them, handing back what
+    // that changed. The savepoint stays, to go back to again
+    pub fn restore_state(&mut self, name: &str) -> Result<String, String> {
+        let saved = self
+            .savepoints
+            .get(name)
+            .ok_or_else(|| format!("No savepoint called '{}'", name))?;
+        let changes = Snapshot::of(&self.brain).render_diff(saved);
+        saved.restore(&mut self.brain);
+        Ok(changes)
+    }
+
+    pub fn drop_state(&mut self, name: &str) -> bool {
+        self.savepoints.remove(name).is_some()
+    }
+
+    // Names in order with what they saved
+    pub fn savepoints(&self) -> impl Iterator<Item = (&str, &Snapshot)> {
+        self.savepoints.iter().map(|(name, snapshot)| (name.as_str(), snapshot))
+    }
+}
+
+// Periodic snapshots written while a long program runs, so a panic or
+// a kill -9 only loses the last few seconds of tape
+#[derive(Debug)]
+pub struct Recovery {
+    path: PathBuf,
+    every: Duration,
+    last: Instant,
+}
+
+impl Recovery {
+    pub fn new(path: PathBuf, every: Duration) -> Recovery {
+        Recovery {
+            path,
+            every,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Cheap enough to call from the dispatch loop every so often
+    pub fn tick(&mut self, brain: &Brain) {
+        if self.last.elapsed() >= self.every {
+            self.write(brain);
+        }
+    }
+
+    pub fn write(&mut self, brain: &Brain) {
+        // A failed checkpoint shouldn't take the running program down with it
+        let _ = Snapshot::of(brain).save(&self.path);
+        self.last = Instant::now();
+    }
+
+    // Nothing to recover after a clean exit
+    pub fn discard(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Where the REPL keeps its session between runs
+pub fn session_path() -> PathBuf {
+    match env::var_os("HOME") {
+        Some(home) => Path::new(&home).join(".brainf_session"),
+        None => PathBuf::from(".brainf_session"),
+    }
+}
+
+// Where the last recovery snapshot of a crashed run ends up
+pub fn recovery_path() -> PathBuf {
+    session_path().with_file_name(".brainf_recovery")
+}
+
+fn parse<T: ::std::str::FromStr>(word: &str) -> io::Result<T> {
+    word.parse()
+        .map_err(|_| invalid(format!("'{}' isn't a number", word)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use std::io::ErrorKind;
+
+    fn read(body: &str) -> ::std::io::Result<Snapshot> {
+        Snapshot::read_from(format!("brainf-snapshot 1\n{}", body).as_bytes())
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = Snapshot {
+            ptr: 2,
+            len: 5,
+            cells: vec![(1, 65), (4, 255)],
+        };
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        assert_eq!(Snapshot::read_from(&bytes[..]).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn rejects_what_restore_cant_do() {
+        for body in [
+            "ptr 18446744073709551615\nlen 3\n",
+            "ptr 3\nlen 3\n",
+            "ptr 0\nlen 99999999999999\n",
+            "ptr 0\nlen 3\ncell 3 1\n",
+            "ptr 0\n",
+        ] {
+            assert_eq!(read(body).unwrap_err().kind(), ErrorKind::InvalidData, "{}", body);
+        }
+    }
+}
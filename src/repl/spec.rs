@@ -0,0 +1,145 @@
+use super::{io, load_program};
+
+// One test case from a .test file, one case per line:
+//
+//     with input "ab"; expect output "ba"; max-steps 10000
+//
+// Strings take \n, \t, \\, \" and \xNN escapes, # starts a comment
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Case {
+    pub line: usize,
+    pub input: Vec<u8>,
+    pub expected: Option<Vec<u8>>,
+    pub max_steps: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail { expected: Vec<u8>, actual: Vec<u8> },
+    Error(String),
+}
+
+pub fn parse(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut case = Case {
+            line: n + 1,
+            ..Case::default()
+        };
+        for clause in split_clauses(line) {
+            parse_clause(clause.trim(), &mut case).map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+pub fn run(source: &str, case: &Case) -> Outcome {
+    let mut interpreter = match load_program(source) {
+        Ok(interpreter) => interpreter,
+        Err(e) => return Outcome::Error(e.to_string()),
+    };
+    let buffer = io::Buffer::new(&case.input);
+    let output = buffer.output();
+    interpreter.set_io(Box::new(buffer));
+    interpreter.set_max_steps(case.max_steps);
+
+    if let Err(e) = interpreter.interpret() {
+        return Outcome::Error(e.to_string());
+    }
+    let actual = output.borrow().clone();
+    match case.expected {
+        Some(ref expected) if *expected != actual => Outcome::Fail {
+            expected: expected.clone(),
+            actual,
+        },
+        _ => Outcome::Pass,
+    }
+}
+
+fn parse_clause(clause: &str, case: &mut Case) -> Result<(), String> {
+    if let Some(rest) = clause.strip_prefix("with input") {
+        case.input = parse_string(rest.trim())?;
+    } else if let Some(rest) = clause.strip_prefix("expect output") {
+        case.expected = Some(parse_string(rest.trim())?);
+    } else if let Some(rest) = clause.strip_prefix("max-steps") {
+        let rest = rest.trim();
+        case.max_steps = Some(rest.parse().map_err(|_| format!("'{}' isn't a step count", rest))?);
+    } else {
+        return Err(format!("don't know what '{}' means", clause));
+    }
+    Ok(())
+}
+
+// `;` separates clauses unless it's inside a string
+fn split_clauses(line: &str) -> Vec<&str> {
+    let mut clauses = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                clauses.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    clauses.push(&line[start..]);
+    clauses.into_iter().filter(|c| !c.trim().is_empty()).collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+// A "quoted string" with the escapes above
+pub fn parse_string(s: &str) -> Result<Vec<u8>, String> {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return Err(format!("expected a \"quoted string\", got '{}'", s));
+    }
+    let mut bytes = Vec::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16).map_err(|_| format!("bad escape '\\x{}'", hex))?;
+                bytes.push(byte);
+            }
+            Some(other) => return Err(format!("bad escape '\\{}'", other)),
+            None => return Err("string ends in a backslash".to_string()),
+        }
+    }
+    Ok(bytes)
+}
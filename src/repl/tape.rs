@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+// Storage behind the Brain
+// len is one past the highest cell the pointer has reached
+pub trait Tape {
+    fn get(&self, i: usize) -> u8;
+    fn set(&mut self, i: usize, value: u8);
+    fn len(&self) -> usize;
+
+    // Make sure cells up to len - 1 exist
+    fn grow(&mut self, len: usize);
+
+    // Forget every cell from len on
+    fn truncate(&mut self, len: usize);
+
+    // How many cells worth of memory are held right now
+    fn capacity(&self) -> usize;
+
+    // Hand memory back, keeping at least cap cells worth if possible
+    fn release(&mut self, cap: usize);
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn last_nonzero(&self) -> Option<usize> {
+        (0..self.len()).rev().find(|&i| self.get(i) != 0)
+    }
+}
+
+// Plain Vec of cells, the default
+#[derive(Default, Debug)]
+pub struct DenseTape {
+    cells: Vec<u8>,
+}
+
+impl DenseTape {
+    pub fn new() -> DenseTape {
+        DenseTape { cells: vec![0; 1] }
+    }
+}
+
+impl Tape for DenseTape {
+    fn get(&self, i: usize) -> u8 {
+        self.cells.get(i).cloned().unwrap_or(0)
+    }
+
+    fn set(&mut self, i: usize, value: u8) {
+        if i >= self.cells.len() {
+            self.grow(i + 1);
+        }
+        self.cells[i] = value;
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn grow(&mut self, len: usize) {
+        if len > self.cells.len() {
+            self.cells.resize(len, 0);
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.cells.truncate(len);
+    }
+
+    fn capacity(&self) -> usize {
+        self.cells.capacity()
+    }
+
+    fn release(&mut self, cap: usize) {
+        let len = self.cells.len();
+        self.cells.shrink_to(cap.max(len));
+    }
+
+    fn last_nonzero(&self) -> Option<usize> {
+        self.cells.iter().rposition(|&cell| cell != 0)
+    }
+}
+
+const PAGE_SIZE: usize = 4096;
+
+// Cells live in fixed size pages that only exist once something nonzero
+// is written to them, so a pointer can wander off a million cells without
+// a million cells being allocated
+#[derive(Default, Debug)]
+pub struct SparseTape {
+    pages: HashMap<usize, Box<[u8]>>,
+    len: usize,
+}
+
+impl SparseTape {
+    pub fn new() -> SparseTape {
+        SparseTape {
+            pages: HashMap::new(),
+            len: 1,
+        }
+    }
+}
+
+impl Tape for SparseTape {
+    fn get(&self, i: usize) -> u8 {
+        match self.pages.get(&(i / PAGE_SIZE)) {
+            Some(page) => page[i % PAGE_SIZE],
+            None => 0,
+        }
+    }
+
+    fn set(&mut self, i: usize, value: u8) {
+        self.grow(i + 1);
+        if value == 0 && !self.pages.contains_key(&(i / PAGE_SIZE)) {
+            return;
+        }
+        let page = self.pages
+            .entry(i / PAGE_SIZE)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+        page[i % PAGE_SIZE] = value;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn grow(&mut self, len: usize) {
+        if len > self.len {
+            self.len = len;
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.pages.retain(|&page, _| page * PAGE_SIZE < len);
+        if let Some(page) = self.pages.get_mut(&(len / PAGE_SIZE)) {
+            for cell in page[len % PAGE_SIZE..].iter_mut() {
+                *cell = 0;
+            }
+        }
+        self.len = len;
+    }
+
+    fn capacity(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+
+    fn release(&mut self, cap: usize) {
+        // Pages of zeros are indistinguishable from missing pages
+        self.pages.retain(|_, page| page.iter().any(|&cell| cell != 0));
+        self.pages.shrink_to(cap / PAGE_SIZE);
+    }
+
+    fn last_nonzero(&self) -> Option<usize> {
+        self.pages
+            .iter()
+            .filter_map(|(&n, page)| {
+                page.iter()
+                    .rposition(|&cell| cell != 0)
+                    .map(|i| n * PAGE_SIZE + i)
+            })
+            .max()
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub use self::mmap::MmapTape;
+
+#[cfg(feature = "mmap")]
+mod mmap {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::path::Path;
+
+    use memmap2::{MmapMut, MmapOptions};
+
+    use super::Tape;
+
+    // Cells mapped from the OS so untouched pages never become real memory
+    // Backed by a file, the tape doubles as an on-disk snapshot
+    pub struct MmapTape {
+        map: MmapMut,
+        file: Option<File>,
+        len: usize,
+    }
+
+    impl MmapTape {
+        // Reserve size cells of address space, nothing is paged in yet
+        pub fn anonymous(size: usize) -> io::Result<MmapTape> {
+            Ok(MmapTape {
+                map: MmapOptions::new().len(size.max(1)).map_anon()?,
+                file: None,
+                len: 1,
+            })
+        }
+
+        // Map a tape file, creating it (or growing it to size) as needed
+        pub fn open<P: AsRef<Path>>(path: P, size: usize) -> io::Result<MmapTape> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            let size = (file.metadata()?.len() as usize).max(size).max(1);
+            file.set_len(size as u64)?;
+            // SAFETY: the map is only sound while nothing else truncates or
+            // writes the file. A tape file belongs to this tape for as long
+            // as it's open, nothing else in brainf touches it, and two
+            // processes sharing one tape file isn't supported
+            let map = unsafe { MmapOptions::new().len(size).map_mut(&file)? };
+
+            let len = map.iter().rposition(|&cell| cell != 0).map_or(1, |i| i + 1);
+            Ok(MmapTape {
+                map,
+                file: Some(file),
+                len,
+            })
+        }
+
+        // Push dirty pages out to the backing file
+        pub fn flush(&self) -> io::Result<()> {
+            self.map.flush()
+        }
+
+        fn remap(&mut self, size: usize) -> io::Result<()> {
+            match self.file {
+                Some(ref file) => {
+                    self.map.flush()?;
+                    file.set_len(size as u64)?;
+                    // SAFETY: the same file open() mapped, still only ours,
+                    // and the old map is flushed and replaced before use
+                    self.map = unsafe { MmapOptions::new().len(size).map_mut(file)? };
+                }
+                None => {
+                    let mut map = MmapOptions::new().len(size).map_anon()?;
+                    map[..self.len].copy_from_slice(&self.map[..self.len]);
+                    self.map = map;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Tape for MmapTape {
+        fn get(&self, i: usize) -> u8 {
+            if i < self.map.len() {
+                self.map[i]
+            } else {
+                0
+            }
+        }
+
+        fn set(&mut self, i: usize, value: u8) {
+            self.grow(i + 1);
+            self.map[i] = value;
+        }
+
+        fn len(&self) -> usize {
+            self.len
+        }
+
+        fn grow(&mut self, len: usize) {
+            if len > self.map.len() {
+                let size = len.max(self.map.len() * 2);
+                self.remap(size).expect("failed to grow mmap tape");
+            }
+            if len > self.len {
+                self.len = len;
+            }
+        }
+
+        fn truncate(&mut self, len: usize) {
+            if len < self.len {
+                for cell in self.map[len..self.len].iter_mut() {
+                    *cell = 0;
+                }
+                self.len = len;
+            }
+        }
+
+        fn capacity(&self) -> usize {
+            self.map.len()
+        }
+
+        // The OS already pages out what we don't touch
+        fn release(&mut self, _cap: usize) {}
+    }
+
+    impl Drop for MmapTape {
+        fn drop(&mut self) {
+            let _ = self.map.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::random::Rng;
+    use super::{DenseTape, SparseTape, Tape, PAGE_SIZE};
+
+    #[test]
+    fn truncate_forgets_across_pages() {
+        let mut tape = SparseTape::new();
+        for &i in &[10, 20, PAGE_SIZE - 1, PAGE_SIZE, 2 * PAGE_SIZE + 1] {
+            tape.set(i, 7);
+        }
+        assert_eq!(tape.capacity(), 3 * PAGE_SIZE);
+
+        tape.truncate(PAGE_SIZE);
+        assert_eq!(tape.len(), PAGE_SIZE);
+        assert_eq!(tape.capacity(), PAGE_SIZE);
+        assert_eq!(tape.last_nonzero(), Some(PAGE_SIZE - 1));
+
+        // Part way into a page, the rest of it is zeroed
+        tape.truncate(15);
+        assert_eq!(tape.last_nonzero(), Some(10));
+        // Growing back doesn't bring anything back
+        tape.grow(3 * PAGE_SIZE);
+        assert_eq!((tape.get(20), tape.get(PAGE_SIZE), tape.get(2 * PAGE_SIZE + 1)), (0, 0, 0));
+        assert_eq!(tape.last_nonzero(), Some(10));
+    }
+
+    #[test]
+    fn release_drops_pages_of_zeros() {
+        let mut tape = SparseTape::new();
+        tape.set(5, 1);
+        tape.set(3 * PAGE_SIZE + 5, 1);
+        tape.set(3 * PAGE_SIZE + 5, 0);
+        assert_eq!(tape.capacity(), 2 * PAGE_SIZE);
+        assert_eq!(tape.last_nonzero(), Some(5));
+
+        tape.release(0);
+        assert_eq!(tape.capacity(), PAGE_SIZE);
+        assert_eq!(tape.len(), 3 * PAGE_SIZE + 6);
+        assert_eq!((tape.get(5), tape.get(3 * PAGE_SIZE + 5)), (1, 0));
+
+        tape.set(5, 0);
+        tape.release(0);
+        assert_eq!(tape.capacity(), 0);
+        assert_eq!(tape.last_nonzero(), None);
+    }
+
+    // The same writes, truncates and releases, nothing a Brain can see
+    // differs
+    #[test]
+    fn sparse_and_dense_agree() {
+        let (mut dense, mut sparse) = (DenseTape::new(), SparseTape::new());
+        let mut rng = Rng::new(207);
+        let span = 4 * PAGE_SIZE as u64;
+        for n in 0..2000 {
+            match rng.below(10) {
+                0 => {
+                    let len = rng.below(span) as usize + 1;
+                    dense.truncate(len);
+                    sparse.truncate(len);
+                }
+                1 => {
+                    let cap = rng.below(span) as usize;
+                    dense.release(cap);
+                    sparse.release(cap);
+                }
+                2 => {
+                    let len = rng.below(span) as usize;
+                    dense.grow(len);
+                    sparse.grow(len);
+                }
+                _ => {
+                    // Near a page boundary half the time
+                    let i = match rng.below(2) {
+                        0 => rng.below(span) as usize,
+                        _ => (rng.below(4) as usize * PAGE_SIZE + rng.below(4) as usize).saturating_sub(2),
+                    };
+                    let value = if rng.below(3) == 0 { 0 } else { rng.below(256) as u8 };
+                    dense.set(i, value);
+                    sparse.set(i, value);
+                }
+            }
+            assert_eq!(dense.len(), sparse.len(), "after {} steps", n);
+            assert_eq!(dense.last_nonzero(), sparse.last_nonzero(), "after {} steps", n);
+            if n % 100 == 0 {
+                assert!((0..span as usize + 1).all(|i| dense.get(i) == sparse.get(i)), "after {} steps", n);
+            }
+        }
+    }
+}
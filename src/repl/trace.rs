@@ -0,0 +1,158 @@
+// Every instruction a run executed, packed small enough to keep around
+//
+// Entries are delta encoded against the one before as zigzag varints, so
+// a typical step costs about three bytes. They're grouped into chunks of
+// CHUNK steps that each start from scratch, which means finding step n
+// only has to decode one chunk. With the compress feature, full chunks
+// are squeezed with zstd as well
+#[cfg(feature = "compress")]
+use zstd::bulk;
+
+pub const CHUNK: u64 = 4096;
+
+// What one step did: the token it ran, and where the pointer and the cell
+// under it ended up
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TraceEntry {
+    pub cursor: usize,
+    pub ptr: usize,
+    pub cell: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct Trace {
+    chunks: Vec<Vec<u8>>,
+    open: Vec<u8>,
+    last: TraceEntry,
+    len: u64,
+    compress: bool,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    // Compress each chunk once it's full
+    #[cfg(feature = "compress")]
+    pub fn compressed() -> Trace {
+        Trace {
+            compress: true,
+            ..Trace::new()
+        }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        put(&mut self.open, entry.cursor as i64 - self.last.cursor as i64);
+        put(&mut self.open, entry.ptr as i64 - self.last.ptr as i64);
+        self.open.push(entry.cell);
+        self.last = entry;
+        self.len += 1;
+
+        if self.len.is_multiple_of(CHUNK) {
+            let chunk = ::std::mem::take(&mut self.open);
+            self.chunks.push(self.seal(chunk));
+            self.last = TraceEntry::default();
+        }
+    }
+
+    // Steps recorded
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Bytes the trace takes up, compressed or not
+    pub fn size(&self) -> usize {
+        self.chunks.iter().map(Vec::len).sum::<usize>() + self.open.len()
+    }
+
+    // The nth step, counting from 0
+    pub fn get(&self, step: u64) -> Option<TraceEntry> {
+        if step >= self.len {
+            return None;
+        }
+        let n = (step / CHUNK) as usize;
+        let skip = step % CHUNK;
+        match self.chunks.get(n) {
+            Some(chunk) => decode(&self.unseal(chunk), skip),
+            None => decode(&self.open, skip),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Trace {
+            compress: self.compress,
+            ..Trace::new()
+        };
+    }
+
+    #[cfg(feature = "compress")]
+    fn seal(&self, chunk: Vec<u8>) -> Vec<u8> {
+        if self.compress {
+            bulk::compress(&chunk, 3).unwrap_or(chunk)
+        } else {
+            chunk
+        }
+    }
+
+    #[cfg(not(feature = "compress"))]
+    fn seal(&self, chunk: Vec<u8>) -> Vec<u8> {
+        chunk
+    }
+
+    #[cfg(feature = "compress")]
+    fn unseal(&self, chunk: &[u8]) -> Vec<u8> {
+        if self.compress {
+            // A chunk of CHUNK entries can't be bigger than this
+            let most = CHUNK as usize * 21;
+            bulk::decompress(chunk, most).unwrap_or_default()
+        } else {
+            chunk.to_vec()
+        }
+    }
+
+    #[cfg(not(feature = "compress"))]
+    fn unseal(&self, chunk: &[u8]) -> Vec<u8> {
+        chunk.to_vec()
+    }
+}
+
+// Walk a chunk up to entry skip
+fn decode(chunk: &[u8], skip: u64) -> Option<TraceEntry> {
+    let mut entry = TraceEntry::default();
+    let mut bytes = chunk.iter().cloned();
+    for _ in 0..skip + 1 {
+        entry.cursor = (entry.cursor as i64 + get(&mut bytes)?) as usize;
+        entry.ptr = (entry.ptr as i64 + get(&mut bytes)?) as usize;
+        entry.cell = bytes.next()?;
+    }
+    Some(entry)
+}
+
+// Zigzag so small negative deltas stay small, then 7 bits per byte
+fn put(out: &mut Vec<u8>, n: i64) {
+    let mut n = ((n << 1) ^ (n >> 63)) as u64;
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn get<I: Iterator<Item = u8>>(bytes: &mut I) -> Option<i64> {
+    let mut n: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next()?;
+        n |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    Some((n >> 1) as i64 ^ -((n & 1) as i64))
+}
@@ -0,0 +1,99 @@
+use super::{Interpreter, Token};
+
+// A pass over a program's tokens: visit gets each one in order and pushes
+// whatever should take its place, which can be nothing, the token as is or
+// any number of others. Brackets are matched up again afterwards, so the
+// indices inside pushed jumps don't matter
+//
+//     // Print the cell after every loop
+//     interpreter.transform(|_: usize, token: Token, out: &mut Vec<Token>| {
+//         out.push(token);
+//         if let Token::JumpBackward(_) = token {
+//             out.push(Token::Output);
+//         }
+//     })?;
+pub trait Transform {
+    fn visit(&mut self, i: usize, token: Token, out: &mut Vec<Token>);
+}
+
+impl<F: FnMut(usize, Token, &mut Vec<Token>)> Transform for F {
+    fn visit(&mut self, i: usize, token: Token, out: &mut Vec<Token>) {
+        self(i, token, out)
+    }
+}
+
+// Swap every token for another
+pub fn map<F: FnMut(Token) -> Token>(mut f: F) -> impl Transform {
+    move |_: usize, token: Token, out: &mut Vec<Token>| out.push(f(token))
+}
+
+// Keep only the tokens f says yes to
+pub fn filter<F: FnMut(Token) -> bool>(mut f: F) -> impl Transform {
+    move |_: usize, token: Token, out: &mut Vec<Token>| {
+        if f(token) {
+            out.push(token)
+        }
+    }
+}
+
+// Run a pass over tokens on their own, no interpreter needed
+pub fn transform<T: Transform>(tokens: &[Token], pass: &mut T) -> Result<Vec<Token>, String> {
+    let mut out = Vec::with_capacity(tokens.len());
+    for (i, &token) in tokens.iter().enumerate() {
+        pass.visit(i, token, &mut out);
+    }
+    relink(&mut out)?;
+    Ok(out)
+}
+
+// Point every bracket at its partner again
+fn relink(tokens: &mut [Token]) -> Result<(), String> {
+    let mut open = Vec::new();
+    for i in 0..tokens.len() {
+        match tokens[i] {
+            Token::JumpForward(_) => open.push(i),
+            Token::JumpBackward(_) => {
+                let j = open
+                    .pop()
+                    .ok_or_else(|| format!("The pass left an unbalanced ']' at #{}", i))?;
+                tokens[i] = Token::JumpBackward(j);
+                tokens[j] = Token::JumpForward(i);
+            }
+            _ => (),
+        }
+    }
+    match open.last() {
+        Some(j) => Err(format!("The pass left an unclosed '[' at #{}", j)),
+        None => Ok(()),
+    }
+}
+
+impl Interpreter {
+    // Run a pass over everything loaded. New tokens get the span of the
+    // one they were pushed for, and the cursor stays on the first token
+    // that came out of the one it was on. If the pass leaves brackets
+    // unbalanced nothing changes
+    pub fn transform<T: Transform>(&mut self, mut pass: T) -> Result<(), String> {
+        let mut tokens = Vec::with_capacity(self.tokens.len());
+        let mut spans = Vec::with_capacity(self.spans.len());
+        let mut ends = Vec::with_capacity(self.ends.len());
+        let mut cursor = None;
+        for (i, &token) in self.tokens.iter().enumerate() {
+            if i == self.cursor {
+                cursor = Some(tokens.len());
+            }
+            pass.visit(i, token, &mut tokens);
+            spans.resize(tokens.len(), self.spans[i]);
+            ends.resize(tokens.len(), self.ends[i]);
+        }
+        relink(&mut tokens)?;
+
+        self.cursor = cursor.unwrap_or(tokens.len());
+        self.tokens = tokens;
+        self.spans = spans;
+        self.ends = ends;
+        // Counts were by the old token indices
+        self.loop_counts.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+// Watch expressions for stepping through a program: anything a breakpoint
+// condition can say, `ptr`, `cell[3]`, `cell[@] - 'A'`, but kept as a
+// number. After every instruction each one is worked out again, and the
+// stepping commands show the ones that changed instead of the whole tape
+use super::condition::Condition;
+use super::{symbol_from_prompt, Interpreter, Prompt};
+
+// Changes kept from one stretch of stepping, past that they're counted
+pub const MAX_CHANGES: usize = 100;
+
+#[derive(Debug)]
+pub struct Watch {
+    expr: Condition,
+    // What it came to the last time it was worked out
+    value: i64,
+}
+
+impl Watch {
+    pub fn expr(&self) -> &Condition {
+        &self.expr
+    }
+
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+}
+
+// One watch going from old to new on the instruction at token cursor
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WatchChange {
+    pub watch: usize,
+    pub cursor: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl Interpreter {
+    pub fn add_watch(&mut self, expr: Condition) {
+        let value = expr.value(&self.brain, self.output_count);
+        self.watches.push(Watch { expr, value });
+    }
+
+    // False when there's no watch i
+    pub fn remove_watch(&mut self, i: usize) -> bool {
+        if i >= self.watches.len() {
+            return false;
+        }
+        self.watches.remove(i);
+        self.watch_changes.clear();
+        true
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.watches.clear();
+        self.watch_changes.clear();
+        self.watch_missed = 0;
+    }
+
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    // What changed in the last run or stretch of stepping, oldest first
+    pub fn watch_changes(&self) -> &[WatchChange] {
+        &self.watch_changes
+    }
+
+    // Called before a run so only changes from here on are kept, whatever
+    // happened to the tape in between
+    pub(super) fn arm_watches(&mut self) {
+        self.watch_changes.clear();
+        self.watch_missed = 0;
+        let (brain, output) = (&self.brain, self.output_count);
+        for watch in &mut self.watches {
+            watch.value = watch.expr.value(brain, output);
+        }
+    }
+
+    // After each instruction
+    pub(super) fn check_watches(&mut self, cursor: usize) {
+        let (brain, output) = (&self.brain, self.output_count);
+        for (i, watch) in self.watches.iter_mut().enumerate() {
+            let now = watch.expr.value(brain, output);
+            if now == watch.value {
+                continue;
+            }
+            if self.watch_changes.len() < MAX_CHANGES {
+                self.watch_changes.push(WatchChange {
+                    watch: i,
+                    cursor,
+                    old: watch.value,
+                    new: now,
+                });
+            } else {
+                self.watch_missed += 1;
+            }
+            watch.value = now;
+        }
+    }
+
+    // A line per change, e.g. `🐛  cell[3] 0 -> 1 at #4`. None when
+    // nothing watched changed
+    pub fn render_watch_changes(&self) -> Option<String> {
+        if self.watch_changes.is_empty() {
+            return None;
+        }
+        let prompt = symbol_from_prompt(Prompt::Debug);
+        let lines: Vec<String> = self
+            .watch_changes
+            .iter()
+            .map(|change| {
+                format!(
+                    "{}  {} {} -> {} at #{}",
+                    prompt, self.watches[change.watch].expr, change.old, change.new, change.cursor
+                )
+            })
+            .collect();
+        let mut out = lines.join("\n");
+        if self.watch_missed > 0 {
+            out.push_str(&format!("\n{}  ...and {} more changes", prompt, self.watch_missed));
+        }
+        Some(out)
+    }
+}
@@ -0,0 +1,602 @@
+// `GET /ws` on serve-http: a program running live over a WebSocket, for
+// playgrounds that want to show output as it happens and let people type
+// input, pause and step. Messages both ways are JSON text frames with a
+// "type". From the page:
+//
+//     {"type": "run", "program": "...", "input": "", "paused": false, "dialect": "...", "max_steps": 1000, "watchdog": 1000}
+//     {"type": "input", "data": "abc"}    {"type": "eof"}
+//     {"type": "pause"}    {"type": "resume"}    {"type": "step", "count": 1}
+//
+// and back from the server:
+//
+//     {"type": "output", "data": "..."}
+//     {"type": "tape", "ptr": 3, "start": 0, "cells": [...], "cursor": 12, "steps": 400}
+//     {"type": "waiting"}    {"type": "paused"}    {"type": "done", "steps": 400}
+//     {"type": "errors", "errors": [...]}    {"type": "stopped", "message": "..."}
+//     {"type": "error", "message": "..."}
+//
+// A tape snapshot goes out every so often while running and whenever the
+// program stops. `waiting` means a `,` is next and there's no input for
+// it, the program carries on when some arrives or on eof
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::mem;
+use std::net::{Shutdown, TcpStream};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cli::check_dialect;
+use super::dialect::Registry;
+use super::error::RuntimeError;
+use super::http::{limit, lower, MAX_BODY};
+use super::io::IoHandler;
+use super::json::Json;
+use super::serve::ServeOptions;
+use super::{Interpreter, Token};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Instructions between looking for messages from the page
+const SLICE: u64 = 100_000;
+const TAPE_EVERY: Duration = Duration::from_millis(200);
+// Cells either side of the pointer in a tape snapshot
+const TAPE_WINDOW: usize = 16;
+
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+// The value for Sec-WebSocket-Accept
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+// Everything after the handshake, whose request has already been read
+// off reader. Output goes to stream, input comes from reader on a thread
+// of its own so messages get through while a program runs
+pub fn session<R>(stream: TcpStream, reader: R, key: &str, options: &ServeOptions) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+{
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    write!(
+        writer.lock().unwrap(),
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+
+    let (sender, messages) = mpsc::channel();
+    let pongs = Arc::clone(&writer);
+    thread::spawn(move || {
+        let mut reader = reader;
+        let mut frames = Frames::default();
+        loop {
+            match frames.read(&mut reader) {
+                Ok((TEXT, payload)) => {
+                    if sender.send(String::from_utf8_lossy(&payload).into_owned()).is_err() {
+                        return;
+                    }
+                }
+                Ok((PING, payload)) => {
+                    let _ = write_frame(&mut *pongs.lock().unwrap(), PONG, &payload);
+                }
+                Ok((CLOSE, _)) | Err(_) => return,
+                Ok(_) => (),
+            }
+        }
+    });
+
+    let mut live = Live {
+        writer,
+        options: *options,
+        run: None,
+    };
+    let result = live.serve(&messages);
+    let _ = write_frame(&mut *live.writer.lock().unwrap(), CLOSE, &[]);
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+struct Live {
+    writer: Arc<Mutex<TcpStream>>,
+    options: ServeOptions,
+    run: Option<Run>,
+}
+
+// The program the page is running
+struct Run {
+    interpreter: Interpreter,
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+    eof: bool,
+    paused: bool,
+    // Steps across every slice, the step limit is for the lot
+    steps: u64,
+    max_steps: Option<u64>,
+    // Already said so, no need to keep saying it
+    waiting: bool,
+    tape_sent: Instant,
+}
+
+impl Run {
+    fn waiting(&self) -> bool {
+        !self.eof
+            && self.input.borrow().is_empty()
+            && matches!(self.interpreter.tokens().get(self.interpreter.cursor()), Some(Token::Input))
+    }
+
+    fn running(&self) -> bool {
+        !self.paused && !self.interpreter.is_finished() && !self.waiting()
+    }
+}
+
+// `,` reads whatever the page has sent so far, and output waits in a
+// buffer for the end of the slice
+struct Channel {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl IoHandler for Channel {
+    fn read(&mut self) -> Option<u8> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.borrow_mut().push(byte);
+    }
+}
+
+impl Live {
+    fn serve(&mut self, messages: &Receiver<String>) -> io::Result<()> {
+        loop {
+            // Most likely a program that wants input first thing
+            if let Some(ref mut run) = self.run {
+                if !run.paused && !run.waiting && run.waiting() {
+                    run.waiting = true;
+                    self.stopped("waiting", vec![])?;
+                }
+            }
+            let running = self.run.as_ref().is_some_and(Run::running);
+            // Wait for the page when there's nothing to run, but not
+            // longer than the idle limit
+            let message = if running {
+                messages.try_recv().ok()
+            } else {
+                match messages.recv_timeout(self.options.idle.unwrap_or(Duration::MAX)) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.send(event("error", vec![("message", Json::from("Quiet for too long, bye"))]))?;
+                        return Ok(());
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            };
+            if let Some(message) = message {
+                if let Err(e) = self.handle(&message) {
+                    self.send(event("error", vec![("message", Json::from(e))]))?;
+                }
+                continue;
+            }
+            if running {
+                self.slice(SLICE)?;
+            }
+        }
+    }
+
+    fn handle(&mut self, message: &str) -> Result<(), String> {
+        let message = Json::parse(message)?;
+        let kind = message.get("type").and_then(Json::as_str).unwrap_or("");
+        match kind {
+            "run" => return self.start(&message),
+            "input" | "eof" | "pause" | "resume" | "step" => (),
+            _ => return Err(format!("There's no message type '{}'", kind)),
+        }
+        let run = match self.run {
+            Some(ref mut run) => run,
+            None => return Err("Nothing's running, send a \"run\" first".to_string()),
+        };
+        match kind {
+            "input" => {
+                let data = message
+                    .get("data")
+                    .and_then(Json::as_str)
+                    .ok_or_else(|| "\"input\" needs a \"data\" string".to_string())?;
+                // A char per byte, the same as output
+                run.input.borrow_mut().extend(data.chars().map(|c| c as u8));
+                run.waiting = false;
+                Ok(())
+            }
+            "eof" => {
+                run.eof = true;
+                run.waiting = false;
+                Ok(())
+            }
+            "pause" => {
+                run.paused = true;
+                self.stopped("paused", vec![]).map_err(|e| e.to_string())
+            }
+            "resume" => {
+                run.paused = false;
+                Ok(())
+            }
+            "step" => {
+                let count = match message.get("count") {
+                    Some(count) => count
+                        .as_u64()
+                        .ok_or_else(|| "\"count\" has to be a whole number".to_string())?,
+                    None => 1,
+                };
+                run.paused = true;
+                self.slice(count).map_err(|e| e.to_string())?;
+                match self.run {
+                    Some(ref run) if !run.waiting => self.stopped("paused", vec![]).map_err(|e| e.to_string()),
+                    _ => Ok(()),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // A fresh interpreter for the program in message, replacing any other
+    fn start(&mut self, message: &Json) -> Result<(), String> {
+        let program = message
+            .get("program")
+            .and_then(Json::as_str)
+            .ok_or_else(|| "\"run\" needs a \"program\" string".to_string())?;
+        let mut interpreter = Interpreter::new();
+        if let Some(name) = message.get("dialect").and_then(Json::as_str) {
+            check_dialect(name)?;
+            interpreter.set_dialect(Registry::new().get(name));
+        }
+        // Limits in the message can only be lower than the server's, the
+        // same as serve-http
+        let max_steps = lower(limit(message, "max_steps")?, self.options.max_steps);
+        interpreter.set_watchdog(lower(limit(message, "watchdog")?, self.options.watchdog));
+        interpreter.brain.set_capacity_cap(self.options.capacity_cap);
+        self.run = None;
+
+        let errors = interpreter.parse_errors(program);
+        if !errors.is_empty() {
+            let errors = errors.iter().map(|e| e.to_json(program)).collect();
+            return self
+                .send(event("errors", vec![("errors", Json::Array(errors))]))
+                .map_err(|e| e.to_string());
+        }
+        interpreter.load_source(program).map_err(|e| e.to_string())?;
+
+        let input = Rc::new(RefCell::new(VecDeque::new()));
+        if let Some(text) = message.get("input").and_then(Json::as_str) {
+            input.borrow_mut().extend(text.chars().map(|c| c as u8));
+        }
+        let output = Rc::new(RefCell::new(Vec::new()));
+        interpreter.set_io(Box::new(Channel {
+            input: Rc::clone(&input),
+            output: Rc::clone(&output),
+        }));
+        interpreter.begin_run();
+        self.run = Some(Run {
+            interpreter,
+            input,
+            output,
+            eof: false,
+            paused: message.get("paused") == Some(&Json::Bool(true)),
+            steps: 0,
+            max_steps,
+            waiting: false,
+            tape_sent: Instant::now(),
+        });
+        let run = self.run.as_ref().unwrap();
+        if run.paused {
+            self.stopped("paused", vec![]).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    // Run up to n instructions, stopping early for input that isn't
+    // there yet, then tell the page what happened
+    fn slice(&mut self, n: u64) -> io::Result<()> {
+        let run = match self.run {
+            Some(ref mut run) => run,
+            None => return Ok(()),
+        };
+        let max_steps = run.max_steps;
+        let budget = max_steps.map_or(n, |max| n.min(max.saturating_sub(run.steps)));
+        let (input, eof) = (Rc::clone(&run.input), run.eof);
+        let mut left = budget;
+        let result = run.interpreter.run_while(|i| {
+            let starved = !eof
+                && input.borrow().is_empty()
+                && matches!(i.tokens().get(i.cursor()), Some(Token::Input));
+            if left == 0 || starved {
+                return false;
+            }
+            left -= 1;
+            true
+        });
+        run.steps += run.interpreter.steps();
+
+        let output: Vec<u8> = run.output.borrow_mut().drain(..).collect();
+        if !output.is_empty() {
+            let data: String = output.iter().map(|&byte| byte as char).collect();
+            self.send(event("output", vec![("data", Json::from(data))]))?;
+        }
+
+        let run = self.run.as_mut().unwrap();
+        let limit = max_steps.filter(|&max| run.steps >= max && !run.interpreter.is_finished());
+        let stopped = match (result, limit) {
+            (Err(e), _) => Some(e),
+            (Ok(()), Some(max)) => Some(RuntimeError::StepLimit(max)),
+            (Ok(()), None) => None,
+        };
+        if let Some(e) = stopped {
+            self.stopped("stopped", vec![("message", Json::from(e.to_string()))])?;
+            self.run = None;
+        } else if run.interpreter.is_finished() {
+            let steps = run.steps;
+            self.stopped("done", vec![("steps", Json::from(steps))])?;
+            self.run = None;
+        } else if run.waiting() && !run.waiting {
+            run.waiting = true;
+            self.stopped("waiting", vec![])?;
+        } else if run.tape_sent.elapsed() >= TAPE_EVERY {
+            self.send_tape()?;
+        }
+        Ok(())
+    }
+
+    // A tape snapshot then the event, for whenever the program stops
+    fn stopped(&mut self, kind: &str, fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.send_tape()?;
+        self.send(event(kind, fields))
+    }
+
+    fn send_tape(&mut self) -> io::Result<()> {
+        let run = match self.run {
+            Some(ref mut run) => run,
+            None => return Ok(()),
+        };
+        run.tape_sent = Instant::now();
+        let brain = &run.interpreter.brain;
+        let ptr = brain.ptr();
+        let start = ptr.saturating_sub(TAPE_WINDOW);
+        let end = (ptr + TAPE_WINDOW + 1).max(start + 1).min(brain.len().max(ptr + 1));
+        let cells = (start..end).map(|i| Json::from(brain.get(i) as u64)).collect();
+        let tape = event(
+            "tape",
+            vec![
+                ("ptr", Json::from(ptr)),
+                ("start", Json::from(start)),
+                ("cells", Json::Array(cells)),
+                ("cursor", Json::from(run.interpreter.cursor())),
+                ("steps", Json::from(run.steps)),
+            ],
+        );
+        self.send(tape)
+    }
+
+    fn send(&self, message: Json) -> io::Result<()> {
+        write_frame(&mut *self.writer.lock().unwrap(), TEXT, message.to_string().as_bytes())
+    }
+}
+
+fn event(kind: &str, fields: Vec<(&str, Json)>) -> Json {
+    let mut pairs = vec![("type", Json::from(kind))];
+    pairs.extend(fields);
+    Json::object(pairs)
+}
+
+// A message's fragments so far, kept when a control frame turns up
+// between them
+#[derive(Default)]
+struct Frames {
+    opcode: Option<u8>,
+    payload: Vec<u8>,
+}
+
+impl Frames {
+    // One whole message as (opcode, payload), fragments put back together
+    fn read<R: Read>(&mut self, reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+        loop {
+            let mut head = [0; 2];
+            reader.read_exact(&mut head)?;
+            let fin = head[0] & 0x80 != 0;
+            let code = head[0] & 0x0f;
+            let len = match head[1] & 0x7f {
+                126 => {
+                    let mut n = [0; 2];
+                    reader.read_exact(&mut n)?;
+                    u16::from_be_bytes(n) as u64
+                }
+                127 => {
+                    let mut n = [0; 8];
+                    reader.read_exact(&mut n)?;
+                    u64::from_be_bytes(n)
+                }
+                n => n as u64,
+            };
+            if self.payload.len() as u64 + len > MAX_BODY as u64 {
+                return Err(io::Error::new(ErrorKind::InvalidData, "message too big"));
+            }
+            let mut mask = [0; 4];
+            if head[1] & 0x80 != 0 {
+                reader.read_exact(&mut mask)?;
+            }
+            let mut data = vec![0; len as usize];
+            reader.read_exact(&mut data)?;
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+            // Control frames can turn up between the fragments of a message
+            if code >= CLOSE {
+                return Ok((code, data));
+            }
+            if code != 0 {
+                self.opcode = Some(code);
+            }
+            self.payload.extend(data);
+            if fin {
+                let opcode = self.opcode.take().unwrap_or(TEXT);
+                return Ok((opcode, mem::take(&mut self.payload)));
+            }
+        }
+    }
+}
+
+// Servers don't mask
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+// Only the handshake needs these, so they're the plain textbook versions
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_key, base64, sha1, write_frame, Frames, PING, TEXT};
+
+    // A client frame, masked the way browsers always do
+    fn masked(first: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+        let mut frame = vec![first, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, &b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    // The example from RFC 6455 section 1.3
+    #[test]
+    fn accept_key_from_the_rfc() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn sha1_and_base64_vectors() {
+        let hex = |digest: [u8; 20]| digest.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Two blocks once the padding goes on
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(hex(sha1(long)), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(base64(b"brainf"), "YnJhaW5m");
+        assert_eq!(base64(b"brain"), "YnJhaW4=");
+        assert_eq!(base64(b"brai"), "YnJhaQ==");
+    }
+
+    #[test]
+    fn masked_frame() {
+        let frame = masked(0x80 | TEXT, b"{\"type\": \"eof\"}", [0x37, 0xfa, 0x21, 0x3d]);
+        assert_eq!(Frames::default().read(&mut &frame[..]).unwrap(), (TEXT, b"{\"type\": \"eof\"}".to_vec()));
+    }
+
+    // A ping between the fragments comes back on its own, then the whole
+    // message, the fragment before the ping included
+    #[test]
+    fn fragmented_frame_around_a_ping() {
+        let mut data = masked(TEXT, b"+[", [1, 2, 3, 4]);
+        data.extend(masked(0x80 | PING, b"hi", [5, 6, 7, 8]));
+        data.extend(masked(0, b">+", [9, 10, 11, 12]));
+        data.extend(masked(0x80, b"]", [13, 14, 15, 16]));
+        let (mut reader, mut frames) = (&data[..], Frames::default());
+        assert_eq!(frames.read(&mut reader).unwrap(), (PING, b"hi".to_vec()));
+        assert_eq!(frames.read(&mut reader).unwrap(), (TEXT, b"+[>+]".to_vec()));
+    }
+
+    #[test]
+    fn cut_short_frame_fails() {
+        let frame = masked(0x80 | TEXT, b"+[>+]", [1, 2, 3, 4]);
+        for end in 0..frame.len() {
+            assert!(Frames::default().read(&mut &frame[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn frame_lengths_round_trip() {
+        for len in [0, 125, 126, 65535, 65536] {
+            let payload = vec![b'+'; len];
+            let mut frame = Vec::new();
+            write_frame(&mut frame, TEXT, &payload).unwrap();
+            assert_eq!(Frames::default().read(&mut &frame[..]).unwrap(), (TEXT, payload));
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// Run another interpreter on the same program and input
+// %f in the command becomes the program path, or the path is tacked on the end
+pub fn run_reference(command: &str, path: &Path, input: &[u8]) -> io::Result<Vec<u8>> {
+    let quoted = shell_quote(&path.to_string_lossy());
+    let command = if command.contains("%f") {
+        command.replace("%f", &quoted)
+    } else {
+        format!("{} {}", command, quoted)
+    };
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    // Feed stdin from its own thread so a chatty child can't deadlock us
+    let mut stdin = child.stdin.take().expect("stdin was piped");
+    let input = input.to_vec();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!("`{}` exited with {}", command, output.status)));
+    }
+    Ok(output.stdout)
+}
+
+// Index of the first byte where the outputs disagree
+pub fn first_difference(ours: &[u8], theirs: &[u8]) -> Option<usize> {
+    match ours.iter().zip(theirs).position(|(a, b)| a != b) {
+        Some(i) => Some(i),
+        None if ours.len() != theirs.len() => Some(ours.len().min(theirs.len())),
+        None => None,
+    }
+}
+
+// Bytes as something that fits on one terminal line
+pub fn escape(bytes: &[u8]) -> String {
+    bytes.iter()
+        .flat_map(|&b| ::std::ascii::escape_default(b))
+        .map(|b| b as char)
+        .collect()
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}